@@ -0,0 +1,46 @@
+#[macro_use]
+extern crate criterion;
+extern crate gb_core;
+
+use criterion::{Criterion, Throughput};
+use gb_core::cartridge::Cartridge;
+use gb_core::gameboy::GameBoy;
+
+/**
+ * Endless loop of ALU, call and jump instructions at the cartridge entry point.
+ */
+fn rom() -> Vec<u8> {
+    let mut rom = vec![0; 0x8000];
+    let program = [
+        0x31, 0xFE, 0xFF, // 0x100 LD SP,0xFFFE
+        0x3C,             // 0x103 INC A
+        0xCD, 0x10, 0x01, // 0x104 CALL 0x0110
+        0x20, 0xFA,       // 0x107 JR NZ,0x0103
+        0x28, 0xF8        // 0x109 JR Z,0x0103
+    ];
+    rom[0x100..0x100 + program.len()].copy_from_slice(&program);
+    rom[0x110] = 0x05; // DEC B
+    rom[0x111] = 0xC9; // RET
+    rom
+}
+
+fn run_frame(gb: &mut GameBoy) {
+    let frame = gb.frame();
+    while gb.frame() == frame {
+        gb.step().expect("benchmark rom stopped");
+    }
+}
+
+fn frames(c: &mut Criterion) {
+    let cartridge = Cartridge::new(rom()).unwrap();
+    let mut gb = GameBoy::for_cartridge(cartridge);
+    gb.skip_boot();
+
+    let mut group = c.benchmark_group("frames");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("headless", |b| b.iter(|| run_frame(&mut gb)));
+    group.finish();
+}
+
+criterion_group!(benches, frames);
+criterion_main!(benches);
@@ -0,0 +1,66 @@
+#[macro_use]
+extern crate criterion;
+extern crate gb_core;
+
+use criterion::{black_box, Criterion, Throughput};
+use gb_core::cpu::Instruction;
+use gb_core::cpu::instructions::parse_command;
+use gb_core::gameboy::GameBoy;
+
+const BATCH: usize = 1000;
+
+/**
+ * Opcodes with their operands, repeated until the batch is full.
+ */
+fn program(mix: &[[u8; 3]]) -> Vec<[u8; 3]> {
+    mix.iter().cycle().take(BATCH).cloned().collect()
+}
+
+fn run(gb: &mut GameBoy, program: &[[u8; 3]]) {
+    gb.register.pc = 0xC000;
+    gb.register.sp = 0xDFFE;
+    for bytes in program {
+        let instruction = parse_command(bytes[0], &bytes[1..]).unwrap();
+        instruction.exec(gb);
+    }
+}
+
+fn instructions(c: &mut Criterion) {
+    let mixes = [
+        ("loads", program(&[
+            [0x3E, 0x42, 0x00], // LD A,n
+            [0x47, 0x00, 0x00], // LD B,A
+            [0x21, 0x00, 0xC0], // LD HL,nn
+            [0x77, 0x00, 0x00], // LD (HL),A
+            [0x7E, 0x00, 0x00], // LD A,(HL)
+            [0xE0, 0x80, 0x00], // LDH (n),A
+        ])),
+        ("alu", program(&[
+            [0x3C, 0x00, 0x00], // INC A
+            [0x05, 0x00, 0x00], // DEC B
+            [0xAF, 0x00, 0x00], // XOR A
+            [0xFE, 0x10, 0x00], // CP n
+            [0x17, 0x00, 0x00], // RLA
+            [0xCB, 0x7C, 0x00], // BIT 7,H
+        ])),
+        ("control", program(&[
+            [0x20, 0x02, 0x00], // JR NZ,n
+            [0xCD, 0x00, 0xC0], // CALL nn
+            [0xC9, 0x00, 0x00], // RET
+            [0xC5, 0x00, 0x00], // PUSH BC
+            [0xC1, 0x00, 0x00], // POP BC
+            [0x00, 0x00, 0x00], // NOP
+        ]))
+    ];
+
+    let mut group = c.benchmark_group("instructions");
+    group.throughput(Throughput::Elements(BATCH as u64));
+    for (name, program) in mixes.iter() {
+        let mut gb = GameBoy::new();
+        group.bench_function(*name, |b| b.iter(|| run(&mut gb, black_box(program))));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, instructions);
+criterion_main!(benches);
@@ -0,0 +1,288 @@
+use std::error::Error;
+use std::fmt;
+
+const TITLE: usize = 0x134;
+const CGB_FLAG: usize = 0x143;
+const SGB_FLAG: usize = 0x146;
+const CARTRIDGE_TYPE: usize = 0x147;
+const RAM_SIZE: usize = 0x149;
+const OLD_LICENSEE: usize = 0x14B;
+const HEADER_END: usize = 0x150;
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_BANK_SIZE: usize = 0x2000;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mbc {
+    None,
+    Mbc1,
+    Mbc3,
+    Mbc5
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CgbSupport {
+    None,
+    Compatible,
+    Only
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CartridgeError {
+    TooSmall(usize),
+    UnsupportedType(u8)
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::TooSmall(size) => write!(f, "ROM is too small ({} bytes)", size),
+            CartridgeError::UnsupportedType(kind) => write!(f, "Unsupported cartridge type {:#04X}", kind)
+        }
+    }
+}
+
+impl Error for CartridgeError {}
+
+/**
+ * Cartridge ROM with its memory bank controller and external RAM.
+ */
+pub struct Cartridge {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    mbc: Mbc,
+    ram_enabled: bool,
+    rom_bank: usize,
+    ram_bank: usize,
+    /// MBC1 banking mode, selects whether the upper bits apply to RAM or ROM
+    advanced_banking: bool
+}
+
+impl Cartridge {
+    pub fn new(rom: Vec<u8>) -> Result<Cartridge, CartridgeError> {
+        if rom.len() < HEADER_END {
+            return Err(CartridgeError::TooSmall(rom.len()));
+        }
+        let mbc = match rom[CARTRIDGE_TYPE] {
+            0x00 | 0x08 | 0x09 => Mbc::None,
+            0x01..=0x03 => Mbc::Mbc1,
+            0x0F..=0x13 => Mbc::Mbc3,
+            0x19..=0x1E => Mbc::Mbc5,
+            kind => return Err(CartridgeError::UnsupportedType(kind))
+        };
+        let ram_size = match rom[RAM_SIZE] {
+            0x02 => RAM_BANK_SIZE,
+            0x03 => RAM_BANK_SIZE * 4,
+            0x04 => RAM_BANK_SIZE * 16,
+            0x05 => RAM_BANK_SIZE * 8,
+            _ => 0
+        };
+        Ok(Cartridge {
+            rom,
+            ram: vec![0; ram_size],
+            mbc,
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            advanced_banking: false
+        })
+    }
+
+    /**
+     * The title is 16 characters long on old cartridges, newer ones use the last bytes for flags.
+     */
+    pub fn title(&self) -> String {
+        let len = if self.cgb_support() == CgbSupport::None { 16 } else { 15 };
+        self.rom[TITLE..TITLE + len]
+            .iter()
+            .take_while(|c| **c != 0)
+            .map(|c| *c as char)
+            .collect()
+    }
+
+    pub fn cgb_support(&self) -> CgbSupport {
+        match self.rom[CGB_FLAG] {
+            0xC0 => CgbSupport::Only,
+            0x80 => CgbSupport::Compatible,
+            _ => CgbSupport::None
+        }
+    }
+
+    /**
+     * SGB functions are only enabled when the old licensee code is 0x33.
+     */
+    pub fn sgb_support(&self) -> bool {
+        self.rom[SGB_FLAG] == 0x03 && self.rom[OLD_LICENSEE] == 0x33
+    }
+
+    pub fn mbc(&self) -> Mbc {
+        self.mbc
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    /**
+     * External (usually battery backed) RAM.
+     */
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn load_ram(&mut self, ram: &[u8]) {
+        let len = ram.len().min(self.ram.len());
+        self.ram[..len].copy_from_slice(&ram[..len]);
+    }
+
+    /**
+     * Bank currently mapped into 0x4000-0x7FFF.
+     */
+    pub fn rom_bank(&self) -> usize {
+        let banks = (self.rom.len() / ROM_BANK_SIZE).max(1);
+        self.rom_bank % banks
+    }
+
+    pub fn read_rom(&self, addr: u16) -> u8 {
+        let addr = addr as usize;
+        let offset = if addr < ROM_BANK_SIZE {
+            match self.mbc {
+                Mbc::Mbc1 if self.advanced_banking => (self.rom_bank & 0x60) * ROM_BANK_SIZE + addr,
+                _ => addr
+            }
+        }else {
+            self.rom_bank() * ROM_BANK_SIZE + addr - ROM_BANK_SIZE
+        };
+        self.rom.get(offset % self.rom.len()).cloned().unwrap_or(0xFF)
+    }
+
+    pub fn write_rom(&mut self, addr: u16, value: u8) {
+        match (self.mbc, addr) {
+            (Mbc::None, _) => {},
+            (_, 0x0000..=0x1FFF) => self.ram_enabled = value & 0x0F == 0x0A,
+            (Mbc::Mbc1, 0x2000..=0x3FFF) => {
+                let bank = (value & 0x1F).max(1) as usize;
+                self.rom_bank = (self.rom_bank & 0x60) | bank;
+            },
+            (Mbc::Mbc1, 0x4000..=0x5FFF) => {
+                let bits = (value & 0x03) as usize;
+                self.rom_bank = (self.rom_bank & 0x1F) | (bits << 5);
+                self.ram_bank = bits;
+            },
+            (Mbc::Mbc1, 0x6000..=0x7FFF) => self.advanced_banking = value & 0x01 != 0,
+            (Mbc::Mbc3, 0x2000..=0x3FFF) => self.rom_bank = (value & 0x7F).max(1) as usize,
+            (Mbc::Mbc3, 0x4000..=0x5FFF) => self.ram_bank = (value & 0x03) as usize,
+            (Mbc::Mbc5, 0x2000..=0x2FFF) => self.rom_bank = (self.rom_bank & 0x100) | value as usize,
+            (Mbc::Mbc5, 0x3000..=0x3FFF) => self.rom_bank = (self.rom_bank & 0xFF) | ((value as usize & 0x01) << 8),
+            (Mbc::Mbc5, 0x4000..=0x5FFF) => self.ram_bank = (value & 0x0F) as usize,
+            _ => {}
+        }
+    }
+
+    pub fn read_ram(&self, addr: u16) -> u8 {
+        match self.ram_offset(addr) {
+            Some(offset) => self.ram[offset],
+            None => 0xFF
+        }
+    }
+
+    pub fn write_ram(&mut self, addr: u16, value: u8) {
+        if let Some(offset) = self.ram_offset(addr) {
+            self.ram[offset] = value;
+        }
+    }
+
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        if self.ram.is_empty() || (self.mbc != Mbc::None && !self.ram_enabled) {
+            return None;
+        }
+        let bank = match self.mbc {
+            Mbc::Mbc1 if !self.advanced_banking => 0,
+            _ => self.ram_bank
+        };
+        let offset = bank * RAM_BANK_SIZE + (addr as usize - 0xA000);
+        Some(offset % self.ram.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(kind: u8, banks: usize) -> Vec<u8> {
+        let mut rom = vec![0; banks * ROM_BANK_SIZE];
+        rom[CARTRIDGE_TYPE] = kind;
+        rom[RAM_SIZE] = 0x03;
+        for bank in 0..banks {
+            rom[bank * ROM_BANK_SIZE + 0x100] = bank as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn it_should_reject_unknown_cartridge_types() {
+        assert!(Cartridge::new(rom(0xFD, 2)).is_err());
+    }
+
+    #[test]
+    fn it_should_read_the_title() {
+        let mut data = rom(0x00, 2);
+        data[TITLE..TITLE + 4].copy_from_slice(b"TEST");
+        let cartridge = Cartridge::new(data).unwrap();
+        assert_eq!(cartridge.title(), "TEST");
+    }
+
+    #[test]
+    fn it_should_detect_cgb_support() {
+        let mut data = rom(0x00, 2);
+        assert_eq!(Cartridge::new(data.clone()).unwrap().cgb_support(), CgbSupport::None);
+        data[CGB_FLAG] = 0x80;
+        assert_eq!(Cartridge::new(data.clone()).unwrap().cgb_support(), CgbSupport::Compatible);
+        data[CGB_FLAG] = 0xC0;
+        assert_eq!(Cartridge::new(data).unwrap().cgb_support(), CgbSupport::Only);
+    }
+
+    #[test]
+    fn it_should_detect_sgb_support() {
+        let mut data = rom(0x00, 2);
+        data[SGB_FLAG] = 0x03;
+        assert!(!Cartridge::new(data.clone()).unwrap().sgb_support());
+        data[OLD_LICENSEE] = 0x33;
+        assert!(Cartridge::new(data).unwrap().sgb_support());
+    }
+
+    #[test]
+    fn it_should_switch_rom_banks() {
+        let mut cartridge = Cartridge::new(rom(0x01, 8)).unwrap();
+        assert_eq!(cartridge.read_rom(0x4100), 1);
+        cartridge.write_rom(0x2000, 5);
+        assert_eq!(cartridge.read_rom(0x4100), 5);
+        assert_eq!(cartridge.read_rom(0x0100), 0);
+    }
+
+    #[test]
+    fn it_should_map_bank_0_to_bank_1() {
+        let mut cartridge = Cartridge::new(rom(0x01, 4)).unwrap();
+        cartridge.write_rom(0x2000, 0);
+        assert_eq!(cartridge.read_rom(0x4100), 1);
+    }
+
+    #[test]
+    fn it_should_only_access_ram_when_enabled() {
+        let mut cartridge = Cartridge::new(rom(0x03, 2)).unwrap();
+        cartridge.write_ram(0xA000, 0x42);
+        assert_eq!(cartridge.read_ram(0xA000), 0xFF);
+        cartridge.write_rom(0x0000, 0x0A);
+        cartridge.write_ram(0xA000, 0x42);
+        assert_eq!(cartridge.read_ram(0xA000), 0x42);
+    }
+
+    #[test]
+    fn it_should_switch_mbc5_rom_banks() {
+        let mut cartridge = Cartridge::new(rom(0x19, 8)).unwrap();
+        cartridge.write_rom(0x2000, 0);
+        assert_eq!(cartridge.read_rom(0x4100), 0);
+        cartridge.write_rom(0x2000, 7);
+        assert_eq!(cartridge.read_rom(0x4100), 7);
+    }
+}
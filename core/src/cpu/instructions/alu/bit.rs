@@ -3,6 +3,7 @@ use cpu::Instruction;
 use std::fmt;
 use cpu::register::{Register8, Flags};
 
+#[derive(Copy, Clone)]
 pub struct Bit {
     pub bit: u8,
     pub register: Register8
@@ -22,5 +23,6 @@ impl Instruction for Bit {
         gb.register.f.remove(Flags::N);
         gb.register.f.set(Flags::H, true);
         pc!(gb, 2);
+        cycles!(gb, 8);
     }
 }
\ No newline at end of file
@@ -3,6 +3,7 @@ use cpu::Instruction;
 use std::fmt;
 use cpu::register::{Register8, Flags};
 
+#[derive(Copy, Clone)]
 pub struct RotateRegisterLeft(pub Register8);
 
 impl fmt::Debug for RotateRegisterLeft {
@@ -15,8 +16,10 @@ impl Instruction for RotateRegisterLeft {
     fn exec(&self, gb: &mut GameBoy) {
         rotate_left(gb, &self.0);
         pc!(gb, 2);
+        cycles!(gb, 8);
     }
 }
+#[derive(Copy, Clone)]
 pub struct RotateRegisterALeft;
 
 impl fmt::Debug for RotateRegisterALeft {
@@ -29,6 +32,7 @@ impl Instruction for RotateRegisterALeft {
     fn exec(&self, gb: &mut GameBoy) {
         rotate_left(gb, &Register8::A);
         pc!(gb);
+        cycles!(gb, 4);
     }
 }
 
@@ -2,6 +2,7 @@ use gameboy::GameBoy;
 use cpu::Instruction;
 use std::fmt;
 
+#[derive(Copy, Clone)]
 pub struct Call(pub u16);
 
 impl fmt::Debug for Call {
@@ -15,6 +16,7 @@ impl Instruction for Call {
         let next_instruction = gb.register.pc + 3;
         gb.push_to_stack(next_instruction);
         gb.register.pc = self.0;
+        cycles!(gb, 24);
     }
 }
 
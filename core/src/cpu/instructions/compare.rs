@@ -3,6 +3,7 @@ use cpu::Instruction;
 use cpu::register::Flags;
 use std::fmt;
 
+#[derive(Copy, Clone)]
 pub struct CompareImmediate(pub u8);
 
 impl fmt::Debug for CompareImmediate {
@@ -25,5 +26,6 @@ impl Instruction for CompareImmediate {
 
         // Increment Program Counter
         pc!(gb, 2);
+        cycles!(gb, 8);
     }
 }
\ No newline at end of file
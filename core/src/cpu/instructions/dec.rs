@@ -3,6 +3,7 @@ use cpu::Instruction;
 use cpu::register::{Flags, Register8};
 use std::fmt;
 
+#[derive(Copy, Clone)]
 pub struct DecrementRegister(pub Register8);
 
 impl fmt::Debug for DecrementRegister {
@@ -27,6 +28,7 @@ impl Instruction for DecrementRegister {
 
         // Increment Program Counter
         pc!(gb);
+        cycles!(gb, 4);
     }
 }
 
@@ -0,0 +1,47 @@
+use gameboy::GameBoy;
+use cpu::Instruction;
+use std::fmt;
+
+/**
+ * Illegal opcodes (0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD)
+ *
+ * Hang the CPU, the program counter stays on the opcode while timers and PPU keep running.
+ */
+#[derive(Copy, Clone)]
+pub struct IllegalOpcode(pub u8);
+
+impl fmt::Debug for IllegalOpcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ILLEGAL {:#04X}", self.0)
+    }
+}
+
+impl Instruction for IllegalOpcode {
+    fn exec(&self, gb: &mut GameBoy) {
+        gb.lock_up(self.0);
+        cycles!(gb, 4);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gameboy::{Event, GameBoy};
+
+    #[test]
+    fn it_should_not_increase_the_pc() {
+        let mut gb = GameBoy::new();
+        IllegalOpcode(0xD3).exec(&mut gb);
+        assert_eq!(gb.register.pc, 0);
+        assert_eq!(gb.cycles, 4);
+    }
+
+    #[test]
+    fn it_should_raise_an_event_once() {
+        let mut gb = GameBoy::new();
+        IllegalOpcode(0xD3).exec(&mut gb);
+        IllegalOpcode(0xD3).exec(&mut gb);
+        assert_eq!(gb.poll_event(), Some(Event::LockedUp { opcode: 0xD3, address: 0 }));
+        assert_eq!(gb.poll_event(), None);
+    }
+}
@@ -3,6 +3,7 @@ use cpu::Instruction;
 use std::fmt;
 use cpu::register::{Flags, Register8, Register16};
 
+#[derive(Copy, Clone)]
 pub struct IncrementRegister(Register8);
 
 impl IncrementRegister {
@@ -41,9 +42,11 @@ impl Instruction for IncrementRegister {
             gb.register.f.set(Flags::H, half_carry);
         }
         pc!(gb);
+        cycles!(gb, 4);
     }
 }
 
+#[derive(Copy, Clone)]
 pub struct Increment16BitRegister(pub Register16);
 
 impl fmt::Debug for Increment16BitRegister {
@@ -58,6 +61,7 @@ impl Instruction for Increment16BitRegister {
         value = value.wrapping_add(1);
         gb.register.write_16bit_register(&self.0, value);
         pc!(gb);
+        cycles!(gb, 8);
     }
 }
 
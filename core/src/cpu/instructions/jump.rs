@@ -3,7 +3,7 @@ use cpu::Instruction;
 use std::fmt;
 use cpu::register::Flags;
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 enum JumpMode {
     NZ,
     Z,
@@ -21,6 +21,7 @@ enum JumpMode {
  * cc = NC, Jump if C flag is reset.
  * cc = C, Jump if C flag is set.
  */
+#[derive(Copy, Clone)]
 pub struct JumpRelative {
     pub target: i8,
     mode: JumpMode
@@ -73,6 +74,9 @@ impl Instruction for JumpRelative {
         pc!(gb, 2);
         if result {
             gb.register.pc = (gb.register.pc as i16 + self.target as i16) as u16;
+            cycles!(gb, 12);
+        }else {
+            cycles!(gb, 8);
         }
     }
 }
@@ -2,6 +2,7 @@ use cpu::Instruction;
 use gameboy::GameBoy;
 use std::fmt;
 
+#[derive(Copy, Clone)]
 pub enum Load16Bit {
     BC(u16),
     DE(u16),
@@ -27,6 +28,7 @@ impl Instruction for Load16Bit {
             Load16Bit::HL(bytes) => gb.register.write_hl(*bytes),
             Load16Bit::SP(bytes) => gb.register.sp = *bytes
         }
-        pc!(gb, 3)
+        pc!(gb, 3);
+        cycles!(gb, 12);
     }
 }
@@ -3,6 +3,7 @@ use gameboy::GameBoy;
 use std::fmt;
 use cpu::register::Register8;
 
+#[derive(Copy, Clone)]
 pub struct Load8Bit {
     register: Register8,
     data: u8
@@ -30,7 +31,8 @@ impl Load8Bit {
 impl Instruction for Load8Bit {
     fn exec(&self, gb: &mut GameBoy) {
         gb.register.write_8bit_register(&self.register, self.data);
-        pc!(gb, 2)
+        pc!(gb, 2);
+        cycles!(gb, 8);
     }
 }
 
@@ -6,6 +6,7 @@ use cpu::register::{Register8, RegisterPair};
 /**
  * LD A,n
  */
+#[derive(Copy, Clone)]
 pub struct LoadRegisterIntoRegisterA(pub Register8);
 
 impl fmt::Debug for LoadRegisterIntoRegisterA {
@@ -19,12 +20,14 @@ impl Instruction for LoadRegisterIntoRegisterA {
         let value = gb.register.get(&self.0);
         gb.register.a = value;
         pc!(gb);
+        cycles!(gb, 4);
     }
 }
 
 /**
  * LD A,n
  */
+#[derive(Copy, Clone)]
 pub struct LoadRegisterRamIntoRegisterA(pub RegisterPair);
 
 impl fmt::Debug for LoadRegisterRamIntoRegisterA {
@@ -35,9 +38,10 @@ impl fmt::Debug for LoadRegisterRamIntoRegisterA {
 
 impl Instruction for LoadRegisterRamIntoRegisterA {
     fn exec(&self, gb: &mut GameBoy) {
-        let location = gb.register.pair(&self.0) as usize;
-        gb.register.a = gb.ram[location];
+        let location = gb.register.pair(&self.0);
+        gb.register.a = gb.read(location);
         pc!(gb);
+        cycles!(gb, 8);
     }
 }
 
@@ -46,6 +50,7 @@ impl Instruction for LoadRegisterRamIntoRegisterA {
  *
  * nn = two byte immediate value
  */
+#[derive(Copy, Clone)]
 pub struct LoadImmediateRamIntoRegisterA(pub u16);
 
 impl fmt::Debug for LoadImmediateRamIntoRegisterA {
@@ -56,8 +61,8 @@ impl fmt::Debug for LoadImmediateRamIntoRegisterA {
 
 impl Instruction for LoadImmediateRamIntoRegisterA {
     fn exec(&self, gb: &mut GameBoy) {
-        let location = self.0 as usize;
-        gb.register.a = gb.ram[location];
+        gb.register.a = gb.read(self.0);
         pc!(gb, 2);
+        cycles!(gb, 16);
     }
 }
\ No newline at end of file
@@ -2,6 +2,7 @@ use gameboy::GameBoy;
 use cpu::Instruction;
 use std::fmt;
 
+#[derive(Copy, Clone)]
 pub struct LoadRamFromRegisterA;
 
 impl fmt::Debug for LoadRamFromRegisterA {
@@ -14,8 +15,8 @@ impl Instruction for LoadRamFromRegisterA {
     fn exec(&self, gb: &mut GameBoy) {
         let c = gb.register.c as u16;
         let a = gb.register.a;
-        let index = (0xff00 + c) as usize;
-        gb.ram[index] = a;
+        gb.write(0xff00 + c, a);
         pc!(gb);
+        cycles!(gb, 8);
     }
 }
\ No newline at end of file
@@ -2,6 +2,7 @@ use gameboy::GameBoy;
 use cpu::Instruction;
 use std::fmt;
 
+#[derive(Copy, Clone)]
 pub struct LoadRegisterAIntoZeroPageRam(pub u8);
 
 impl fmt::Debug for LoadRegisterAIntoZeroPageRam {
@@ -14,8 +15,8 @@ impl Instruction for LoadRegisterAIntoZeroPageRam {
     fn exec(&self, gb: &mut GameBoy) {
         let offset = self.0 as u16;
         let a = gb.register.a;
-        let index = (0xff00 + offset) as usize;
-        gb.ram[index] = a;
+        gb.write(0xff00 + offset, a);
         pc!(gb, 2);
+        cycles!(gb, 12);
     }
 }
\ No newline at end of file
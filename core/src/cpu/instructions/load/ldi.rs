@@ -2,6 +2,7 @@ use gameboy::GameBoy;
 use cpu::Instruction;
 use std::fmt;
 
+#[derive(Copy, Clone)]
 pub struct LoadIncrementHLA;
 
 impl fmt::Debug for LoadIncrementHLA {
@@ -14,8 +15,9 @@ impl Instruction for LoadIncrementHLA {
     fn exec(&self, gb: &mut GameBoy) {
         let hl = gb.register.read_hl();
         let a = gb.register.a;
-        gb.ram[hl as usize] = a;
+        gb.write(hl, a);
         gb.register.write_hl(hl + 1);
         pc!(gb);
+        cycles!(gb, 8);
     }
 }
\ No newline at end of file
@@ -3,6 +3,7 @@ use cpu::Instruction;
 use std::fmt;
 use cpu::register::{Register8, Register16};
 
+#[derive(Copy, Clone)]
 pub struct LoadIntoRegisterFromRegisterA(pub Register8);
 
 impl fmt::Debug for LoadIntoRegisterFromRegisterA {
@@ -19,9 +20,11 @@ impl Instruction for LoadIntoRegisterFromRegisterA {
             *register = a;
         }
         pc!(gb);
+        cycles!(gb, 4);
     }
 }
 
+#[derive(Copy, Clone)]
 pub struct LoadIntoRegisterRamFromRegisterA(Register16);
 
 impl LoadIntoRegisterRamFromRegisterA {
@@ -46,13 +49,14 @@ impl Instruction for LoadIntoRegisterRamFromRegisterA {
     fn exec(&self, gb: &mut GameBoy) {
         let a = gb.register.a;
         {
-            let location = gb.register.read_16bit_register(&self.0) as usize;
-            let ram = gb.ram_mut();
-            ram[location] = a;
+            let location = gb.register.read_16bit_register(&self.0);
+            gb.write(location, a);
         }
         pc!(gb);
+        cycles!(gb, 8);
     }
 }
+#[derive(Copy, Clone)]
 pub struct LoadIntoImmediateRamFromRegisterA(pub u16);
 
 impl fmt::Debug for LoadIntoImmediateRamFromRegisterA {
@@ -65,10 +69,9 @@ impl Instruction for LoadIntoImmediateRamFromRegisterA {
     fn exec(&self, gb: &mut GameBoy) {
         let a = gb.register.a;
         {
-            let location = self.0 as usize;
-            let ram = gb.ram_mut();
-            ram[location] = a;
+            gb.write(self.0, a);
         }
         pc!(gb, 2);
+        cycles!(gb, 16);
     }
 }
\ No newline at end of file
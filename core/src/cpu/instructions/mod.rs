@@ -1,11 +1,14 @@
 use byteorder::{LittleEndian, ByteOrder};
 use cpu::register::{Register8, Register16, RegisterPair};
 use cpu::Instruction;
+use gameboy::GameBoy;
+use std::fmt;
 
 mod alu;
 mod call;
 mod compare;
 mod dec;
+mod illegal;
 mod inc;
 mod jump;
 mod noop;
@@ -14,12 +17,90 @@ mod xor;
 mod push;
 mod ret;
 mod pop;
+mod stop;
 
-pub fn parse_command(opcode: u8, rom: &[u8]) -> Option<Box<dyn Instruction>> {
+macro_rules! commands {
+    ($($variant:ident($instruction:ty)),*) => {
+        /**
+         * Any decoded instruction.
+         *
+         * Dispatches statically to the instruction, decoding doesn't allocate.
+         */
+        #[derive(Copy, Clone)]
+        pub enum Command {
+            $($variant($instruction)),*
+        }
+
+        $(
+            impl From<$instruction> for Command {
+                fn from(instruction: $instruction) -> Command {
+                    Command::$variant(instruction)
+                }
+            }
+        )*
+
+        impl fmt::Debug for Command {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                match self {
+                    $(Command::$variant(instruction) => fmt::Debug::fmt(instruction, f)),*
+                }
+            }
+        }
+
+        impl Instruction for Command {
+            fn exec(&self, gb: &mut GameBoy) {
+                match self {
+                    $(Command::$variant(instruction) => instruction.exec(gb)),*
+                }
+            }
+        }
+    };
+}
+
+commands! {
+    NoOp(noop::NoOp),
+    Stop(stop::Stop),
+    Load16Bit(load::Load16Bit),
+    Load8Bit(load::Load8Bit),
+    LoadIntoRegisterRamFromRegisterA(load::LoadIntoRegisterRamFromRegisterA),
+    LoadRegisterIntoRegisterA(load::LoadRegisterIntoRegisterA),
+    LoadRegisterRamIntoRegisterA(load::LoadRegisterRamIntoRegisterA),
+    LoadIncrementHLA(load::LoadIncrementHLA),
+    LoadDecrementHLA(load::LoadDecrementHLA),
+    LoadIntoRegisterFromRegisterA(load::LoadIntoRegisterFromRegisterA),
+    LoadRegisterAIntoZeroPageRam(load::LoadRegisterAIntoZeroPageRam),
+    LoadRamFromRegisterA(load::LoadRamFromRegisterA),
+    LoadIntoImmediateRamFromRegisterA(load::LoadIntoImmediateRamFromRegisterA),
+    LoadImmediateRamIntoRegisterA(load::LoadImmediateRamIntoRegisterA),
+    RotateRegisterALeft(alu::RotateRegisterALeft),
+    RotateRegisterLeft(alu::RotateRegisterLeft),
+    Bit(alu::Bit),
+    Increment16BitRegister(inc::Increment16BitRegister),
+    IncrementRegister(inc::IncrementRegister),
+    DecrementRegister(dec::DecrementRegister),
+    JumpRelative(jump::JumpRelative),
+    Xor(xor::Xor),
+    Call(call::Call),
+    Return(ret::Return),
+    Push(push::Push),
+    Pop(pop::Pop),
+    CompareImmediate(compare::CompareImmediate),
+    IllegalOpcode(illegal::IllegalOpcode)
+}
+
+/**
+ * Decodes the opcode with the following bytes in `rom`.
+ *
+ * Returns None for opcodes which aren't emulated.
+ */
+pub fn parse_command(opcode: u8, rom: &[u8]) -> Option<Command> {
     match opcode {
         /* NOP */
         0x00 =>
             cmd!(noop::NoOp),
+        /* STOP */
+        0x10 =>
+            cmd!(stop::Stop),
         /* LD BC,nn */
         0x01 =>
             cmd!(load::Load16Bit::BC(u16!(rom))),
@@ -179,20 +260,35 @@ pub fn parse_command(opcode: u8, rom: &[u8]) -> Option<Box<dyn Instruction>> {
         /* CP # */
         0xFE =>
             cmd!(compare::CompareImmediate(u8!(rom))),
-        _ => {
-            println!("Unknown OpCode {:#X?}", opcode);
-            None
-        }
+        /* Illegal */
+        0xD3 | 0xDB | 0xDD | 0xE3 | 0xE4 | 0xEB | 0xEC | 0xED | 0xF4 | 0xFC | 0xFD =>
+            cmd!(illegal::IllegalOpcode(opcode)),
+        _ => None
     }
 }
 
-fn parse_prefix_command(rom: &[u8]) -> Option<Box<dyn Instruction>> {
+/**
+ * Size of the instruction in bytes including the opcode, known for every opcode whether it's emulated or not.
+ */
+pub fn length(opcode: u8) -> u16 {
+    match opcode {
+        /* LD rr,nn | LD (nn),SP | JP | CALL | LD (nn),A | LD A,(nn) */
+        0x01 | 0x11 | 0x21 | 0x31 | 0x08 |
+        0xC2 | 0xC3 | 0xCA | 0xD2 | 0xDA |
+        0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC |
+        0xEA | 0xFA => 3,
+        /* LD r,n | STOP | JR | ALU n | LDH | ADD SP,e | LD HL,SP+e | prefix */
+        0x06 | 0x0E | 0x16 | 0x1E | 0x26 | 0x2E | 0x36 | 0x3E |
+        0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 |
+        0xC6 | 0xCE | 0xD6 | 0xDE | 0xE6 | 0xEE | 0xF6 | 0xFE |
+        0xE0 | 0xF0 | 0xE8 | 0xF8 | 0xCB => 2,
+        _ => 1
+    }
+}
+
+fn parse_prefix_command(rom: &[u8]) -> Option<Command> {
     let opcode = rom[0];
     match opcode {
-        /* RLC r */
-        0x00...0x07 => unimplemented!("RLC r"),
-        /* RRC r */
-        0x08...0x0F => unimplemented!("RRC r"),
         /* RL B */
         0x10 => cmd!(alu::RotateRegisterLeft(r8!(B))),
         /* RL C */
@@ -207,20 +303,8 @@ fn parse_prefix_command(rom: &[u8]) -> Option<Box<dyn Instruction>> {
         0x15 => cmd!(alu::RotateRegisterLeft(r8!(L))),
         /* RL A */
         0x17 => cmd!(alu::RotateRegisterLeft(r8!(A))),
-        /* RL (HL) */
-        0x16 => unimplemented!("RL (HL)"),
-        /* RR r */
-        0x17...0x1F => unimplemented!("RR r"),
-        /* SLA r */
-        0x20...0x27 => unimplemented!("SLA r"),
-        /* SRA r */
-        0x27...0x2F => unimplemented!("SRA r"),
-        /* BIT 0,r */
-        0x40...0x47 => unimplemented!("BIT 0,r"),
-        /* BIT 1,r */
-        0x47...0x4F => unimplemented!("BIT 1,r"),
         /* BIT 7,r */
-        0x78...0x7F => {
+        0x78..=0x7D | 0x7F => {
             let bit: u8 = 0b1000_0000;
             let register = match opcode {
                 0x78 => Register8::B,
@@ -242,4 +326,29 @@ fn parse_prefix_command(rom: &[u8]) -> Option<Box<dyn Instruction>> {
 
     }
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+
+    #[test]
+    fn it_should_keep_the_mnemonics() {
+        let command = parse_command(0x20, &[0xFE, 0x00]).unwrap();
+        assert_eq!(format!("{:?}", command), "JR NZ, -2");
+    }
+
+    #[test]
+    fn it_should_know_the_instruction_length() {
+        assert_eq!(length(0x00), 1);
+        assert_eq!(length(0xCB), 2);
+        assert_eq!(length(0x20), 2);
+        assert_eq!(length(0xCD), 3);
+        assert_eq!(length(0xDD), 1);
+    }
+
+    #[test]
+    fn it_should_decode_without_allocation() {
+        assert!(mem::size_of::<Command>() <= 8);
+    }
+}
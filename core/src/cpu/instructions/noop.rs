@@ -1,10 +1,11 @@
 use gameboy::GameBoy;
 use cpu::Instruction;
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub struct NoOp;
 impl Instruction for NoOp {
     fn exec(&self, gb: &mut GameBoy) {
         pc!(gb);
+        cycles!(gb, 4);
     }
 }
\ No newline at end of file
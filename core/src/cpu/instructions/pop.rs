@@ -3,6 +3,7 @@ use cpu::Instruction;
 use std::fmt;
 use cpu::register::RegisterPair;
 
+#[derive(Copy, Clone)]
 pub struct Pop(pub RegisterPair);
 
 impl fmt::Debug for Pop {
@@ -16,6 +17,7 @@ impl Instruction for Pop {
         let value = gb.pop_from_stack();
         gb.register.write_16bit_register(&self.0.into(), value);
         pc!(gb);
+        cycles!(gb, 12);
     }
 }
 
@@ -3,6 +3,7 @@ use cpu::Instruction;
 use std::fmt;
 use cpu::register::RegisterPair;
 
+#[derive(Copy, Clone)]
 pub struct Push(pub RegisterPair);
 
 impl fmt::Debug for Push {
@@ -16,6 +17,7 @@ impl Instruction for Push {
         let register = gb.register.pair(&self.0);
         gb.push_to_stack(register);
         pc!(gb);
+        cycles!(gb, 16);
     }
 }
 
@@ -2,6 +2,7 @@ use gameboy::GameBoy;
 use cpu::Instruction;
 use std::fmt;
 
+#[derive(Copy, Clone)]
 pub struct Return;
 
 impl fmt::Debug for Return {
@@ -14,5 +15,6 @@ impl Instruction for Return {
     fn exec(&self, gb: &mut GameBoy) {
         let next_instruction = gb.pop_from_stack();
         gb.register.pc = next_instruction;
+        cycles!(gb, 16);
     }
 }
\ No newline at end of file
@@ -0,0 +1,47 @@
+use gameboy::GameBoy;
+use cpu::Instruction;
+use std::fmt;
+
+/**
+ * STOP
+ *
+ * Switches the CPU speed on CGB when it was prepared through KEY1.
+ * The low power mode itself is not emulated.
+ */
+#[derive(Copy, Clone)]
+pub struct Stop;
+
+impl fmt::Debug for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "STOP")
+    }
+}
+
+impl Instruction for Stop {
+    fn exec(&self, gb: &mut GameBoy) {
+        gb.stop();
+        pc!(gb, 2);
+        cycles!(gb, 4);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gameboy::{GameBoy, Model, KEY1};
+
+    #[test]
+    fn it_should_increase_the_pc() {
+        let mut gb = GameBoy::new();
+        Stop.exec(&mut gb);
+        assert_eq!(gb.register.pc, 2);
+    }
+
+    #[test]
+    fn it_should_switch_to_double_speed() {
+        let mut gb = GameBoy::with_model(Model::Cgb);
+        gb.write(KEY1, 0x01);
+        Stop.exec(&mut gb);
+        assert!(gb.is_double_speed());
+    }
+}
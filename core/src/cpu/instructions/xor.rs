@@ -3,6 +3,7 @@ use cpu::Instruction;
 use std::fmt;
 use cpu::register::{Flags, Register8};
 
+#[derive(Copy, Clone)]
 pub struct Xor(Register8);
 
 impl fmt::Debug for Xor {
@@ -39,5 +40,6 @@ impl Instruction for Xor {
             Flags::empty()
         };
         pc!(gb);
+        cycles!(gb, 4);
     }
 }
\ No newline at end of file
@@ -5,8 +5,12 @@ macro_rules! pc {
     ($gb:expr, 3) => ($gb.register.pc += 3);
 }
 
+macro_rules! cycles {
+    ($gb:expr, $cycles:expr) => ($gb.cycles += $cycles);
+}
+
 macro_rules! cmd {
-    ($cmd:expr) => (Some(Box::new($cmd)));
+    ($cmd:expr) => (Some(Command::from($cmd)));
 }
 
 macro_rules! u8 {
@@ -11,7 +11,7 @@ bitflags! {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Register8 {
     A,
     B,
@@ -22,7 +22,7 @@ pub enum Register8 {
     L
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Register16 {
     SP,
     AF,
@@ -94,7 +94,8 @@ impl Register {
         let mut buf = [0; 2];
         LittleEndian::write_u16(&mut buf, af);
         self.a = buf[0];
-        self.f = Flags::from_bits(buf[1]).unwrap(); // TODO: remove unwrap
+        // the lower 4 bits of F always read as zero
+        self.f = Flags::from_bits_truncate(buf[1]);
     }
 
     pub fn write_bc(&mut self, bc: u16) {
@@ -204,6 +205,7 @@ impl fmt::Debug for Register {
 }
 
 #[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
 mod tests {
     use super::*;
 
@@ -216,6 +218,14 @@ mod tests {
         assert_eq!(af, 0x80ff);
     }
 
+    #[test]
+    fn it_should_ignore_the_lower_bits_of_f() {
+        let mut register = Register::default();
+        register.write_af(0x0FFF);
+        assert_eq!(register.a, 0xFF);
+        assert_eq!(register.f.bits(), 0x00);
+    }
+
     #[test]
     fn it_should_pair_bc() {
         let mut register = Register::default();
@@ -0,0 +1,350 @@
+use debugger::expression::{Expression, ParseError};
+use debugger::symbols::Symbols;
+use gameboy::GameBoy;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+const ROM_BANK_SIZE: u16 = 0x4000;
+
+/**
+ * Address with an optional ROM bank, written as `bank:address` in hexadecimal, e.g. `03:4A10`.
+ *
+ * The bank only applies to the switchable ROM area, other addresses match in any bank.
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Location {
+    pub bank: Option<usize>,
+    pub address: u16
+}
+
+impl Location {
+    pub fn new(address: u16) -> Location {
+        Location {
+            bank: None,
+            address
+        }
+    }
+
+    /**
+     * Parses a location or the name of a symbol, e.g. `Main.loop`.
+     */
+    pub fn parse(location: &str, symbols: &Symbols) -> Result<Location, ParseError> {
+        location.parse().or_else(|err| symbols.find(location.trim()).ok_or(err))
+    }
+
+    pub fn matches(&self, gb: &GameBoy, address: u16) -> bool {
+        address == self.address && gb.cartridge.as_ref().is_none_or(|cartridge| self.is_in_bank(cartridge.rom_bank()))
+    }
+
+    /**
+     * Whether the location is mapped while the ROM bank is.
+     */
+    pub fn is_in_bank(&self, bank: usize) -> bool {
+        match self.bank {
+            Some(own) if (ROM_BANK_SIZE..2 * ROM_BANK_SIZE).contains(&self.address) => own == bank,
+            _ => true
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.bank {
+            Some(bank) => write!(f, "{:02X}:{:04X}", bank, self.address),
+            None => write!(f, "{:04X}", self.address)
+        }
+    }
+}
+
+impl FromStr for Location {
+    type Err = ParseError;
+
+    fn from_str(location: &str) -> Result<Location, ParseError> {
+        let hex = |number: &str| {
+            let number = number.trim();
+            let digits = number.strip_prefix("0x").or_else(|| number.strip_prefix('$')).unwrap_or(number);
+            usize::from_str_radix(digits, 16).map_err(|_| ParseError::InvalidNumber(number.to_string()))
+        };
+        let (bank, address) = match location.find(':') {
+            Some(index) => (Some(hex(&location[..index])?), &location[index + 1..]),
+            None => (None, location)
+        };
+        let address = hex(address)?;
+        if address > 0xFFFF {
+            return Err(ParseError::InvalidNumber(format!("{:X}", address)));
+        }
+        Ok(Location {
+            bank,
+            address: address as u16
+        })
+    }
+}
+
+/**
+ * When a breakpoint stops depending on how often its location was reached with the condition met.
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum HitCount {
+    Always,
+    /// Only on the nth hit
+    Equal(u64),
+    /// On the nth hit and every one after
+    AtLeast(u64),
+    /// On every nth hit
+    Multiple(u64)
+}
+
+impl HitCount {
+    fn triggers(&self, hits: u64) -> bool {
+        match *self {
+            HitCount::Always => true,
+            HitCount::Equal(count) => hits == count,
+            HitCount::AtLeast(count) => hits >= count,
+            HitCount::Multiple(count) => hits.is_multiple_of(count)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    pub location: Location,
+    pub condition: Option<Expression>,
+    pub hit_count: HitCount,
+    pub enabled: bool,
+    /// Times the location was reached with the condition met
+    pub hits: u64
+}
+
+impl Breakpoint {
+    pub fn new(location: Location) -> Breakpoint {
+        Breakpoint {
+            location,
+            condition: None,
+            hit_count: HitCount::Always,
+            enabled: true,
+            hits: 0
+        }
+    }
+
+    /**
+     * Parses `location [if condition]` where the location may be the name of a symbol, e.g. `Main.loop if A == 0`.
+     */
+    pub fn parse(breakpoint: &str, symbols: &Symbols) -> Result<Breakpoint, ParseError> {
+        let breakpoint = breakpoint.trim();
+        let (location, condition) = match breakpoint.find(" if ") {
+            Some(index) => (&breakpoint[..index], Some(breakpoint[index + 4..].parse()?)),
+            None => (breakpoint, None)
+        };
+        Ok(Breakpoint {
+            condition,
+            ..Breakpoint::new(Location::parse(location, symbols)?)
+        })
+    }
+}
+
+/**
+ * Parses `location [if condition]`, e.g. `03:4A10 if A == 0x3F && [HL] != 0`.
+ */
+impl FromStr for Breakpoint {
+    type Err = ParseError;
+
+    fn from_str(breakpoint: &str) -> Result<Breakpoint, ParseError> {
+        Breakpoint::parse(breakpoint, &Symbols::default())
+    }
+}
+
+/**
+ * Breakpoints by id.
+ *
+ * The addresses of the enabled breakpoints are kept in a bitmap, so checking the PC costs a
+ * single lookup unless there is a breakpoint at it.
+ */
+#[derive(Debug, Default)]
+pub struct Breakpoints {
+    breakpoints: BTreeMap<usize, Breakpoint>,
+    next_id: usize,
+    addresses: Vec<u64>
+}
+
+impl Breakpoints {
+    pub fn add(&mut self, breakpoint: Breakpoint) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.breakpoints.insert(id, breakpoint);
+        self.update();
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Breakpoint> {
+        let breakpoint = self.breakpoints.remove(&id);
+        self.update();
+        breakpoint
+    }
+
+    /**
+     * Returns false when there is no breakpoint with the id.
+     */
+    pub fn enable(&mut self, id: usize, enabled: bool) -> bool {
+        let found = match self.breakpoints.get_mut(&id) {
+            Some(breakpoint) => {
+                breakpoint.enabled = enabled;
+                true
+            },
+            None => false
+        };
+        self.update();
+        found
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Breakpoint> {
+        self.breakpoints.get(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Breakpoint)> {
+        self.breakpoints.iter().map(|(id, breakpoint)| (*id, breakpoint))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.breakpoints.is_empty()
+    }
+
+    /**
+     * Id of the first breakpoint at the location.
+     */
+    pub fn find(&self, location: Location) -> Option<usize> {
+        self.iter()
+            .find(|(_, breakpoint)| breakpoint.location == location)
+            .map(|(id, _)| id)
+    }
+
+    /**
+     * Whether an enabled breakpoint is set at the address in any bank.
+     */
+    pub fn is_set(&self, address: u16) -> bool {
+        self.addresses
+            .get(address as usize / 64)
+            .is_some_and(|bits| bits & (1 << (address % 64)) != 0)
+    }
+
+    /**
+     * Counts the hits at PC and returns the id of the breakpoint which stops there.
+     */
+    pub fn check(&mut self, gb: &GameBoy) -> Option<usize> {
+        let pc = gb.register.pc;
+        if !self.is_set(pc) {
+            return None;
+        }
+        for (id, breakpoint) in self.breakpoints.iter_mut() {
+            if !breakpoint.enabled || !breakpoint.location.matches(gb, pc) {
+                continue;
+            }
+            if breakpoint.condition.as_ref().is_some_and(|condition| !condition.is_true(gb)) {
+                continue;
+            }
+            breakpoint.hits += 1;
+            if breakpoint.hit_count.triggers(breakpoint.hits) {
+                return Some(*id);
+            }
+        }
+        None
+    }
+
+    fn update(&mut self) {
+        self.addresses.clear();
+        for breakpoint in self.breakpoints.values().filter(|breakpoint| breakpoint.enabled) {
+            if self.addresses.is_empty() {
+                self.addresses = vec![0; 0x10000 / 64];
+            }
+            let address = breakpoint.location.address;
+            self.addresses[address as usize / 64] |= 1 << (address % 64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_locations() {
+        assert_eq!("4A10".parse(), Ok(Location::new(0x4A10)));
+        assert_eq!("0x150".parse(), Ok(Location::new(0x0150)));
+        assert_eq!("03:4A10".parse(), Ok(Location { bank: Some(3), address: 0x4A10 }));
+        assert!("10000".parse::<Location>().is_err());
+        assert_eq!(Location { bank: Some(3), address: 0x4A10 }.to_string(), "03:4A10");
+    }
+
+    #[test]
+    fn it_should_parse_conditions() {
+        let breakpoint: Breakpoint = "0150 if A == 0x3F".parse().unwrap();
+        assert_eq!(breakpoint.location, Location::new(0x0150));
+        assert!(breakpoint.condition.is_some());
+    }
+
+    #[test]
+    fn it_should_resolve_symbols() {
+        let symbols = Symbols::parse_sym("01:4A10 Main.loop");
+        let breakpoint = Breakpoint::parse("Main.loop if A == 1", &symbols).unwrap();
+        assert_eq!(breakpoint.location, Location { bank: Some(1), address: 0x4A10 });
+        assert!(breakpoint.condition.is_some());
+        assert!(Breakpoint::parse("Main.exit", &symbols).is_err());
+    }
+
+    #[test]
+    fn it_should_stop_at_the_pc() {
+        let mut gb = GameBoy::new();
+        let mut breakpoints = Breakpoints::default();
+        let id = breakpoints.add(Breakpoint::new(Location::new(0x0150)));
+        assert_eq!(breakpoints.check(&gb), None);
+        gb.register.pc = 0x0150;
+        assert_eq!(breakpoints.check(&gb), Some(id));
+        assert_eq!(breakpoints.get(id).unwrap().hits, 1);
+    }
+
+    #[test]
+    fn it_should_skip_disabled_breakpoints() {
+        let mut gb = GameBoy::new();
+        gb.register.pc = 0x0150;
+        let mut breakpoints = Breakpoints::default();
+        let id = breakpoints.add(Breakpoint::new(Location::new(0x0150)));
+        breakpoints.enable(id, false);
+        assert!(!breakpoints.is_set(0x0150));
+        assert_eq!(breakpoints.check(&gb), None);
+        breakpoints.enable(id, true);
+        assert_eq!(breakpoints.check(&gb), Some(id));
+    }
+
+    #[test]
+    fn it_should_evaluate_the_condition() {
+        let mut gb = GameBoy::new();
+        gb.register.pc = 0x0150;
+        let mut breakpoints = Breakpoints::default();
+        let id = breakpoints.add("0150 if A == 0x3F".parse().unwrap());
+        assert_eq!(breakpoints.check(&gb), None);
+        gb.register.a = 0x3F;
+        assert_eq!(breakpoints.check(&gb), Some(id));
+    }
+
+    #[test]
+    fn it_should_count_hits() {
+        let mut gb = GameBoy::new();
+        gb.register.pc = 0x0150;
+        let mut breakpoints = Breakpoints::default();
+        let id = breakpoints.add(Breakpoint {
+            hit_count: HitCount::Multiple(3),
+            ..Breakpoint::new(Location::new(0x0150))
+        });
+        let stops: Vec<bool> = (0..6).map(|_| breakpoints.check(&gb) == Some(id)).collect();
+        assert_eq!(stops, vec![false, false, true, false, false, true]);
+    }
+
+    #[test]
+    fn it_should_remove_breakpoints() {
+        let mut breakpoints = Breakpoints::default();
+        let id = breakpoints.add(Breakpoint::new(Location::new(0x0150)));
+        assert!(breakpoints.remove(id).is_some());
+        assert!(breakpoints.is_empty());
+        assert!(!breakpoints.is_set(0x0150));
+    }
+}
@@ -0,0 +1,294 @@
+use cpu::register::{Register8, Register16};
+use gameboy::GameBoy;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operator {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or
+}
+
+/**
+ * Expression over registers and memory, e.g. `A == 0x3F && [HL] != 0`.
+ *
+ * Numbers are decimal unless prefixed with `0x` or `$`, `[address]` reads a byte from memory.
+ * Comparisons evaluate to 1 or 0.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Number(u16),
+    Register8(Register8),
+    Register16(Register16),
+    Flags,
+    ProgramCounter,
+    Memory(Box<Expression>),
+    Binary(Box<Expression>, Operator, Box<Expression>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    UnexpectedEnd,
+    UnexpectedToken(String),
+    InvalidNumber(String)
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedEnd => write!(f, "unexpected end of expression"),
+            ParseError::UnexpectedToken(token) => write!(f, "unexpected `{}`", token),
+            ParseError::InvalidNumber(number) => write!(f, "invalid number `{}`", number)
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+impl Expression {
+    pub fn evaluate(&self, gb: &GameBoy) -> u16 {
+        match self {
+            Expression::Number(value) => *value,
+            Expression::Register8(register) => gb.register.read_8bit_register(register) as u16,
+            Expression::Register16(register) => gb.register.read_16bit_register(register),
+            Expression::Flags => gb.register.f.bits() as u16,
+            Expression::ProgramCounter => gb.register.pc,
+            Expression::Memory(address) => gb.read(address.evaluate(gb)) as u16,
+            Expression::Binary(left, operator, right) => {
+                let left = left.evaluate(gb);
+                // the right side of && and || is only evaluated when needed
+                let result = match operator {
+                    Operator::And => left != 0 && right.evaluate(gb) != 0,
+                    Operator::Or => left != 0 || right.evaluate(gb) != 0,
+                    Operator::Equal => left == right.evaluate(gb),
+                    Operator::NotEqual => left != right.evaluate(gb),
+                    Operator::Less => left < right.evaluate(gb),
+                    Operator::LessEqual => left <= right.evaluate(gb),
+                    Operator::Greater => left > right.evaluate(gb),
+                    Operator::GreaterEqual => left >= right.evaluate(gb)
+                };
+                result as u16
+            }
+        }
+    }
+
+    pub fn is_true(&self, gb: &GameBoy) -> bool {
+        self.evaluate(gb) != 0
+    }
+}
+
+impl FromStr for Expression {
+    type Err = ParseError;
+
+    fn from_str(input: &str) -> Result<Expression, ParseError> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser { tokens, position: 0 };
+        let expression = parser.or()?;
+        match parser.next() {
+            Some(token) => Err(ParseError::UnexpectedToken(token)),
+            None => Ok(expression)
+        }
+    }
+}
+
+/**
+ * Parses `0x` or `$` prefixed hexadecimal and plain decimal numbers.
+ */
+pub fn parse_number(number: &str) -> Result<u16, ParseError> {
+    let result = if let Some(hex) = number.strip_prefix("0x").or_else(|| number.strip_prefix('$')) {
+        u16::from_str_radix(hex, 16)
+    }else {
+        number.parse()
+    };
+    result.map_err(|_| ParseError::InvalidNumber(number.to_string()))
+}
+
+fn tokenize(input: &str) -> Result<Vec<String>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        }else if c.is_ascii_alphanumeric() || c == '$' {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if !c.is_ascii_alphanumeric() && c != '$' {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        }else {
+            chars.next();
+            let token = match (c, chars.peek()) {
+                ('=', Some('=')) | ('!', Some('=')) | ('<', Some('=')) | ('>', Some('=')) |
+                ('&', Some('&')) | ('|', Some('|')) => {
+                    let second = chars.next().unwrap_or_default();
+                    format!("{}{}", c, second)
+                },
+                ('<', _) | ('>', _) | ('[', _) | (']', _) | ('(', _) | (')', _) => c.to_string(),
+                _ => return Err(ParseError::UnexpectedToken(c.to_string()))
+            };
+            tokens.push(token);
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    position: usize
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), ParseError> {
+        match self.next() {
+            Some(ref token) if token == expected => Ok(()),
+            Some(token) => Err(ParseError::UnexpectedToken(token)),
+            None => Err(ParseError::UnexpectedEnd)
+        }
+    }
+
+    fn or(&mut self) -> Result<Expression, ParseError> {
+        let mut left = self.and()?;
+        while self.peek() == Some("||") {
+            self.next();
+            left = Expression::Binary(Box::new(left), Operator::Or, Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expression, ParseError> {
+        let mut left = self.comparison()?;
+        while self.peek() == Some("&&") {
+            self.next();
+            left = Expression::Binary(Box::new(left), Operator::And, Box::new(self.comparison()?));
+        }
+        Ok(left)
+    }
+
+    fn comparison(&mut self) -> Result<Expression, ParseError> {
+        let left = self.operand()?;
+        let operator = match self.peek() {
+            Some("==") => Operator::Equal,
+            Some("!=") => Operator::NotEqual,
+            Some("<") => Operator::Less,
+            Some("<=") => Operator::LessEqual,
+            Some(">") => Operator::Greater,
+            Some(">=") => Operator::GreaterEqual,
+            _ => return Ok(left)
+        };
+        self.next();
+        Ok(Expression::Binary(Box::new(left), operator, Box::new(self.operand()?)))
+    }
+
+    fn operand(&mut self) -> Result<Expression, ParseError> {
+        let token = self.next().ok_or(ParseError::UnexpectedEnd)?;
+        match token.as_str() {
+            "(" => {
+                let expression = self.or()?;
+                self.expect(")")?;
+                Ok(expression)
+            },
+            "[" => {
+                let address = self.or()?;
+                self.expect("]")?;
+                Ok(Expression::Memory(Box::new(address)))
+            },
+            _ => register(&token)
+                .map(Ok)
+                .unwrap_or_else(|| parse_number(&token).map(Expression::Number))
+        }
+    }
+}
+
+fn register(name: &str) -> Option<Expression> {
+    let expression = match name.to_uppercase().as_str() {
+        "A" => Expression::Register8(Register8::A),
+        "B" => Expression::Register8(Register8::B),
+        "C" => Expression::Register8(Register8::C),
+        "D" => Expression::Register8(Register8::D),
+        "E" => Expression::Register8(Register8::E),
+        "H" => Expression::Register8(Register8::H),
+        "L" => Expression::Register8(Register8::L),
+        "F" => Expression::Flags,
+        "AF" => Expression::Register16(Register16::AF),
+        "BC" => Expression::Register16(Register16::BC),
+        "DE" => Expression::Register16(Register16::DE),
+        "HL" => Expression::Register16(Register16::HL),
+        "SP" => Expression::Register16(Register16::SP),
+        "PC" => Expression::ProgramCounter,
+        _ => return None
+    };
+    Some(expression)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(expression: &str, gb: &GameBoy) -> u16 {
+        expression.parse::<Expression>().unwrap().evaluate(gb)
+    }
+
+    #[test]
+    fn it_should_parse_numbers() {
+        assert_eq!(parse_number("0x3F"), Ok(0x3F));
+        assert_eq!(parse_number("$3F"), Ok(0x3F));
+        assert_eq!(parse_number("63"), Ok(63));
+        assert!(parse_number("3F").is_err());
+    }
+
+    #[test]
+    fn it_should_compare_registers() {
+        let mut gb = GameBoy::new();
+        gb.register.a = 0x3F;
+        assert_eq!(evaluate("A == 0x3F", &gb), 1);
+        assert_eq!(evaluate("a != $3F", &gb), 0);
+        assert_eq!(evaluate("A > 10 && A < 100", &gb), 1);
+    }
+
+    #[test]
+    fn it_should_read_memory() {
+        let mut gb = GameBoy::new();
+        gb.register.a = 0x3F;
+        gb.write(0xC000, 0x12);
+        gb.register.write_hl(0xC000);
+        assert_eq!(evaluate("[HL]", &gb), 0x12);
+        assert_eq!(evaluate("A == 0x3F && [HL] != 0", &gb), 1);
+        assert_eq!(evaluate("[0xC000] == 0x12", &gb), 1);
+    }
+
+    #[test]
+    fn it_should_bind_and_tighter_than_or() {
+        let gb = GameBoy::new();
+        assert_eq!(evaluate("1 || 1 && 0", &gb), 1);
+        assert_eq!(evaluate("(1 || 1) && 0", &gb), 0);
+    }
+
+    #[test]
+    fn it_should_reject_invalid_expressions() {
+        assert_eq!("A ==".parse::<Expression>(), Err(ParseError::UnexpectedEnd));
+        assert_eq!("A == 1)".parse::<Expression>(), Err(ParseError::UnexpectedToken(")".to_string())));
+        assert_eq!("A = 1".parse::<Expression>(), Err(ParseError::UnexpectedToken("=".to_string())));
+        assert_eq!("X".parse::<Expression>(), Err(ParseError::InvalidNumber("X".to_string())));
+    }
+}
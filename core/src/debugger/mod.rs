@@ -0,0 +1,326 @@
+use cpu::instructions::{self, Command};
+use error::EmulationError;
+use gameboy::{Event, GameBoy};
+
+pub mod breakpoint;
+pub mod expression;
+pub mod symbols;
+pub mod watchpoint;
+
+use self::breakpoint::{Breakpoints, Location};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Mode {
+    Running,
+    Paused,
+    Step,
+    /// Until the instruction after the call is reached in the same stack frame
+    StepOver { address: u16, sp: u16 },
+    /// Until a return leaves the stack frame
+    StepOut { sp: u16 },
+    RunTo(u16),
+    /// Until the given frame started
+    RunToFrame(u64)
+}
+
+/**
+ * A subroutine entered by a call which hasn't returned yet.
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frame {
+    /// Start of the subroutine with the ROM bank mapped when it was called
+    pub function: Location,
+    /// Address of the call instruction
+    pub caller: u16,
+    /// SP right after the call, the frame is left once SP is above it
+    pub sp: u16
+}
+
+/**
+ * Execution control for debugging frontends.
+ *
+ * Frontends request how far to run and call `step` in their loop, the debugger pauses once the
+ * requested location or a breakpoint is reached, or after an instruction accessed memory watched
+ * by `GameBoy::watchpoints`.
+ */
+#[derive(Debug)]
+pub struct Debugger {
+    pub breakpoints: Breakpoints,
+    mode: Mode,
+    call_stack: Vec<Frame>
+}
+
+impl Default for Debugger {
+    fn default() -> Debugger {
+        Debugger {
+            breakpoints: Breakpoints::default(),
+            mode: Mode::Running,
+            call_stack: Vec::new()
+        }
+    }
+}
+
+impl Debugger {
+    pub fn is_paused(&self) -> bool {
+        self.mode == Mode::Paused
+    }
+
+    pub fn pause(&mut self) {
+        self.mode = Mode::Paused;
+    }
+
+    pub fn resume(&mut self) {
+        self.mode = Mode::Running;
+    }
+
+    /**
+     * Executes a single instruction.
+     */
+    pub fn step_into(&mut self) {
+        self.mode = Mode::Step;
+    }
+
+    /**
+     * Executes calls and restarts as a whole, any other instruction is stepped into.
+     */
+    pub fn step_over(&mut self, gb: &GameBoy) {
+        let pc = gb.register.pc;
+        let opcode = gb.read(pc);
+        self.mode = if is_call(opcode) {
+            Mode::StepOver { address: pc.wrapping_add(instructions::length(opcode)), sp: gb.register.sp }
+        }else {
+            Mode::Step
+        };
+    }
+
+    /**
+     * Runs until the current subroutine returns to its caller.
+     */
+    pub fn step_out(&mut self, gb: &GameBoy) {
+        self.mode = Mode::StepOut { sp: gb.register.sp };
+    }
+
+    pub fn run_to(&mut self, address: u16) {
+        self.mode = Mode::RunTo(address);
+    }
+
+    pub fn run_to_next_frame(&mut self, gb: &GameBoy) {
+        self.mode = Mode::RunToFrame(gb.frame() + 1);
+    }
+
+    /**
+     * The subroutines called while stepping through the debugger, innermost last.
+     */
+    pub fn call_stack(&self) -> &[Frame] {
+        &self.call_stack
+    }
+
+    /**
+     * Executes the next instruction unless paused.
+     *
+     * Hitting a breakpoint or watchpoint raises an event, errors pause the debugger as well.
+     */
+    pub fn step(&mut self, gb: &mut GameBoy) -> Result<Option<Command>, EmulationError> {
+        if self.is_paused() {
+            return Ok(None);
+        }
+        let (pc, sp) = (gb.register.pc, gb.register.sp);
+        let opcode = gb.read(pc);
+        let instruction = match gb.step() {
+            Ok(instruction) => instruction,
+            Err(err) => {
+                gb.watchpoints.take_hit();
+                self.pause();
+                return Err(err);
+            }
+        };
+        let mut reached = match self.mode {
+            Mode::Running | Mode::Paused => false,
+            Mode::Step => true,
+            Mode::StepOver { address, sp } => gb.register.pc == address && gb.register.sp >= sp,
+            Mode::StepOut { sp } => is_return(opcode) && gb.register.sp > sp,
+            Mode::RunTo(address) => gb.register.pc == address,
+            Mode::RunToFrame(frame) => gb.frame() >= frame
+        };
+        self.update_call_stack(gb, opcode, pc, sp);
+        if let Some(id) = self.breakpoints.check(gb) {
+            let address = gb.register.pc;
+            gb.raise(Event::Breakpoint { id, address });
+            reached = true;
+        }
+        if let Some(hit) = gb.watchpoints.take_hit() {
+            gb.raise(Event::Watchpoint(hit));
+            reached = true;
+        }
+        if reached {
+            self.pause();
+        }
+        Ok(Some(instruction))
+    }
+
+    fn update_call_stack(&mut self, gb: &GameBoy, opcode: u8, pc: u16, sp: u16) {
+        let sp_after = gb.register.sp;
+        // returns and anything else dropping the return address leave the frame
+        while self.call_stack.last().is_some_and(|frame| sp_after > frame.sp) {
+            self.call_stack.pop();
+        }
+        if is_call(opcode) && sp_after == sp.wrapping_sub(2) {
+            let address = gb.register.pc;
+            self.call_stack.push(Frame {
+                function: Location {
+                    bank: Some(symbols::rom_bank(gb, address)),
+                    address
+                },
+                caller: pc,
+                sp: sp_after
+            });
+        }
+    }
+}
+
+/**
+ * CALL, conditional CALLs and RST.
+ */
+fn is_call(opcode: u8) -> bool {
+    match opcode {
+        0xC4 | 0xCC | 0xCD | 0xD4 | 0xDC => true,
+        _ => opcode & 0xC7 == 0xC7
+    }
+}
+
+/**
+ * RET, conditional RETs and RETI.
+ */
+fn is_return(opcode: u8) -> bool {
+    matches!(opcode, 0xC0 | 0xC8 | 0xC9 | 0xD0 | 0xD8 | 0xD9)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use debugger::watchpoint::{Access, WatchHit};
+
+    /// CALL 0x0006; NOP; NOP; NOP; NOP; RET
+    const PROGRAM: [u8; 7] = [0xCD, 0x06, 0x00, 0x00, 0x00, 0x00, 0xC9];
+
+    fn setup() -> GameBoy {
+        let mut gb = GameBoy::new();
+        gb.load_firmware(&PROGRAM);
+        gb.register.sp = 0xFFFE;
+        gb
+    }
+
+    fn run(debugger: &mut Debugger, gb: &mut GameBoy) {
+        for _ in 0..100 {
+            debugger.step(gb).unwrap();
+        }
+    }
+
+    #[test]
+    fn it_should_track_the_call_stack() {
+        let mut gb = setup();
+        let mut debugger = Debugger::default();
+        debugger.step_into();
+        debugger.step(&mut gb).unwrap();
+        assert_eq!(debugger.call_stack(), &[Frame {
+            function: Location { bank: Some(0), address: 0x0006 },
+            caller: 0x0000,
+            sp: 0xFFFC
+        }]);
+        debugger.step_into();
+        debugger.step(&mut gb).unwrap();
+        assert!(debugger.call_stack().is_empty());
+    }
+
+    #[test]
+    fn it_should_not_step_while_paused() {
+        let mut gb = setup();
+        let mut debugger = Debugger::default();
+        debugger.pause();
+        assert!(debugger.step(&mut gb).unwrap().is_none());
+        assert_eq!(gb.register.pc, 0);
+    }
+
+    #[test]
+    fn it_should_step_into_calls() {
+        let mut gb = setup();
+        let mut debugger = Debugger::default();
+        debugger.step_into();
+        run(&mut debugger, &mut gb);
+        assert!(debugger.is_paused());
+        assert_eq!(gb.register.pc, 0x0006);
+    }
+
+    #[test]
+    fn it_should_step_over_calls() {
+        let mut gb = setup();
+        let mut debugger = Debugger::default();
+        debugger.step_over(&gb);
+        run(&mut debugger, &mut gb);
+        assert_eq!(gb.register.pc, 0x0003);
+        assert_eq!(gb.register.sp, 0xFFFE);
+    }
+
+    #[test]
+    fn it_should_step_out_of_subroutines() {
+        let mut gb = setup();
+        let mut debugger = Debugger::default();
+        debugger.step_into();
+        debugger.step(&mut gb).unwrap();
+        debugger.step_out(&gb);
+        run(&mut debugger, &mut gb);
+        assert_eq!(gb.register.pc, 0x0003);
+    }
+
+    #[test]
+    fn it_should_run_to_an_address() {
+        let mut gb = setup();
+        let mut debugger = Debugger::default();
+        debugger.run_to(0x0005);
+        run(&mut debugger, &mut gb);
+        assert!(debugger.is_paused());
+        assert_eq!(gb.register.pc, 0x0005);
+    }
+
+    #[test]
+    fn it_should_pause_at_breakpoints() {
+        let mut gb = setup();
+        let mut debugger = Debugger::default();
+        let id = debugger.breakpoints.add("0004".parse().unwrap());
+        run(&mut debugger, &mut gb);
+        assert!(debugger.is_paused());
+        assert_eq!(gb.register.pc, 0x0004);
+        assert_eq!(gb.poll_event(), Some(Event::Breakpoint { id, address: 0x0004 }));
+    }
+
+    #[test]
+    fn it_should_pause_after_watched_writes() {
+        let mut gb = GameBoy::new();
+        // NOP; LD (HL-),A
+        gb.load_firmware(&[0x00, 0x32]);
+        gb.register.a = 0x12;
+        gb.register.write_hl(0xC010);
+        let id = gb.watchpoints.add("C000-C0FF".parse().unwrap());
+        let mut debugger = Debugger::default();
+        run(&mut debugger, &mut gb);
+        assert!(debugger.is_paused());
+        assert_eq!(gb.register.pc, 0x0002);
+        assert_eq!(gb.poll_event(), Some(Event::Watchpoint(WatchHit {
+            id,
+            access: Access::Write,
+            address: 0xC010,
+            pc: 0x0001,
+            old: 0x00,
+            new: 0x12
+        })));
+    }
+
+    #[test]
+    fn it_should_detect_calls() {
+        assert!(is_call(0xCD));
+        assert!(is_call(0xDC));
+        assert!(is_call(0xFF));
+        assert!(!is_call(0xC3));
+        assert!(!is_call(0xC9));
+    }
+}
@@ -0,0 +1,177 @@
+use debugger::breakpoint::Location;
+use gameboy::GameBoy;
+use std::collections::{BTreeMap, HashMap};
+
+/// Symbols this far before an address are still used to name it, e.g. `Main+0x12`
+const MAX_OFFSET: u16 = 0x100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub location: Location
+}
+
+/**
+ * Names of addresses from the symbol files written by RGBDS.
+ *
+ * Every symbol has a bank, which only tells symbols in the switchable ROM area apart.
+ */
+#[derive(Debug, Default)]
+pub struct Symbols {
+    by_address: BTreeMap<u16, Vec<Symbol>>,
+    by_name: HashMap<String, Location>
+}
+
+impl Symbols {
+    /**
+     * Parses a `.sym` file with one `bank:address name` per line, e.g. `01:4A10 Main.loop`.
+     *
+     * Comments starting with `;` and lines which aren't symbols are skipped.
+     */
+    pub fn parse_sym(sym: &str) -> Symbols {
+        let mut symbols = Symbols::default();
+        for line in sym.lines() {
+            let line = line.split(';').next().unwrap_or_default();
+            let mut parts = line.split_whitespace();
+            let (location, name) = match (parts.next(), parts.next()) {
+                (Some(location), Some(name)) => (location, name),
+                _ => continue
+            };
+            if let Ok(location) = location.parse::<Location>() {
+                if location.bank.is_some() {
+                    symbols.add(name, location);
+                }
+            }
+        }
+        symbols
+    }
+
+    /**
+     * Parses the symbols listed in a `.map` file, e.g. `$4A10 = Main.loop` below `ROMX bank #1:`.
+     *
+     * Everything else in the file, like the sections and their sizes, is skipped.
+     */
+    pub fn parse_map(map: &str) -> Symbols {
+        let mut symbols = Symbols::default();
+        let mut bank = None;
+        for line in map.lines() {
+            let line = line.trim();
+            if let Some(header) = line.strip_suffix(':') {
+                bank = header.split(" bank #").nth(1).and_then(|bank| bank.parse().ok());
+                continue;
+            }
+            let (address, name) = match (line.strip_prefix('$'), line.find(" = ")) {
+                (Some(_), Some(index)) => (&line[1..index], line[index + 3..].trim()),
+                _ => continue
+            };
+            if let (Some(bank), Ok(address)) = (bank, u16::from_str_radix(address, 16)) {
+                symbols.add(name, Location { bank: Some(bank), address });
+            }
+        }
+        symbols
+    }
+
+    pub fn add(&mut self, name: &str, location: Location) {
+        self.by_address.entry(location.address).or_default().push(Symbol {
+            name: name.to_string(),
+            location
+        });
+        self.by_name.entry(name.to_string()).or_insert(location);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    /**
+     * Location of the symbol with the name.
+     */
+    pub fn find(&self, name: &str) -> Option<Location> {
+        self.by_name.get(name).cloned()
+    }
+
+    /**
+     * The first symbol at the address in the ROM bank.
+     */
+    pub fn get(&self, bank: usize, address: u16) -> Option<&Symbol> {
+        self.by_address.get(&address)?
+            .iter()
+            .find(|symbol| symbol.location.is_in_bank(bank))
+    }
+
+    /**
+     * The symbol at the address or the closest one before it in ROM with the offset, e.g. `Main+0x12`.
+     */
+    pub fn name(&self, bank: usize, address: u16) -> Option<String> {
+        if let Some(symbol) = self.get(bank, address) {
+            return Some(symbol.name.clone());
+        }
+        if address >= 0x8000 {
+            return None;
+        }
+        let start = address.saturating_sub(MAX_OFFSET);
+        self.by_address.range(start..address)
+            .rev()
+            .filter_map(|(_, symbols)| symbols.iter().find(|symbol| symbol.location.is_in_bank(bank)))
+            // symbols in the fixed bank don't name code in the switchable one
+            .find(|symbol| (symbol.location.address < 0x4000) == (address < 0x4000))
+            .map(|symbol| format!("{}+{:#X}", symbol.name, address - symbol.location.address))
+    }
+
+    /**
+     * Names the address with the ROM bank currently mapped.
+     */
+    pub fn name_at(&self, gb: &GameBoy, address: u16) -> Option<String> {
+        self.name(rom_bank(gb, address), address)
+    }
+}
+
+/**
+ * Bank of the address in the ROM, 0 for anything outside the switchable area.
+ */
+pub fn rom_bank(gb: &GameBoy, address: u16) -> usize {
+    match (address, &gb.cartridge) {
+        (0x4000..=0x7FFF, Some(cartridge)) => cartridge.rom_bank(),
+        (0x4000..=0x7FFF, None) => 1,
+        _ => 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SYM: &str = "; File generated by rgblink\n00:0150 Main\n00:0153 Main.loop\n01:4000 Banked\n02:4000 Other\n00:FFEE hStack\n";
+
+    #[test]
+    fn it_should_parse_sym_files() {
+        let symbols = Symbols::parse_sym(SYM);
+        assert_eq!(symbols.len(), 5);
+        assert_eq!(symbols.find("Main.loop"), Some(Location { bank: Some(0), address: 0x0153 }));
+        assert_eq!(symbols.get(2, 0x4000).map(|symbol| symbol.name.as_str()), Some("Other"));
+        assert_eq!(symbols.get(1, 0x0150).map(|symbol| symbol.name.as_str()), Some("Main"));
+    }
+
+    #[test]
+    fn it_should_parse_map_files() {
+        let map = "ROM0 bank #0:\n\tSECTION: $0150-$0155 ($0006 bytes) [\"Main\"]\n\t         $0150 = Main\n\
+                   ROMX bank #3:\n\tSECTION: $4000-$4001 ($0002 bytes) [\"Banked\"]\n\t         $4000 = Banked\n";
+        let symbols = Symbols::parse_map(map);
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols.find("Banked"), Some(Location { bank: Some(3), address: 0x4000 }));
+    }
+
+    #[test]
+    fn it_should_name_addresses_after_a_symbol() {
+        let symbols = Symbols::parse_sym(SYM);
+        assert_eq!(symbols.name(0, 0x0153), Some("Main.loop".to_string()));
+        assert_eq!(symbols.name(0, 0x0158), Some("Main.loop+0x5".to_string()));
+        assert_eq!(symbols.name(2, 0x4003), Some("Other+0x3".to_string()));
+        assert_eq!(symbols.name(1, 0x0100), None);
+        assert_eq!(symbols.name(3, 0x4003), None);
+    }
+}
@@ -0,0 +1,276 @@
+use debugger::expression::{parse_number, ParseError};
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite
+}
+
+impl Access {
+    fn includes(&self, access: Access) -> bool {
+        *self == Access::ReadWrite || *self == access
+    }
+}
+
+impl FromStr for Access {
+    type Err = ParseError;
+
+    fn from_str(access: &str) -> Result<Access, ParseError> {
+        match access {
+            "r" => Ok(Access::Read),
+            "w" => Ok(Access::Write),
+            "rw" => Ok(Access::ReadWrite),
+            _ => Err(ParseError::UnexpectedToken(access.to_string()))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub access: Access,
+    /// Only accesses of this value are reported
+    pub value: Option<u8>,
+    pub enabled: bool
+}
+
+impl Watchpoint {
+    pub fn new(range: RangeInclusive<u16>, access: Access) -> Watchpoint {
+        Watchpoint {
+            range,
+            access,
+            value: None,
+            enabled: true
+        }
+    }
+
+    fn matches(&self, access: Access, address: u16, value: u8) -> bool {
+        self.enabled
+            && self.access.includes(access)
+            && self.range.contains(&address)
+            && self.value.is_none_or(|expected| expected == value)
+    }
+}
+
+/**
+ * Parses `start[-end] [r|w|rw] [== value]` with hexadecimal addresses, e.g. `C000-C0FF w == 0`.
+ *
+ * Watchpoints only watch writes unless specified otherwise.
+ */
+impl FromStr for Watchpoint {
+    type Err = ParseError;
+
+    fn from_str(watchpoint: &str) -> Result<Watchpoint, ParseError> {
+        let hex = |number: &str| {
+            let digits = number.strip_prefix("0x").or_else(|| number.strip_prefix('$')).unwrap_or(number);
+            u16::from_str_radix(digits, 16).map_err(|_| ParseError::InvalidNumber(number.to_string()))
+        };
+        let mut parts = watchpoint.split_whitespace();
+        let range = parts.next().ok_or(ParseError::UnexpectedEnd)?;
+        let range = match range.find('-') {
+            Some(index) => hex(&range[..index])?..=hex(&range[index + 1..])?,
+            None => hex(range)?..=hex(range)?
+        };
+        let mut watchpoint = Watchpoint::new(range, Access::Write);
+        let mut next = parts.next();
+        if let Some(access) = next.and_then(|access| access.parse().ok()) {
+            watchpoint.access = access;
+            next = parts.next();
+        }
+        match next {
+            Some("==") => {
+                let value = parts.next().ok_or(ParseError::UnexpectedEnd)?;
+                let value = parse_number(value)?;
+                if value > 0xFF {
+                    return Err(ParseError::InvalidNumber(value.to_string()));
+                }
+                watchpoint.value = Some(value as u8);
+            },
+            Some(token) => return Err(ParseError::UnexpectedToken(token.to_string())),
+            None => {}
+        }
+        match parts.next() {
+            Some(token) => Err(ParseError::UnexpectedToken(token.to_string())),
+            None => Ok(watchpoint)
+        }
+    }
+}
+
+/**
+ * Access which triggered a watchpoint, reads have the same old and new value.
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct WatchHit {
+    pub id: usize,
+    pub access: Access,
+    pub address: u16,
+    /// Address of the accessing instruction
+    pub pc: u16,
+    pub old: u8,
+    pub new: u8
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.access {
+            Access::Read => write!(f, "Watchpoint {}: {:#06X} read {:#04X} from {:#06X}",
+                                   self.id, self.pc, self.new, self.address),
+            _ => write!(f, "Watchpoint {}: {:#06X} changed {:#06X} from {:#04X} to {:#04X}",
+                        self.id, self.pc, self.address, self.old, self.new)
+        }
+    }
+}
+
+/**
+ * Watchpoints by id, checked on every memory access of the executed instructions.
+ *
+ * Accesses outside of instructions, e.g. by a debugger showing memory, are ignored.
+ */
+#[derive(Debug, Default)]
+pub struct Watchpoints {
+    watchpoints: BTreeMap<usize, Watchpoint>,
+    next_id: usize,
+    /// PC of the executing instruction while any watchpoint is enabled
+    executing: Option<u16>,
+    /// Memory reads don't borrow the Game Boy mutably
+    hit: Cell<Option<WatchHit>>
+}
+
+impl Watchpoints {
+    pub fn add(&mut self, watchpoint: Watchpoint) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.watchpoints.insert(id, watchpoint);
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Watchpoint> {
+        self.watchpoints.remove(&id)
+    }
+
+    /**
+     * Returns false when there is no watchpoint with the id.
+     */
+    pub fn enable(&mut self, id: usize, enabled: bool) -> bool {
+        match self.watchpoints.get_mut(&id) {
+            Some(watchpoint) => {
+                watchpoint.enabled = enabled;
+                true
+            },
+            None => false
+        }
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Watchpoint> {
+        self.watchpoints.get(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Watchpoint)> {
+        self.watchpoints.iter().map(|(id, watchpoint)| (*id, watchpoint))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watchpoints.is_empty()
+    }
+
+    /**
+     * Starts watching the accesses of the instruction at `pc`.
+     */
+    pub fn begin(&mut self, pc: u16) {
+        let enabled = self.watchpoints.values().any(|watchpoint| watchpoint.enabled);
+        self.executing = if enabled { Some(pc) } else { None };
+    }
+
+    pub fn end(&mut self) {
+        self.executing = None;
+    }
+
+    pub fn is_watching(&self) -> bool {
+        self.executing.is_some()
+    }
+
+    pub fn read(&self, address: u16, value: u8) {
+        self.access(Access::Read, address, value, value);
+    }
+
+    pub fn write(&self, address: u16, old: u8, new: u8) {
+        self.access(Access::Write, address, old, new);
+    }
+
+    /**
+     * The first hit since the last call, the instruction is reported only once.
+     */
+    pub fn take_hit(&mut self) -> Option<WatchHit> {
+        self.hit.take()
+    }
+
+    fn access(&self, access: Access, address: u16, old: u8, new: u8) {
+        let pc = match self.executing {
+            Some(pc) => pc,
+            None => return
+        };
+        if self.hit.get().is_some() {
+            return;
+        }
+        let id = self.watchpoints.iter()
+            .find(|(_, watchpoint)| watchpoint.matches(access, address, new))
+            .map(|(id, _)| *id);
+        if let Some(id) = id {
+            self.hit.set(Some(WatchHit { id, access, address, pc, old, new }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_watchpoints() {
+        assert_eq!("C000".parse(), Ok(Watchpoint::new(0xC000..=0xC000, Access::Write)));
+        let watchpoint: Watchpoint = "C000-C0FF rw == 0x12".parse().unwrap();
+        assert_eq!(watchpoint.range, 0xC000..=0xC0FF);
+        assert_eq!(watchpoint.access, Access::ReadWrite);
+        assert_eq!(watchpoint.value, Some(0x12));
+        assert!("C000 x".parse::<Watchpoint>().is_err());
+        assert!("C000 == 256".parse::<Watchpoint>().is_err());
+    }
+
+    #[test]
+    fn it_should_only_watch_executing_instructions() {
+        let mut watchpoints = Watchpoints::default();
+        watchpoints.add(Watchpoint::new(0xC000..=0xC000, Access::Read));
+        watchpoints.read(0xC000, 0x12);
+        assert_eq!(watchpoints.take_hit(), None);
+    }
+
+    #[test]
+    fn it_should_filter_by_access_and_value() {
+        let mut watchpoints = Watchpoints::default();
+        let id = watchpoints.add(Watchpoint {
+            value: Some(0x12),
+            ..Watchpoint::new(0xC000..=0xC0FF, Access::Write)
+        });
+        watchpoints.begin(0x0150);
+        watchpoints.read(0xC010, 0x12);
+        watchpoints.write(0xC010, 0x00, 0x11);
+        watchpoints.write(0xC100, 0x00, 0x12);
+        assert_eq!(watchpoints.take_hit(), None);
+        watchpoints.write(0xC010, 0x00, 0x12);
+        watchpoints.end();
+        assert_eq!(watchpoints.take_hit(), Some(WatchHit {
+            id,
+            access: Access::Write,
+            address: 0xC010,
+            pc: 0x0150,
+            old: 0x00,
+            new: 0x12
+        }));
+    }
+}
@@ -0,0 +1,307 @@
+use debugger::symbols::Symbols;
+use disasm::{decode, Flow, Instruction, Operand};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+const BANK_SIZE: usize = 0x4000;
+/// Data bytes shown per line
+const DATA_LINE: usize = 8;
+
+const VECTORS: [(usize, &str); 6] = [
+    (0x0040, "VBlank"),
+    (0x0048, "LCDStat"),
+    (0x0050, "Timer"),
+    (0x0058, "Serial"),
+    (0x0060, "Joypad"),
+    (0x0100, "Entry")
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Label {
+    Jump,
+    Call,
+    Vector(&'static str)
+}
+
+/**
+ * Disassembly of a whole ROM, telling code from data by following the control flow from the entry
+ * point and the interrupt vectors.
+ *
+ * Targets in the switchable bank are followed within the same bank. Code in bank 0 only reaches
+ * them when the ROM has no banks, or after an `LD A,n` directly followed by an `LD (nn),A` to the
+ * bank register selected which bank is mapped. Bytes which are never reached are listed as data.
+ *
+ * Symbols name the labels and memory addresses, labels without a symbol are named after their
+ * location, e.g. `Call_01_4A10`.
+ */
+pub struct Listing<'a> {
+    rom: &'a [u8],
+    symbols: &'a Symbols,
+    /// ROM offsets where instructions start
+    instructions: BTreeSet<usize>,
+    code: Vec<bool>,
+    /// ROM offsets of the jump and call targets by the offset of the instruction
+    targets: BTreeMap<usize, usize>,
+    labels: BTreeMap<usize, Label>
+}
+
+impl<'a> Listing<'a> {
+    pub fn new(rom: &'a [u8], symbols: &'a Symbols) -> Listing<'a> {
+        let mut listing = Listing {
+            rom,
+            symbols,
+            instructions: BTreeSet::new(),
+            code: vec![false; rom.len()],
+            targets: BTreeMap::new(),
+            labels: BTreeMap::new()
+        };
+        for &(offset, name) in VECTORS.iter() {
+            listing.labels.insert(offset, Label::Vector(name));
+        }
+        let entries = VECTORS.iter().map(|&(offset, _)| (offset, None)).collect();
+        listing.traverse(entries);
+        listing
+    }
+
+    pub fn is_code(&self, offset: usize) -> bool {
+        self.code.get(offset).cloned().unwrap_or(false)
+    }
+
+    /**
+     * Label at the ROM offset, if there is a symbol or anything jumps or calls there.
+     */
+    pub fn label(&self, offset: usize) -> Option<String> {
+        let (bank, address) = (offset / BANK_SIZE, address(offset));
+        if let Some(symbol) = self.symbols.get(bank, address as u16) {
+            return Some(symbol.name.clone());
+        }
+        self.labels.get(&offset).map(|label| {
+            match *label {
+                Label::Vector(name) => name.to_string(),
+                Label::Call => format!("Call_{:02X}_{:04X}", bank, address),
+                Label::Jump => format!("Jump_{:02X}_{:04X}", bank, address)
+            }
+        })
+    }
+
+    /**
+     * Writes one line per instruction, `bank:address  bytes  instruction`, with the labels on their own lines.
+     */
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut offset = 0;
+        while offset < self.rom.len() {
+            let bank = offset / BANK_SIZE;
+            if offset.is_multiple_of(BANK_SIZE) {
+                writeln!(out, "; Bank {:02X}", bank)?;
+            }
+            if let Some(label) = self.label(offset) {
+                writeln!(out)?;
+                writeln!(out, "{}:", label)?;
+            }
+            let (length, text) = if self.instructions.contains(&offset) {
+                let instruction = decode(&self.rom[offset..], address(offset) as u16);
+                let target = self.targets.get(&offset).and_then(|&target| self.label(target));
+                let text = instruction.format(|address| match instruction.target() {
+                    Some(jump) if jump == address => target.clone(),
+                    _ => self.symbols.get(bank, address).map(|symbol| symbol.name.clone())
+                });
+                (instruction.length as usize, text)
+            }else {
+                let length = self.data_length(offset);
+                let bytes: Vec<String> = self.rom[offset..offset + length].iter()
+                    .map(|byte| format!("{:#04X}", byte))
+                    .collect();
+                (length, format!("DB {}", bytes.join(",")))
+            };
+            let end = self.rom.len().min(offset + length);
+            let bytes: Vec<String> = self.rom[offset..end].iter().map(|byte| format!("{:02X}", byte)).collect();
+            let bytes = if self.instructions.contains(&offset) { bytes.join(" ") } else { String::new() };
+            writeln!(out, "{:02X}:{:04X}  {:<8}  {}", bank, address(offset), bytes, text)?;
+            offset = end;
+        }
+        Ok(())
+    }
+
+    /**
+     * Data runs until the next code, label or bank.
+     */
+    fn data_length(&self, offset: usize) -> usize {
+        let mut length = 1;
+        while length < DATA_LINE {
+            let next = offset + length;
+            if next >= self.rom.len() || next.is_multiple_of(BANK_SIZE) || self.code[next] || self.labels.contains_key(&next) {
+                break;
+            }
+            length += 1;
+        }
+        length
+    }
+
+    fn traverse(&mut self, mut pending: Vec<(usize, Option<usize>)>) {
+        while let Some((mut offset, mut mapped)) = pending.pop() {
+            // the value of A while it is known from an `LD A,n` just before
+            let mut accumulator = None;
+            while offset < self.rom.len() && !self.instructions.contains(&offset) {
+                let bank = offset / BANK_SIZE;
+                let instruction = decode(&self.rom[offset..], address(offset) as u16);
+                let end = offset + instruction.length as usize;
+                if instruction.flow == Flow::Invalid || end > (bank + 1) * BANK_SIZE || end > self.rom.len() {
+                    break;
+                }
+                self.instructions.insert(offset);
+                for code in &mut self.code[offset..end] {
+                    *code = true;
+                }
+                if let Some(selected) = selected_bank(&instruction, accumulator) {
+                    mapped = Some(selected);
+                }
+                accumulator = match instruction.opcode {
+                    0x3E => Some(self.rom[offset + 1]),
+                    _ => None
+                };
+                let target = instruction.target().and_then(|target| self.resolve(target, bank, mapped));
+                if let Some(target) = target {
+                    self.targets.insert(offset, target);
+                    let label = match instruction.flow {
+                        Flow::Call(_) => Label::Call,
+                        _ => Label::Jump
+                    };
+                    let existing = self.labels.entry(target).or_insert(label);
+                    *existing = (*existing).max(label);
+                    pending.push((target, mapped));
+                }
+                match instruction.flow {
+                    // a restart into itself never returns
+                    Flow::Call(target) if target == instruction.address => break,
+                    Flow::Next | Flow::ConditionalJump(_) | Flow::Call(_) | Flow::ConditionalReturn => offset = end,
+                    _ => break
+                }
+            }
+        }
+    }
+
+    /**
+     * ROM offset of an address seen from code in the bank, with the bank mapped by that code if known.
+     */
+    fn resolve(&self, target: u16, bank: usize, mapped: Option<usize>) -> Option<usize> {
+        let target = target as usize;
+        let banks = self.rom.len().div_ceil(BANK_SIZE);
+        let offset = match target {
+            0x0000..=0x3FFF => target,
+            0x4000..=0x7FFF => {
+                let switchable = match (bank, mapped) {
+                    (0, _) if banks <= 2 => 1,
+                    (0, Some(mapped)) => mapped,
+                    (0, None) => return None,
+                    _ => bank
+                };
+                switchable * BANK_SIZE + target - BANK_SIZE
+            },
+            _ => return None
+        };
+        if offset < self.rom.len() { Some(offset) } else { None }
+    }
+}
+
+/**
+ * Bank selected by writing the known accumulator to the bank register.
+ */
+fn selected_bank(instruction: &Instruction, accumulator: Option<u8>) -> Option<usize> {
+    match (instruction.opcode, instruction.operands.first(), accumulator) {
+        (0xEA, Some(&Operand::Address(0x2000..=0x3FFF)), Some(bank)) => Some((bank as usize).max(1)),
+        _ => None
+    }
+}
+
+/**
+ * Address of the ROM offset while its bank is mapped.
+ */
+fn address(offset: usize) -> usize {
+    if offset < BANK_SIZE {
+        offset
+    }else {
+        BANK_SIZE + offset % BANK_SIZE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom(banks: usize, code: &[(usize, &[u8])]) -> Vec<u8> {
+        let mut rom = vec![0xFF; banks * BANK_SIZE];
+        for &(offset, bytes) in code {
+            rom[offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+        // the interrupt vectors return right away
+        for &(offset, _) in VECTORS.iter().take(5) {
+            rom[offset] = 0xD9;
+        }
+        rom
+    }
+
+    fn listing(rom: &[u8]) -> String {
+        let mut out = Vec::new();
+        Listing::new(rom, &Symbols::default()).write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn it_should_follow_jumps_and_calls() {
+        let rom = rom(2, &[
+            (0x0100, &[0x00, 0xC3, 0x50, 0x01]),
+            (0x0150, &[0xCD, 0x00, 0x02, 0x18, 0xFE]),
+            (0x0200, &[0xC9])
+        ]);
+        let symbols = Symbols::default();
+        let listing = Listing::new(&rom, &symbols);
+        assert!(listing.is_code(0x0150));
+        assert!(listing.is_code(0x0200));
+        assert!(!listing.is_code(0x0104));
+        assert!(!listing.is_code(0x0201));
+        assert_eq!(listing.label(0x0150), Some("Jump_00_0150".to_string()));
+        assert_eq!(listing.label(0x0200), Some("Call_00_0200".to_string()));
+        assert_eq!(listing.label(0x0153), Some("Jump_00_0153".to_string()));
+    }
+
+    #[test]
+    fn it_should_list_code_and_data() {
+        let rom = rom(2, &[(0x0100, &[0x00, 0xC3, 0x50, 0x01]), (0x0150, &[0x18, 0xFE])]);
+        let text = listing(&rom);
+        assert!(text.contains("\nEntry:\n00:0100  00        NOP\n00:0101  C3 50 01  JP Jump_00_0150\n"));
+        assert!(text.contains("00:0104            DB 0xFF,0xFF,"));
+        assert!(text.contains("Jump_00_0150:\n00:0150  18 FE     JR Jump_00_0150\n"));
+    }
+
+    #[test]
+    fn it_should_name_labels_after_symbols() {
+        let rom = rom(2, &[(0x0100, &[0xC3, 0x50, 0x01]), (0x0150, &[0xEA, 0x00, 0xC0, 0x18, 0xFB])]);
+        let symbols = Symbols::parse_sym("00:0150 Main\n00:C000 wCounter\n");
+        let mut out = Vec::new();
+        Listing::new(&rom, &symbols).write(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("JP Main\n"));
+        assert!(text.contains("Main:\n00:0150  EA 00 C0  LD (wCounter),A\n00:0153  18 FB     JR Main\n"));
+    }
+
+    #[test]
+    fn it_should_follow_bank_switches() {
+        let rom = rom(4, &[
+            (0x0100, &[0x3E, 0x02, 0xEA, 0x00, 0x20, 0xCD, 0x00, 0x40, 0x18, 0xFE]),
+            (2 * BANK_SIZE, &[0xC9])
+        ]);
+        let symbols = Symbols::default();
+        let listing = Listing::new(&rom, &symbols);
+        assert!(listing.is_code(2 * BANK_SIZE));
+        assert!(!listing.is_code(BANK_SIZE));
+        assert_eq!(listing.label(2 * BANK_SIZE), Some("Call_02_4000".to_string()));
+    }
+
+    #[test]
+    fn it_should_not_guess_the_bank() {
+        let rom = rom(4, &[(0x0100, &[0xCD, 0x00, 0x40, 0x18, 0xFE])]);
+        let symbols = Symbols::default();
+        let listing = Listing::new(&rom, &symbols);
+        assert!((1..4).all(|bank| !listing.is_code(bank * BANK_SIZE)));
+    }
+}
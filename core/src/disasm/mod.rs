@@ -0,0 +1,361 @@
+use cpu::instructions::length;
+use gameboy::GameBoy;
+use std::fmt;
+
+pub mod listing;
+
+const R: [&str; 8] = ["B", "C", "D", "E", "H", "L", "(HL)", "A"];
+const RP: [&str; 4] = ["BC", "DE", "HL", "SP"];
+const RP2: [&str; 4] = ["BC", "DE", "HL", "AF"];
+const CC: [&str; 4] = ["NZ", "Z", "NC", "C"];
+const ROT: [&str; 8] = ["RLC", "RRC", "RL", "RR", "SLA", "SRA", "SWAP", "SRL"];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operand {
+    Register(&'static str),
+    /// Memory at a register, e.g. `(HL+)` or `(C)`
+    Indirect(&'static str),
+    Condition(&'static str),
+    Byte(u8),
+    Word(u16),
+    /// Memory at an immediate address
+    Address(u16),
+    /// Jump, call or restart target
+    Target(u16),
+    /// Signed offset added to SP
+    Offset(i8),
+    /// SP plus a signed offset
+    StackOffset(i8),
+    Bit(u8)
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Register(name) | Operand::Indirect(name) | Operand::Condition(name) => write!(f, "{}", name),
+            Operand::Byte(value) => write!(f, "{:#04X}", value),
+            Operand::Word(value) | Operand::Target(value) => write!(f, "{:#06X}", value),
+            Operand::Address(address) => write!(f, "({:#06X})", address),
+            Operand::Offset(offset) => write!(f, "{}", offset),
+            Operand::StackOffset(offset) if offset < 0 => write!(f, "SP-{:#04X}", -(offset as i16)),
+            Operand::StackOffset(offset) => write!(f, "SP+{:#04X}", offset),
+            Operand::Bit(bit) => write!(f, "{}", bit)
+        }
+    }
+}
+
+/**
+ * How an instruction continues the control flow.
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Flow {
+    Next,
+    Jump(u16),
+    ConditionalJump(u16),
+    /// Calls and restarts return to the next instruction
+    Call(u16),
+    Return,
+    ConditionalReturn,
+    /// `JP HL`, the target is unknown
+    IndirectJump,
+    /// Illegal opcodes lock up the CPU
+    Invalid
+}
+
+/**
+ * An instruction decoded without executing it.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub address: u16,
+    /// The opcode, prefixed with 0xCB for CB instructions
+    pub opcode: u16,
+    pub length: u16,
+    pub mnemonic: &'static str,
+    pub operands: Vec<Operand>,
+    pub flow: Flow
+}
+
+impl Instruction {
+    /**
+     * Target of the jump or call, if known.
+     */
+    pub fn target(&self) -> Option<u16> {
+        match self.flow {
+            Flow::Jump(target) | Flow::ConditionalJump(target) | Flow::Call(target) => Some(target),
+            _ => None
+        }
+    }
+
+    /**
+     * Address of the following instruction.
+     */
+    pub fn next(&self) -> u16 {
+        self.address.wrapping_add(self.length)
+    }
+
+    /**
+     * Formats the instruction with the label of each known target and memory address.
+     */
+    pub fn format<F>(&self, label: F) -> String
+        where F: Fn(u16) -> Option<String> {
+        let operands: Vec<String> = self.operands.iter()
+            .map(|operand| match *operand {
+                Operand::Target(target) => label(target).unwrap_or_else(|| operand.to_string()),
+                Operand::Address(address) => label(address)
+                    .map(|label| format!("({})", label))
+                    .unwrap_or_else(|| operand.to_string()),
+                _ => operand.to_string()
+            })
+            .collect();
+        if operands.is_empty() {
+            self.mnemonic.to_string()
+        }else {
+            format!("{} {}", self.mnemonic, operands.join(","))
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format(|_| None))
+    }
+}
+
+/**
+ * Decodes the instruction in `bytes` located at `address`, missing bytes are read as 0.
+ */
+pub fn decode(bytes: &[u8], address: u16) -> Instruction {
+    let byte = |i: usize| bytes.get(i).cloned().unwrap_or(0);
+    let opcode = byte(0);
+    let d8 = byte(1);
+    let d16 = byte(1) as u16 | (byte(2) as u16) << 8;
+    let relative = address.wrapping_add(2).wrapping_add(d8 as i8 as u16);
+
+    let x = opcode >> 6;
+    let y = ((opcode >> 3) & 0x07) as usize;
+    let z = (opcode & 0x07) as usize;
+    let p = y >> 1;
+    let q = y & 0x01;
+
+    use self::Operand::*;
+    let (mnemonic, operands, flow) = match (x, z) {
+        (0, 0) => match y {
+            0 => ("NOP", vec![], Flow::Next),
+            1 => ("LD", vec![Address(d16), Register("SP")], Flow::Next),
+            2 => ("STOP", vec![], Flow::Next),
+            3 => ("JR", vec![Target(relative)], Flow::Jump(relative)),
+            _ => ("JR", vec![Condition(CC[y - 4]), Target(relative)], Flow::ConditionalJump(relative))
+        },
+        (0, 1) if q == 0 => ("LD", vec![Register(RP[p]), Word(d16)], Flow::Next),
+        (0, 1) => ("ADD", vec![Register("HL"), Register(RP[p])], Flow::Next),
+        (0, 2) => {
+            let memory = Indirect(["(BC)", "(DE)", "(HL+)", "(HL-)"][p]);
+            let operands = if q == 0 { vec![memory, Register("A")] } else { vec![Register("A"), memory] };
+            ("LD", operands, Flow::Next)
+        },
+        (0, 3) => (if q == 0 { "INC" } else { "DEC" }, vec![Register(RP[p])], Flow::Next),
+        (0, 4) => ("INC", vec![register(y)], Flow::Next),
+        (0, 5) => ("DEC", vec![register(y)], Flow::Next),
+        (0, 6) => ("LD", vec![register(y), Byte(d8)], Flow::Next),
+        (0, _) => (["RLCA", "RRCA", "RLA", "RRA", "DAA", "CPL", "SCF", "CCF"][y], vec![], Flow::Next),
+        (1, 6) if y == 6 => ("HALT", vec![], Flow::Next),
+        (1, _) => ("LD", vec![register(y), register(z)], Flow::Next),
+        (2, _) => alu(y, register(z)),
+        (3, 0) => match y {
+            0..=3 => ("RET", vec![Condition(CC[y])], Flow::ConditionalReturn),
+            4 => ("LDH", vec![Address(0xFF00 | d8 as u16), Register("A")], Flow::Next),
+            5 => ("ADD", vec![Register("SP"), Offset(d8 as i8)], Flow::Next),
+            6 => ("LDH", vec![Register("A"), Address(0xFF00 | d8 as u16)], Flow::Next),
+            _ => ("LD", vec![Register("HL"), StackOffset(d8 as i8)], Flow::Next)
+        },
+        (3, 1) if q == 0 => ("POP", vec![Register(RP2[p])], Flow::Next),
+        (3, 1) => match p {
+            0 => ("RET", vec![], Flow::Return),
+            1 => ("RETI", vec![], Flow::Return),
+            2 => ("JP", vec![Register("HL")], Flow::IndirectJump),
+            _ => ("LD", vec![Register("SP"), Register("HL")], Flow::Next)
+        },
+        (3, 2) => match y {
+            0..=3 => ("JP", vec![Condition(CC[y]), Target(d16)], Flow::ConditionalJump(d16)),
+            4 => ("LD", vec![Indirect("(C)"), Register("A")], Flow::Next),
+            5 => ("LD", vec![Address(d16), Register("A")], Flow::Next),
+            6 => ("LD", vec![Register("A"), Indirect("(C)")], Flow::Next),
+            _ => ("LD", vec![Register("A"), Address(d16)], Flow::Next)
+        },
+        (3, 3) => match y {
+            0 => ("JP", vec![Target(d16)], Flow::Jump(d16)),
+            1 => return prefixed(byte(1), address),
+            6 => ("DI", vec![], Flow::Next),
+            7 => ("EI", vec![], Flow::Next),
+            _ => illegal(opcode)
+        },
+        (3, 4) if y < 4 => ("CALL", vec![Condition(CC[y]), Target(d16)], Flow::Call(d16)),
+        (3, 5) if q == 0 => ("PUSH", vec![Register(RP2[p])], Flow::Next),
+        (3, 5) if p == 0 => ("CALL", vec![Target(d16)], Flow::Call(d16)),
+        (3, 6) => alu(y, Byte(d8)),
+        (3, 7) => ("RST", vec![Target(y as u16 * 8)], Flow::Call(y as u16 * 8)),
+        _ => illegal(opcode)
+    };
+    Instruction {
+        address,
+        opcode: opcode as u16,
+        length: length(opcode),
+        mnemonic,
+        operands,
+        flow
+    }
+}
+
+/**
+ * Decodes the instruction at `address` as the CPU sees it.
+ */
+pub fn decode_at(gb: &GameBoy, address: u16) -> Instruction {
+    let bytes = [gb.read(address), gb.read(address.wrapping_add(1)), gb.read(address.wrapping_add(2))];
+    decode(&bytes, address)
+}
+
+/**
+ * Up to `count` instructions ending right before `address`.
+ *
+ * Instructions can't be decoded backwards, so this decodes forward from the earliest address
+ * which lines up with `address`.
+ */
+pub fn preceding(gb: &GameBoy, address: u16, count: usize) -> Vec<Instruction> {
+    // instructions are at most 3 bytes long
+    let span = (count * 3).min(address as usize);
+    for distance in (1..=span).rev() {
+        let mut current = address - distance as u16;
+        let mut instructions = Vec::new();
+        while current < address {
+            let instruction = decode_at(gb, current);
+            current = current.saturating_add(instruction.length);
+            instructions.push(instruction);
+        }
+        if current == address {
+            let skipped = instructions.len().saturating_sub(count);
+            return instructions.split_off(skipped);
+        }
+    }
+    Vec::new()
+}
+
+fn register(index: usize) -> Operand {
+    if index == 6 { Operand::Indirect(R[index]) } else { Operand::Register(R[index]) }
+}
+
+fn alu(operation: usize, operand: Operand) -> (&'static str, Vec<Operand>, Flow) {
+    let (mnemonic, accumulator) = [
+        ("ADD", true), ("ADC", true), ("SUB", false), ("SBC", true),
+        ("AND", false), ("XOR", false), ("OR", false), ("CP", false)
+    ][operation];
+    let operands = if accumulator { vec![Operand::Register("A"), operand] } else { vec![operand] };
+    (mnemonic, operands, Flow::Next)
+}
+
+fn illegal(opcode: u8) -> (&'static str, Vec<Operand>, Flow) {
+    ("DB", vec![Operand::Byte(opcode)], Flow::Invalid)
+}
+
+fn prefixed(opcode: u8, address: u16) -> Instruction {
+    let y = (opcode >> 3) & 0x07;
+    let target = register((opcode & 0x07) as usize);
+    let (mnemonic, operands) = match opcode >> 6 {
+        0 => (ROT[y as usize], vec![target]),
+        1 => ("BIT", vec![Operand::Bit(y), target]),
+        2 => ("RES", vec![Operand::Bit(y), target]),
+        _ => ("SET", vec![Operand::Bit(y), target])
+    };
+    Instruction {
+        address,
+        opcode: 0xCB00 | opcode as u16,
+        length: 2,
+        mnemonic,
+        operands,
+        flow: Flow::Next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(bytes: &[u8]) -> String {
+        decode(bytes, 0x0150).to_string()
+    }
+
+    #[test]
+    fn it_should_decode_loads() {
+        assert_eq!(text(&[0x3E, 0x12]), "LD A,0x12");
+        assert_eq!(text(&[0x21, 0x34, 0x12]), "LD HL,0x1234");
+        assert_eq!(text(&[0x7E]), "LD A,(HL)");
+        assert_eq!(text(&[0x22]), "LD (HL+),A");
+        assert_eq!(text(&[0xE0, 0x44]), "LDH (0xFF44),A");
+        assert_eq!(text(&[0xEA, 0x00, 0xC0]), "LD (0xC000),A");
+        assert_eq!(text(&[0xF8, 0xFE]), "LD HL,SP-0x02");
+        assert_eq!(text(&[0x76]), "HALT");
+    }
+
+    #[test]
+    fn it_should_decode_arithmetic() {
+        assert_eq!(text(&[0x80]), "ADD A,B");
+        assert_eq!(text(&[0xAF]), "XOR A");
+        assert_eq!(text(&[0xFE, 0x90]), "CP 0x90");
+        assert_eq!(text(&[0xE8, 0xF0]), "ADD SP,-16");
+    }
+
+    #[test]
+    fn it_should_decode_prefixed_instructions() {
+        let instruction = decode(&[0xCB, 0x7C], 0);
+        assert_eq!(instruction.to_string(), "BIT 7,H");
+        assert_eq!(instruction.opcode, 0xCB7C);
+        assert_eq!(instruction.length, 2);
+        assert_eq!(text(&[0xCB, 0x37]), "SWAP A");
+        assert_eq!(text(&[0xCB, 0xC6]), "SET 0,(HL)");
+    }
+
+    #[test]
+    fn it_should_resolve_targets() {
+        let instruction = decode(&[0x20, 0xFE], 0x0150);
+        assert_eq!(instruction.flow, Flow::ConditionalJump(0x0150));
+        assert_eq!(instruction.to_string(), "JR NZ,0x0150");
+        assert_eq!(decode(&[0xCD, 0x00, 0x40], 0).flow, Flow::Call(0x4000));
+        assert_eq!(decode(&[0xEF], 0).flow, Flow::Call(0x0028));
+        assert_eq!(decode(&[0xC9], 0).flow, Flow::Return);
+        assert_eq!(decode(&[0xE9], 0).flow, Flow::IndirectJump);
+    }
+
+    #[test]
+    fn it_should_decode_illegal_opcodes_as_data() {
+        let instruction = decode(&[0xDD], 0);
+        assert_eq!(instruction.flow, Flow::Invalid);
+        assert_eq!(instruction.to_string(), "DB 0xDD");
+    }
+
+    #[test]
+    fn it_should_find_preceding_instructions() {
+        let mut gb = GameBoy::new();
+        for (i, &byte) in [0x3E, 0x12, 0x21, 0x34, 0x12, 0x00].iter().enumerate() {
+            gb.write(0xC000 + i as u16, byte);
+        }
+        let addresses: Vec<u16> = preceding(&gb, 0xC006, 3).iter().map(|instruction| instruction.address).collect();
+        assert_eq!(addresses, vec![0xC000, 0xC002, 0xC005]);
+        assert!(preceding(&gb, 0x0000, 3).is_empty());
+    }
+
+    #[test]
+    fn it_should_label_targets() {
+        let instruction = decode(&[0xC3, 0x50, 0x01], 0x0100);
+        let text = instruction.format(|target| if target == 0x0150 { Some("Main".to_string()) } else { None });
+        assert_eq!(text, "JP Main");
+        let instruction = decode(&[0xFA, 0x00, 0xC0], 0x0100);
+        assert_eq!(instruction.format(|_| Some("wCounter".to_string())), "LD A,(wCounter)");
+    }
+
+    #[test]
+    fn it_should_know_the_length_of_every_opcode() {
+        for opcode in 0..=0xFF {
+            assert_eq!(decode(&[opcode, 0, 0], 0).length, length(opcode), "{:#04X}", opcode);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::ops::Range;
+
+pub const DMA: u16 = 0xFF46;
+
+const OAM_SIZE: u16 = 0xA0;
+/// One byte is copied per M-cycle
+const BYTE_CYCLES: u32 = 4;
+
+/**
+ * OAM DMA (0xFF46)
+ *
+ * Writing XX copies 0xXX00-0xXX9F into OAM, one byte per M-cycle.
+ * The copying itself is done by the bus, this only keeps track of the progress.
+ */
+pub struct OamDma {
+    register: u8,
+    offset: u16,
+    cycles: u32,
+    active: bool
+}
+
+impl Default for OamDma {
+    fn default() -> OamDma {
+        OamDma {
+            register: 0xFF,
+            offset: 0,
+            cycles: 0,
+            active: false
+        }
+    }
+}
+
+impl OamDma {
+    pub fn read(&self) -> u8 {
+        self.register
+    }
+
+    /**
+     * Starts a new transfer, a running one is restarted.
+     */
+    pub fn write(&mut self, value: u8) {
+        self.register = value;
+        self.offset = 0;
+        self.cycles = 0;
+        self.active = true;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /**
+     * Sources above 0xDFFF read from the echo of the work RAM.
+     */
+    pub fn source(&self) -> u16 {
+        let source = (self.register as u16) << 8;
+        if source >= 0xE000 {
+            source - 0x2000
+        }else {
+            source
+        }
+    }
+
+    /**
+     * Advances the transfer by the given amount of CPU clock cycles.
+     *
+     * Returns the offsets of the bytes which have to be copied.
+     */
+    pub fn step(&mut self, cycles: u32) -> Range<u16> {
+        if !self.active {
+            return 0..0;
+        }
+        self.cycles += cycles;
+        let start = self.offset;
+        let end = (start as u32 + self.cycles / BYTE_CYCLES).min(OAM_SIZE as u32) as u16;
+        self.cycles %= BYTE_CYCLES;
+        self.offset = end;
+        if end == OAM_SIZE {
+            self.active = false;
+        }
+        start..end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_copy_one_byte_per_m_cycle() {
+        let mut dma = OamDma::default();
+        dma.write(0xC1);
+        assert_eq!(dma.step(10), 0..2);
+        assert_eq!(dma.step(6), 2..4);
+        assert!(dma.is_active());
+    }
+
+    #[test]
+    fn it_should_finish_after_160_m_cycles() {
+        let mut dma = OamDma::default();
+        dma.write(0xC1);
+        assert_eq!(dma.step(1000), 0..OAM_SIZE);
+        assert!(!dma.is_active());
+        assert_eq!(dma.step(4), 0..0);
+        assert_eq!(dma.read(), 0xC1);
+    }
+
+    #[test]
+    fn it_should_read_from_echo_ram_above_0xdfff() {
+        let mut dma = OamDma::default();
+        dma.write(0xFE);
+        assert_eq!(dma.source(), 0xDE00);
+    }
+}
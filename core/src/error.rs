@@ -0,0 +1,41 @@
+use std::error::Error;
+use std::fmt;
+use cartridge::CartridgeError;
+
+/**
+ * Reasons the emulation can't continue.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum EmulationError {
+    /// One of the opcodes which lock up the hardware, only reported when they don't lock up the CPU
+    IllegalOpcode { opcode: u8, address: u16 },
+    /// Valid opcode which isn't emulated yet, CB prefixed opcodes are returned as 0xCBxx
+    UnimplementedOpcode { opcode: u16, address: u16 },
+    /// Instruction fetch from an address nothing is mapped to
+    UnmappedAccess(u16),
+    BadRom(CartridgeError)
+}
+
+impl fmt::Display for EmulationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmulationError::IllegalOpcode { opcode, address } =>
+                write!(f, "illegal opcode {:#04X} at {:#06X}", opcode, address),
+            EmulationError::UnimplementedOpcode { opcode, address } if *opcode > 0xFF =>
+                write!(f, "unimplemented opcode {:#06X} at {:#06X}", opcode, address),
+            EmulationError::UnimplementedOpcode { opcode, address } =>
+                write!(f, "unimplemented opcode {:#04X} at {:#06X}", opcode, address),
+            EmulationError::UnmappedAccess(address) =>
+                write!(f, "nothing is mapped at {:#06X}", address),
+            EmulationError::BadRom(err) => write!(f, "bad rom: {}", err)
+        }
+    }
+}
+
+impl Error for EmulationError {}
+
+impl From<CartridgeError> for EmulationError {
+    fn from(err: CartridgeError) -> EmulationError {
+        EmulationError::BadRom(err)
+    }
+}
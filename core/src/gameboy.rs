@@ -0,0 +1,843 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use cpu::register::{Flags, Register};
+use cpu::Instruction;
+use cpu::instructions::{self, Command};
+use byteorder::{LittleEndian, WriteBytesExt, ByteOrder};
+use cartridge::{Cartridge, CgbSupport};
+use debugger::watchpoint::{Watchpoints, WatchHit};
+use dma::{self, OamDma};
+use error::EmulationError;
+use hdma::{self, Hdma};
+use interrupt::{self, Interrupt};
+use joypad::{self, Button, Joypad};
+use palette;
+use ppu::{self, Mode, Ppu, Screen};
+use serial::{self, Serial};
+use sgb::{self, Sgb};
+
+/// Prepares the speed switch on CGB
+pub const KEY1: u16 = 0xFF4D;
+/// Writing to this register unmaps the boot rom
+pub const BOOT: u16 = 0xFF50;
+/// Work RAM bank mapped to 0xD000-0xDFFF on CGB
+pub const SVBK: u16 = 0xFF70;
+
+/// Clock cycles per second in normal speed mode
+pub const CLOCK_SPEED: u32 = 4_194_304;
+
+const WRAM_BANK_SIZE: usize = 0x1000;
+/// The CPU is halted for 8 M-cycles per transferred VRAM DMA block
+const HDMA_BLOCK_CYCLES: u64 = 32;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Model {
+    Dmg,
+    /// Game Boy Pocket, only the boot rom leaves different register values behind
+    Mgb,
+    Cgb,
+    Sgb
+}
+
+impl Model {
+    /**
+     * CGB mode is used for every cartridge which supports it, SGB mode for the remaining SGB cartridges.
+     */
+    pub fn detect(cartridge: &Cartridge) -> Model {
+        match cartridge.cgb_support() {
+            CgbSupport::Compatible | CgbSupport::Only => Model::Cgb,
+            CgbSupport::None if cartridge.sgb_support() => Model::Sgb,
+            CgbSupport::None => Model::Dmg
+        }
+    }
+}
+
+/**
+ * Notable things which happened during emulation, see `GameBoy::poll_event`.
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+    /// The CPU hangs on an illegal opcode
+    LockedUp { opcode: u8, address: u16 },
+    /// A debugger breakpoint stopped the execution
+    Breakpoint { id: usize, address: u16 },
+    /// An instruction accessed watched memory
+    Watchpoint(WatchHit)
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::LockedUp { opcode, address } =>
+                write!(f, "CPU locked up by illegal opcode {:#04X} at {:#06X}", opcode, address),
+            Event::Breakpoint { id, address } =>
+                write!(f, "Breakpoint {} hit at {:#06X}", id, address),
+            Event::Watchpoint(hit) => write!(f, "{}", hit)
+        }
+    }
+}
+
+/**
+ * What `GameBoy::step` does when it encounters an illegal opcode.
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum IllegalOpcodePolicy {
+    /// Freezes the CPU like the hardware, the peripherals keep running
+    LockUp,
+    /// Stops in front of the opcode so a debugger can inspect or change the state
+    Break,
+    /// Stops the emulation for good
+    Abort
+}
+
+pub struct GameBoy {
+    pub model: Model,
+    pub register: Register,
+    pub ram: [u8; 0x10000],
+    pub cartridge: Option<Cartridge>,
+    pub ppu: Ppu,
+    pub serial: Serial,
+    pub joypad: Joypad,
+    pub sgb: Option<Sgb>,
+    pub dma: OamDma,
+    pub hdma: Hdma,
+    /// Clock cycles executed since power on
+    pub cycles: u64,
+    pub illegal_opcode_policy: IllegalOpcodePolicy,
+    pub watchpoints: Watchpoints,
+    /// Set once the emulation was aborted
+    stopped: Option<EmulationError>,
+    locked: bool,
+    events: VecDeque<Event>,
+    wram: Vec<u8>,
+    wram_bank: usize,
+    double_speed: bool,
+    prepare_speed_switch: bool,
+    boot_rom: bool
+}
+
+impl fmt::Debug for GameBoy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "GameBoy {{ registers: {:?} }}", self.register)
+    }
+}
+
+impl Default for GameBoy {
+    fn default() -> GameBoy {
+        GameBoy::new()
+    }
+}
+
+impl GameBoy {
+    pub fn new() -> GameBoy {
+        GameBoy::with_model(Model::Dmg)
+    }
+
+    pub fn with_model(model: Model) -> GameBoy {
+        let wram_banks = match model {
+            Model::Dmg | Model::Mgb | Model::Sgb => 2,
+            Model::Cgb => 8
+        };
+        GameBoy {
+            model,
+            register: Register::default(),
+            ram: [0; 0x10000],
+            cartridge: None,
+            ppu: Ppu::new(model == Model::Cgb),
+            serial: Serial::default(),
+            joypad: Joypad::default(),
+            sgb: if model == Model::Sgb { Some(Sgb::default()) } else { None },
+            dma: OamDma::default(),
+            hdma: Hdma::default(),
+            cycles: 0,
+            illegal_opcode_policy: IllegalOpcodePolicy::LockUp,
+            watchpoints: Watchpoints::default(),
+            stopped: None,
+            locked: false,
+            events: VecDeque::new(),
+            wram: vec![0; wram_banks * WRAM_BANK_SIZE],
+            wram_bank: 1,
+            double_speed: false,
+            prepare_speed_switch: false,
+            boot_rom: false
+        }
+    }
+
+    /**
+     * Builds a Game Boy of the model the cartridge was made for.
+     */
+    pub fn for_cartridge(cartridge: Cartridge) -> GameBoy {
+        let mut gb = GameBoy::with_model(Model::detect(&cartridge));
+        gb.insert(cartridge);
+        gb
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        self.model == Model::Cgb
+    }
+
+    /**
+     * Sets the registers to the values the boot rom leaves behind and starts at the cartridge entry point.
+     */
+    pub fn skip_boot(&mut self) {
+        let (af, bc, de, hl) = match self.model {
+            Model::Dmg => (0x01B0, 0x0013, 0x00D8, 0x014D),
+            Model::Mgb => (0xFFB0, 0x0013, 0x00D8, 0x014D),
+            Model::Cgb => (0x1180, 0x0000, 0xFF56, 0x000D),
+            Model::Sgb => (0x0100, 0x0014, 0x0000, 0xC060)
+        };
+        self.register.a = (af >> 8) as u8;
+        self.register.f = Flags::from_bits_truncate(af as u8);
+        self.register.b = (bc >> 8) as u8;
+        self.register.c = bc as u8;
+        self.register.d = (de >> 8) as u8;
+        self.register.e = de as u8;
+        self.register.h = (hl >> 8) as u8;
+        self.register.l = hl as u8;
+        self.register.sp = 0xFFFE;
+        self.register.pc = 0x0100;
+        self.write(ppu::LCDC, 0x91);
+        self.write(ppu::BGP, 0xFC);
+        self.boot_rom = false;
+    }
+
+    pub fn is_double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    /**
+     * Executed by STOP, switches the CPU speed when it was prepared through KEY1.
+     */
+    pub fn stop(&mut self) {
+        if self.prepare_speed_switch {
+            self.double_speed = !self.double_speed;
+            self.prepare_speed_switch = false;
+        }
+    }
+
+    pub fn load_firmware(&mut self, firmware: &[u8]) {
+        for (i, byte) in firmware.iter().enumerate() {
+            self.ram[i] = *byte;
+        }
+        self.boot_rom = true;
+    }
+
+    /**
+     * Whether the boot rom is still mapped over the cartridge.
+     */
+    pub fn is_booting(&self) -> bool {
+        self.boot_rom
+    }
+
+    pub fn insert(&mut self, cartridge: Cartridge) {
+        self.cartridge = Some(cartridge);
+    }
+
+    /**
+     * Frames since power on, see `Ppu::frame`.
+     */
+    pub fn frame(&self) -> u64 {
+        self.ppu.frame()
+    }
+
+    /**
+     * The last frame as shown on the display: the 256x224 picture with SGB colors and border
+     * on a Super Game Boy, the 160x144 LCD screen otherwise.
+     */
+    pub fn screen(&self) -> Screen<'_> {
+        match self.sgb {
+            Some(ref sgb) => Screen { width: sgb::WIDTH, height: sgb::HEIGHT, colors: sgb.framebuffer() },
+            None => Screen { width: ppu::WIDTH, height: ppu::HEIGHT, colors: self.ppu.screen() }
+        }
+    }
+
+    pub fn save_screenshot(&self, path: &Path) -> io::Result<()> {
+        self.screen().save(path, self.ppu.color_correction)
+    }
+
+    /**
+     * All work RAM banks, on the CGB including the ones SVBK doesn't map right now.
+     */
+    pub fn wram(&self) -> &[u8] {
+        &self.wram
+    }
+
+    /**
+     * High RAM at 0xFF80-0xFFFE.
+     */
+    pub fn hram(&self) -> &[u8] {
+        &self.ram[0xFF80..0xFFFF]
+    }
+
+    /**
+     * During OAM DMA the CPU can only access HRAM and the I/O registers.
+     */
+    pub fn read(&self, addr: u16) -> u8 {
+        let value = if self.dma.is_active() && addr < 0xFF00 {
+            0xFF
+        }else {
+            self.read_bus(addr)
+        };
+        if self.watchpoints.is_watching() {
+            self.watchpoints.read(addr, value);
+        }
+        value
+    }
+
+    /**
+     * Reads like the CPU without OAM DMA blocking the bus or watchpoints noticing, e.g. for memory viewers.
+     */
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.read_bus(addr)
+    }
+
+    fn read_bus(&self, addr: u16) -> u8 {
+        match (addr, &self.cartridge) {
+            (0x0000..=0x00FF, _) if self.boot_rom => self.ram[addr as usize],
+            (0x0000..=0x7FFF, Some(cartridge)) => cartridge.read_rom(addr),
+            (0xA000..=0xBFFF, Some(cartridge)) => cartridge.read_ram(addr),
+            _ => self.read_io(addr)
+        }
+    }
+
+    fn read_io(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0x9FFF | 0xFE00..=0xFE9F | ppu::LCDC..=ppu::LYC | ppu::BGP..=ppu::WX | ppu::VBK | palette::BCPS..=palette::OCPD => self.ppu.read(addr),
+            0xC000..=0xFDFF => self.wram[self.wram_offset(addr)],
+            KEY1 if self.is_cgb() => {
+                let speed = if self.double_speed { 0b1000_0000 } else { 0 };
+                let prepare = if self.prepare_speed_switch { 0b0000_0001 } else { 0 };
+                0b0111_1110 | speed | prepare
+            },
+            SVBK if self.is_cgb() => 0b1111_1000 | self.wram_bank as u8,
+            hdma::HDMA1..=hdma::HDMA5 if self.is_cgb() => self.hdma.read(addr),
+            KEY1 | SVBK | hdma::HDMA1..=hdma::HDMA5 => 0xFF,
+            dma::DMA => self.dma.read(),
+            joypad::P1 => match self.sgb {
+                Some(ref sgb) => sgb.read_joypad(self.joypad.read()),
+                None => self.joypad.read()
+            },
+            serial::SB => self.serial.read_data(),
+            serial::SC => self.serial.read_control(),
+            _ => self.ram[addr as usize]
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, value: u8) {
+        if self.dma.is_active() && addr < 0xFF00 {
+            return;
+        }
+        if self.watchpoints.is_watching() {
+            let old = self.read_bus(addr);
+            self.watchpoints.write(addr, old, value);
+        }
+        self.write_bus(addr, value)
+    }
+
+    fn write_bus(&mut self, addr: u16, value: u8) {
+        match (addr, &mut self.cartridge) {
+            (0x0000..=0x7FFF, Some(cartridge)) => cartridge.write_rom(addr, value),
+            (0xA000..=0xBFFF, Some(cartridge)) => cartridge.write_ram(addr, value),
+            _ => self.write_io(addr, value)
+        }
+    }
+
+    fn write_io(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x8000..=0x9FFF | 0xFE00..=0xFE9F | ppu::LCDC..=ppu::LYC | ppu::BGP..=ppu::WX | ppu::VBK | palette::BCPS..=palette::OCPD => {
+                let interrupts = self.ppu.write(addr, value);
+                self.request_interrupt(interrupts);
+            },
+            0xC000..=0xFDFF => {
+                let offset = self.wram_offset(addr);
+                self.wram[offset] = value;
+            },
+            KEY1 if self.is_cgb() => self.prepare_speed_switch = value & 0b0000_0001 != 0,
+            SVBK if self.is_cgb() => self.wram_bank = ((value & 0b0000_0111) as usize).max(1),
+            hdma::HDMA1..=hdma::HDMA5 if self.is_cgb() => {
+                let blocks = self.hdma.write(addr, value);
+                for _ in 0..blocks {
+                    self.transfer_block();
+                }
+                // with the LCD off there is no HBlank, the first block is copied right away
+                if addr == hdma::HDMA5 && self.hdma.is_active() && self.ppu.read(ppu::LCDC) & 0x80 == 0 {
+                    self.transfer_block();
+                }
+            },
+            KEY1 | SVBK | hdma::HDMA1..=hdma::HDMA5 => {},
+            BOOT => self.boot_rom = false,
+            dma::DMA => self.dma.write(value),
+            joypad::P1 => {
+                if let Some(ref mut sgb) = self.sgb {
+                    sgb.write_joypad(value);
+                }
+                if self.joypad.write(value) {
+                    self.request_interrupt(Interrupt::JOYPAD);
+                }
+            },
+            serial::SB => self.serial.write_data(value),
+            serial::SC => self.serial.write_control(value),
+            _ => self.ram[addr as usize] = value
+        }
+    }
+
+    /**
+     * 0xC000-0xCFFF is always bank 0, 0xE000-0xFDFF mirrors 0xC000-0xDDFF.
+     */
+    fn wram_offset(&self, addr: u16) -> usize {
+        let addr = (addr as usize - 0xC000) % 0x2000;
+        if addr < WRAM_BANK_SIZE {
+            addr
+        }else {
+            self.wram_bank * WRAM_BANK_SIZE + addr - WRAM_BANK_SIZE
+        }
+    }
+
+    /**
+     * Copies the next VRAM DMA block while the CPU is halted.
+     */
+    fn transfer_block(&mut self) {
+        let (source, destination) = self.hdma.next_block();
+        for i in 0..hdma::BLOCK_SIZE {
+            let value = self.read_bus(source.wrapping_add(i));
+            self.write_bus(destination + i, value);
+        }
+        // the transfer takes the same time in double speed mode
+        self.cycles += if self.double_speed { HDMA_BLOCK_CYCLES * 2 } else { HDMA_BLOCK_CYCLES };
+    }
+
+    pub fn press(&mut self, button: Button) {
+        if self.joypad.press(button) {
+            self.request_interrupt(Interrupt::JOYPAD);
+        }
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.joypad.release(button);
+    }
+
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        self.ram[interrupt::IF as usize] |= interrupt.bits();
+    }
+
+    pub fn push_to_stack(&mut self, addr: u16) {
+        let mut data = vec![];
+        data.write_u16::<LittleEndian>(addr).unwrap();
+        let sp = self.register.sp;
+        self.write(sp, data[0]);
+        self.write(sp - 1, data[1]);
+
+        self.register.sp -= 2;
+    }
+
+    pub fn pop_from_stack(&mut self) -> u16 {
+        let sp = self.register.sp;
+        let b1 = self.read(sp + 2);
+        let b2 = self.read(sp + 1);
+
+        self.register.sp += 2;
+
+        LittleEndian::read_u16(&[b1, b2])
+    }
+
+    /**
+     * Fetches and executes the next instruction.
+     *
+     * Returns the executed instruction or why it couldn't be executed.
+     */
+    pub fn step(&mut self) -> Result<Command, EmulationError> {
+        if let Some(ref err) = self.stopped {
+            return Err(err.clone());
+        }
+        let instruction = self.fetch()?;
+        if let Command::IllegalOpcode(illegal) = instruction {
+            let err = EmulationError::IllegalOpcode { opcode: illegal.0, address: self.register.pc };
+            match self.illegal_opcode_policy {
+                IllegalOpcodePolicy::LockUp => {},
+                IllegalOpcodePolicy::Break => return Err(err),
+                IllegalOpcodePolicy::Abort => {
+                    self.stopped = Some(err.clone());
+                    return Err(err);
+                }
+            }
+        }
+        self.exec(instruction);
+        Ok(instruction)
+    }
+
+    /**
+     * Executed by illegal opcodes, the CPU stays on the opcode from now on.
+     */
+    pub fn lock_up(&mut self, opcode: u8) {
+        if !self.locked {
+            self.locked = true;
+            let address = self.register.pc;
+            self.raise(Event::LockedUp { opcode, address });
+        }
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    pub fn raise(&mut self, event: Event) {
+        self.events.push_back(event);
+    }
+
+    /**
+     * Returns the oldest event which wasn't polled yet.
+     */
+    pub fn poll_event(&mut self) -> Option<Event> {
+        self.events.pop_front()
+    }
+
+    /**
+     * Decodes the instruction at the program counter without executing it.
+     */
+    pub fn fetch(&self) -> Result<Command, EmulationError> {
+        let pc = self.register.pc;
+        if !self.is_mapped(pc) {
+            return Err(EmulationError::UnmappedAccess(pc));
+        }
+        let opcode = self.read(pc);
+        let operands = [self.read(pc.wrapping_add(1)), self.read(pc.wrapping_add(2))];
+        instructions::parse_command(opcode, &operands).ok_or_else(|| {
+            let opcode = if opcode == 0xCB { 0xCB00 | operands[0] as u16 } else { opcode as u16 };
+            EmulationError::UnimplementedOpcode { opcode, address: pc }
+        })
+    }
+
+    /**
+     * Without a cartridge only the boot rom and the internal memory can be executed.
+     */
+    fn is_mapped(&self, addr: u16) -> bool {
+        match addr {
+            0x0000..=0x00FF if self.boot_rom => true,
+            0x0000..=0x7FFF | 0xA000..=0xBFFF => self.cartridge.is_some(),
+            0xFEA0..=0xFEFF => false,
+            _ => true
+        }
+    }
+
+    /**
+     * Executes the instruction and advances the peripherals by the cycles it took.
+     */
+    pub fn exec(&mut self, instruction: Command) {
+        // the CPU only leaves the lock up when something else moved the PC, e.g. a debugger
+        self.locked &= matches!(instruction, Command::IllegalOpcode(_));
+        let before = self.cycles;
+        let pc = self.register.pc;
+        self.watchpoints.begin(pc);
+        instruction.exec(self);
+        self.watchpoints.end();
+        let cycles = self.cycles - before;
+        self.advance(cycles);
+    }
+
+    /**
+     * Advances the peripherals by the given amount of CPU clock cycles.
+     */
+    fn advance(&mut self, cycles: u64) {
+        let mut cycles = cycles as u32;
+        let source = self.dma.source();
+        for offset in self.dma.step(cycles) {
+            let value = self.read_bus(source + offset);
+            self.write_bus(0xFE00 + offset, value);
+        }
+        // the serial clock is derived from DIV and doubles its speed with the CPU
+        if self.serial.step(cycles) {
+            self.request_interrupt(Interrupt::SERIAL);
+        }
+        if self.double_speed {
+            // the PPU keeps running at normal speed
+            cycles /= 2;
+        }
+        let hblank = self.ppu.mode() == Mode::HBlank;
+        let interrupts = self.ppu.step(cycles);
+        self.request_interrupt(interrupts);
+        if interrupts.contains(Interrupt::VBLANK) {
+            if let Some(ref mut sgb) = self.sgb {
+                sgb.vblank(&self.ppu);
+            }
+        }
+        if !hblank && self.ppu.mode() == Mode::HBlank && self.hdma.is_active() {
+            let before = self.cycles;
+            self.transfer_block();
+            let cycles = self.cycles - before;
+            self.advance(cycles);
+        }
+    }
+}
+
+/**
+ * Yields the decoded instructions, ends at the first instruction which can't be executed.
+ */
+impl Iterator for GameBoy {
+    type Item = Command;
+
+    fn next(&mut self) -> Option<<Self as Iterator>::Item> {
+        self.fetch().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_request_the_joypad_interrupt_on_press() {
+        let mut gb = GameBoy::new();
+        gb.write(joypad::P1, 0x10);
+        gb.press(Button::A);
+        assert_eq!(gb.read(interrupt::IF), Interrupt::JOYPAD.bits());
+        assert_eq!(gb.read(joypad::P1), 0xDE);
+    }
+
+    #[test]
+    fn it_should_switch_wram_banks_on_cgb() {
+        let mut gb = GameBoy::with_model(Model::Cgb);
+        gb.write(0xD000, 0x11);
+        gb.write(SVBK, 2);
+        assert_eq!(gb.read(0xD000), 0x00);
+        gb.write(0xD000, 0x22);
+        gb.write(SVBK, 0);
+        assert_eq!(gb.read(SVBK), 0xF9);
+        assert_eq!(gb.read(0xD000), 0x11);
+    }
+
+    #[test]
+    fn it_should_ignore_svbk_on_dmg() {
+        let mut gb = GameBoy::new();
+        gb.write(0xD000, 0x11);
+        gb.write(SVBK, 2);
+        assert_eq!(gb.read(0xD000), 0x11);
+        assert_eq!(gb.read(SVBK), 0xFF);
+    }
+
+    #[test]
+    fn it_should_mirror_wram_in_echo_ram() {
+        let mut gb = GameBoy::new();
+        gb.write(0xC123, 0x42);
+        assert_eq!(gb.read(0xE123), 0x42);
+    }
+
+    #[test]
+    fn it_should_switch_speed_when_prepared() {
+        let mut gb = GameBoy::with_model(Model::Cgb);
+        gb.stop();
+        assert!(!gb.is_double_speed());
+        gb.write(KEY1, 0x01);
+        assert_eq!(gb.read(KEY1), 0x7F);
+        gb.stop();
+        assert!(gb.is_double_speed());
+        assert_eq!(gb.read(KEY1), 0xFE);
+    }
+
+    #[test]
+    fn it_should_use_cgb_register_values_after_boot() {
+        let mut gb = GameBoy::with_model(Model::Cgb);
+        gb.skip_boot();
+        assert_eq!(gb.register.a, 0x11);
+        assert_eq!(gb.register.pc, 0x0100);
+        assert_eq!(gb.register.sp, 0xFFFE);
+    }
+
+    #[test]
+    fn it_should_use_mgb_register_values_after_boot() {
+        let mut gb = GameBoy::with_model(Model::Mgb);
+        gb.skip_boot();
+        assert_eq!(gb.register.a, 0xFF);
+        assert!(!gb.is_cgb());
+    }
+
+    fn hdma(gb: &mut GameBoy, source: u16, destination: u16) {
+        for i in 0..0x40 {
+            gb.write(source + i, i as u8);
+        }
+        gb.write(hdma::HDMA1, (source >> 8) as u8);
+        gb.write(hdma::HDMA2, source as u8);
+        gb.write(hdma::HDMA3, (destination >> 8) as u8);
+        gb.write(hdma::HDMA4, destination as u8);
+    }
+
+    #[test]
+    fn it_should_copy_general_purpose_dma_at_once() {
+        let mut gb = GameBoy::with_model(Model::Cgb);
+        gb.write(ppu::LCDC, 0x80);
+        hdma(&mut gb, 0xC000, 0x8100);
+        gb.write(hdma::HDMA5, 0x01);
+        assert_eq!(gb.read(0x8100), 0x00);
+        assert_eq!(gb.read(0x811F), 0x1F);
+        assert_eq!(gb.read(0x8120), 0x00);
+        assert_eq!(gb.cycles, 2 * HDMA_BLOCK_CYCLES);
+        assert_eq!(gb.read(hdma::HDMA5), 0xFF);
+    }
+
+    #[test]
+    fn it_should_copy_one_block_per_hblank() {
+        let mut gb = GameBoy::with_model(Model::Cgb);
+        gb.write(ppu::LCDC, 0x80);
+        hdma(&mut gb, 0xC000, 0x8000);
+        gb.write(hdma::HDMA5, 0x82);
+        assert_eq!(gb.read(0x8001), 0x00);
+        let nop = instructions::parse_command(0x00, &[0, 0]).unwrap();
+        while gb.ppu.mode() != Mode::HBlank {
+            gb.exec(nop);
+        }
+        assert_eq!(gb.read(0x8001), 0x01);
+        assert_eq!(gb.read(0x8011), 0x00);
+        assert_eq!(gb.read(hdma::HDMA5), 0x01);
+
+        // cancel before the next line
+        gb.write(hdma::HDMA5, 0x00);
+        assert_eq!(gb.read(hdma::HDMA5), 0x81);
+        for _ in 0..200 {
+            gb.exec(nop);
+        }
+        assert_eq!(gb.read(0x8011), 0x00);
+    }
+
+    #[test]
+    fn it_should_copy_the_first_hblank_block_while_the_lcd_is_off() {
+        let mut gb = GameBoy::with_model(Model::Cgb);
+        hdma(&mut gb, 0xC000, 0x8000);
+        gb.write(hdma::HDMA5, 0x81);
+        assert_eq!(gb.read(0x800F), 0x0F);
+        assert_eq!(gb.read(0x8010), 0x00);
+        assert_eq!(gb.read(hdma::HDMA5), 0x00);
+    }
+
+    #[test]
+    fn it_should_only_access_hram_during_oam_dma() {
+        let mut gb = GameBoy::new();
+        gb.write(0xC000, 0x42);
+        gb.write(0xFF80, 0x24);
+        gb.write(dma::DMA, 0xC0);
+        assert_eq!(gb.read(0xC000), 0xFF);
+        gb.write(0xC001, 0x11);
+        assert_eq!(gb.read(0xFF80), 0x24);
+        assert_eq!(gb.read(dma::DMA), 0xC0);
+    }
+
+    #[test]
+    fn it_should_peek_without_side_effects() {
+        use debugger::watchpoint::{Access, Watchpoint};
+        let mut gb = GameBoy::with_model(Model::Cgb);
+        gb.write(0xC000, 0x42);
+        gb.watchpoints.add(Watchpoint::new(0xC000..=0xC000, Access::Read));
+        gb.write(dma::DMA, 0xC0);
+        assert_eq!(gb.peek(0xC000), 0x42);
+        assert_eq!(gb.watchpoints.take_hit(), None);
+    }
+
+    #[test]
+    fn it_should_copy_to_oam_in_160_m_cycles() {
+        let mut gb = GameBoy::new();
+        for i in 0..0xA0 {
+            gb.write(0xC000 + i, i as u8);
+        }
+        gb.write(dma::DMA, 0xC0);
+        let nop = instructions::parse_command(0x00, &[0, 0]).unwrap();
+        for _ in 0..159 {
+            gb.exec(nop);
+        }
+        assert!(gb.dma.is_active());
+        assert_eq!(gb.ppu.read(0xFE9E), 0x9E);
+        assert_eq!(gb.ppu.read(0xFE9F), 0x00);
+        gb.exec(nop);
+        assert!(!gb.dma.is_active());
+        assert_eq!(gb.read(0xFE9F), 0x9F);
+        assert_eq!(gb.read(0xC000), 0x00);
+    }
+
+    #[test]
+    fn it_should_select_the_model_of_the_cartridge() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x146] = 0x03;
+        rom[0x14B] = 0x33;
+        let gb = GameBoy::for_cartridge(Cartridge::new(rom.clone()).unwrap());
+        assert_eq!(gb.model, Model::Sgb);
+        assert!(gb.sgb.is_some());
+        rom[0x143] = 0x80;
+        let gb = GameBoy::for_cartridge(Cartridge::new(rom).unwrap());
+        assert_eq!(gb.model, Model::Cgb);
+        assert!(gb.sgb.is_none());
+    }
+
+    #[test]
+    fn it_should_show_the_sgb_framebuffer_on_a_super_game_boy() {
+        let gb = GameBoy::with_model(Model::Sgb);
+        let screen = gb.screen();
+        assert_eq!((screen.width, screen.height), (sgb::WIDTH, sgb::HEIGHT));
+        assert_eq!(screen.colors, gb.sgb.as_ref().unwrap().framebuffer());
+        let gb = GameBoy::with_model(Model::Dmg);
+        let screen = gb.screen();
+        assert_eq!((screen.width, screen.height), (ppu::WIDTH, ppu::HEIGHT));
+        assert_eq!(screen.colors, gb.ppu.screen());
+    }
+
+    fn illegal(policy: IllegalOpcodePolicy) -> GameBoy {
+        let mut gb = GameBoy::new();
+        gb.load_firmware(&[0x00, 0xDD]);
+        gb.illegal_opcode_policy = policy;
+        gb.write(ppu::LCDC, 0x80);
+        gb.step().unwrap();
+        gb
+    }
+
+    #[test]
+    fn it_should_lock_up_on_illegal_opcodes() {
+        let mut gb = illegal(IllegalOpcodePolicy::LockUp);
+        assert_eq!(format!("{:?}", gb.step().unwrap()), "ILLEGAL 0xDD");
+        assert!(gb.is_locked());
+        assert_eq!(gb.poll_event(), Some(Event::LockedUp { opcode: 0xDD, address: 0x0001 }));
+        for _ in 0..ppu::FRAME_CYCLES / 4 {
+            gb.step().unwrap();
+        }
+        assert_eq!(gb.register.pc, 0x0001);
+        assert_eq!(gb.frame(), 1);
+        assert_eq!(gb.poll_event(), None);
+        assert!(gb.next().is_some());
+    }
+
+    #[test]
+    fn it_should_break_on_illegal_opcodes() {
+        let mut gb = illegal(IllegalOpcodePolicy::Break);
+        assert_eq!(gb.step().unwrap_err(), EmulationError::IllegalOpcode { opcode: 0xDD, address: 0x0001 });
+        gb.register.pc = 0x0000;
+        assert!(gb.step().is_ok());
+    }
+
+    #[test]
+    fn it_should_abort_on_illegal_opcodes() {
+        let mut gb = illegal(IllegalOpcodePolicy::Abort);
+        assert!(gb.step().is_err());
+        gb.register.pc = 0x0000;
+        assert!(gb.step().is_err());
+    }
+
+    #[test]
+    fn it_should_report_unimplemented_opcodes() {
+        let mut gb = GameBoy::new();
+        gb.load_firmware(&[0xCB, 0x00]);
+        assert_eq!(gb.step().unwrap_err(), EmulationError::UnimplementedOpcode { opcode: 0xCB00, address: 0x0000 });
+    }
+
+    #[test]
+    fn it_should_not_execute_unmapped_memory() {
+        let mut gb = GameBoy::new();
+        gb.register.pc = 0x0100;
+        assert_eq!(gb.step().unwrap_err(), EmulationError::UnmappedAccess(0x0100));
+    }
+
+    #[test]
+    fn it_should_read_released_buttons_as_high() {
+        let mut gb = GameBoy::new();
+        gb.write(joypad::P1, 0x20);
+        gb.press(Button::Up);
+        gb.release(Button::Up);
+        assert_eq!(gb.read(joypad::P1), 0xEF);
+    }
+}
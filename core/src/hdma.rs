@@ -0,0 +1,146 @@
+pub const HDMA1: u16 = 0xFF51;
+pub const HDMA2: u16 = 0xFF52;
+pub const HDMA3: u16 = 0xFF53;
+pub const HDMA4: u16 = 0xFF54;
+pub const HDMA5: u16 = 0xFF55;
+
+/// Bytes copied per block, HBlank transfers copy one block per line
+pub const BLOCK_SIZE: u16 = 0x10;
+
+const HBLANK_MODE: u8 = 0b1000_0000;
+
+/**
+ * CGB VRAM DMA (HDMA1-HDMA5)
+ *
+ * HDMA1/2 hold the source and HDMA3/4 the destination in VRAM, both aligned to 16 bytes.
+ * Writing HDMA5 starts a transfer of `(value & 0x7F) + 1` blocks, either all at once
+ * (general purpose DMA) or one block per HBlank when bit 7 is set.
+ * Reading HDMA5 returns the remaining blocks minus one, bit 7 is set when no transfer is active.
+ */
+pub struct Hdma {
+    source: u16,
+    destination: u16,
+    remaining: u8,
+    active: bool
+}
+
+impl Default for Hdma {
+    fn default() -> Hdma {
+        Hdma {
+            source: 0,
+            destination: 0,
+            remaining: 0x7F,
+            active: false
+        }
+    }
+}
+
+impl Hdma {
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            HDMA5 if self.active => self.remaining,
+            HDMA5 => HBLANK_MODE | self.remaining,
+            _ => 0xFF
+        }
+    }
+
+    /**
+     * Returns the number of blocks which have to be copied immediately.
+     */
+    pub fn write(&mut self, addr: u16, value: u8) -> u8 {
+        match addr {
+            HDMA1 => self.source = (self.source & 0x00FF) | (value as u16) << 8,
+            HDMA2 => self.source = (self.source & 0xFF00) | (value & 0xF0) as u16,
+            HDMA3 => self.destination = (self.destination & 0x00FF) | ((value & 0x1F) as u16) << 8,
+            HDMA4 => self.destination = (self.destination & 0xFF00) | (value & 0xF0) as u16,
+            HDMA5 if self.active && value & HBLANK_MODE == 0 => self.active = false,
+            HDMA5 => {
+                self.remaining = value & 0x7F;
+                if value & HBLANK_MODE == 0 {
+                    return self.remaining + 1;
+                }
+                self.active = true;
+            },
+            _ => {}
+        }
+        0
+    }
+
+    /**
+     * True while an HBlank transfer waits for the next HBlank.
+     */
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /**
+     * Returns the source and VRAM destination of the next block and advances the transfer.
+     */
+    pub fn next_block(&mut self) -> (u16, u16) {
+        let block = (self.source, 0x8000 | self.destination);
+        self.source = self.source.wrapping_add(BLOCK_SIZE);
+        self.destination = (self.destination + BLOCK_SIZE) & 0x1FF0;
+        self.remaining = self.remaining.wrapping_sub(1) & 0x7F;
+        if self.remaining == 0x7F {
+            self.active = false;
+        }
+        block
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hdma() -> Hdma {
+        let mut hdma = Hdma::default();
+        hdma.write(HDMA1, 0xC1);
+        hdma.write(HDMA2, 0x2F);
+        hdma.write(HDMA3, 0xE0);
+        hdma.write(HDMA4, 0x48);
+        hdma
+    }
+
+    #[test]
+    fn it_should_align_source_and_destination() {
+        let mut hdma = hdma();
+        hdma.write(HDMA5, 0x00);
+        assert_eq!(hdma.next_block(), (0xC120, 0x8040));
+    }
+
+    #[test]
+    fn it_should_copy_general_purpose_transfers_at_once() {
+        let mut hdma = hdma();
+        assert_eq!(hdma.write(HDMA5, 0x03), 4);
+        assert!(!hdma.is_active());
+    }
+
+    #[test]
+    fn it_should_count_down_hblank_transfers() {
+        let mut hdma = hdma();
+        assert_eq!(hdma.write(HDMA5, 0x81), 0);
+        assert_eq!(hdma.read(HDMA5), 0x01);
+        hdma.next_block();
+        assert_eq!(hdma.read(HDMA5), 0x00);
+        assert_eq!(hdma.next_block(), (0xC130, 0x8050));
+        assert!(!hdma.is_active());
+        assert_eq!(hdma.read(HDMA5), 0xFF);
+    }
+
+    #[test]
+    fn it_should_cancel_hblank_transfers() {
+        let mut hdma = hdma();
+        hdma.write(HDMA5, 0x85);
+        hdma.next_block();
+        assert_eq!(hdma.write(HDMA5, 0x00), 0);
+        assert!(!hdma.is_active());
+        assert_eq!(hdma.read(HDMA5), 0x84);
+    }
+
+    #[test]
+    fn it_should_not_read_source_and_destination() {
+        let hdma = hdma();
+        assert_eq!(hdma.read(HDMA1), 0xFF);
+        assert_eq!(hdma.read(HDMA4), 0xFF);
+    }
+}
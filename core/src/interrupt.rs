@@ -0,0 +1,13 @@
+pub const IF: u16 = 0xFF0F;
+pub const IE: u16 = 0xFFFF;
+
+bitflags! {
+    #[derive(Default)]
+    pub struct Interrupt: u8 {
+        const VBLANK = 0b0000_0001;
+        const LCD_STAT = 0b0000_0010;
+        const TIMER = 0b0000_0100;
+        const SERIAL = 0b0000_1000;
+        const JOYPAD = 0b0001_0000;
+    }
+}
@@ -0,0 +1,209 @@
+pub const P1: u16 = 0xFF00;
+
+const SELECT_DIRECTIONS: u8 = 0b0001_0000;
+const SELECT_BUTTONS: u8 = 0b0010_0000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start
+    ];
+
+    /**
+     * Bit of the button in `Joypad::state`.
+     */
+    pub fn mask(self) -> u8 {
+        if self.is_direction() {
+            self.line()
+        }else {
+            self.line() << 4
+        }
+    }
+
+    fn is_direction(self) -> bool {
+        matches!(self, Button::Right | Button::Left | Button::Up | Button::Down)
+    }
+
+    fn line(self) -> u8 {
+        match self {
+            Button::Right | Button::A => 0b0001,
+            Button::Left | Button::B => 0b0010,
+            Button::Up | Button::Select => 0b0100,
+            Button::Down | Button::Start => 0b1000
+        }
+    }
+}
+
+/**
+ * Joypad (P1 0xFF00)
+ *
+ * Bit 5 and 4 select the buttons or directions (active low),
+ * bit 3-0 read the input lines of the selected group (active low).
+ */
+pub struct Joypad {
+    select: u8,
+    directions: u8,
+    buttons: u8
+}
+
+impl Default for Joypad {
+    fn default() -> Joypad {
+        Joypad {
+            select: SELECT_DIRECTIONS | SELECT_BUTTONS,
+            directions: 0,
+            buttons: 0
+        }
+    }
+}
+
+impl Joypad {
+    pub fn read(&self) -> u8 {
+        0b1100_0000 | self.select | (!self.lines() & 0x0F)
+    }
+
+    /**
+     * Returns true when a line went from high to low and the joypad interrupt should be requested.
+     */
+    pub fn write(&mut self, value: u8) -> bool {
+        self.update(|joypad| joypad.select = value & (SELECT_DIRECTIONS | SELECT_BUTTONS))
+    }
+
+    /**
+     * Returns true when the joypad interrupt should be requested.
+     */
+    pub fn press(&mut self, button: Button) -> bool {
+        self.update(|joypad| *joypad.group(button) |= button.line())
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.update(|joypad| *joypad.group(button) &= !button.line());
+    }
+
+    pub fn is_pressed(&self, button: Button) -> bool {
+        let group = if button.is_direction() { self.directions } else { self.buttons };
+        group & button.line() != 0
+    }
+
+    /**
+     * Pressed directions in the lower and pressed buttons in the upper nibble.
+     */
+    pub fn state(&self) -> u8 {
+        self.directions | self.buttons << 4
+    }
+
+    fn group(&mut self, button: Button) -> &mut u8 {
+        if button.is_direction() {
+            &mut self.directions
+        }else {
+            &mut self.buttons
+        }
+    }
+
+    /**
+     * Pressed lines of the selected groups, active high.
+     */
+    fn lines(&self) -> u8 {
+        let mut lines = 0;
+        if self.select & SELECT_DIRECTIONS == 0 {
+            lines |= self.directions;
+        }
+        if self.select & SELECT_BUTTONS == 0 {
+            lines |= self.buttons;
+        }
+        lines
+    }
+
+    fn update<F: FnOnce(&mut Joypad)>(&mut self, change: F) -> bool {
+        let before = self.lines();
+        change(self);
+        self.lines() & !before != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_read_all_lines_high_by_default() {
+        let joypad = Joypad::default();
+        assert_eq!(joypad.read(), 0xFF);
+    }
+
+    #[test]
+    fn it_should_read_pressed_directions() {
+        let mut joypad = Joypad::default();
+        joypad.write(0x20);
+        joypad.press(Button::Down);
+        joypad.press(Button::A);
+        assert_eq!(joypad.read(), 0xE7);
+    }
+
+    #[test]
+    fn it_should_read_pressed_buttons() {
+        let mut joypad = Joypad::default();
+        joypad.write(0x10);
+        joypad.press(Button::Down);
+        joypad.press(Button::A);
+        assert_eq!(joypad.read(), 0xDE);
+    }
+
+    #[test]
+    fn it_should_release_buttons() {
+        let mut joypad = Joypad::default();
+        joypad.write(0x10);
+        joypad.press(Button::Start);
+        joypad.release(Button::Start);
+        assert_eq!(joypad.read(), 0xDF);
+        assert!(!joypad.is_pressed(Button::Start));
+    }
+
+    #[test]
+    fn it_should_interrupt_when_a_selected_line_goes_low() {
+        let mut joypad = Joypad::default();
+        joypad.write(0x10);
+        assert!(joypad.press(Button::B));
+    }
+
+    #[test]
+    fn it_should_not_interrupt_for_unselected_groups() {
+        let mut joypad = Joypad::default();
+        joypad.write(0x10);
+        assert!(!joypad.press(Button::Left));
+    }
+
+    #[test]
+    fn it_should_interrupt_when_selecting_a_pressed_group() {
+        let mut joypad = Joypad::default();
+        joypad.press(Button::Left);
+        assert!(joypad.write(0x20));
+        assert!(!joypad.write(0x20));
+    }
+
+    #[test]
+    fn it_should_combine_all_pressed_buttons_into_the_state() {
+        let mut joypad = Joypad::default();
+        joypad.press(Button::Up);
+        joypad.press(Button::Start);
+        assert_eq!(joypad.state(), Button::Up.mask() | Button::Start.mask());
+        assert_eq!(joypad.state(), 0x84);
+    }
+}
@@ -0,0 +1,36 @@
+//! Game Boy emulator core without any frontend.
+//!
+//! The types most frontends need are re-exported here, everything else is reachable through the modules.
+
+extern crate byteorder;
+#[macro_use]
+extern crate bitflags;
+extern crate png;
+extern crate crc32fast;
+#[macro_use]
+extern crate log;
+
+pub mod cartridge;
+pub mod cpu;
+pub mod debugger;
+pub mod disasm;
+pub mod dma;
+pub mod error;
+pub mod gameboy;
+pub mod hdma;
+pub mod interrupt;
+pub mod joypad;
+pub mod link;
+pub mod movie;
+pub mod palette;
+pub mod ppu;
+pub mod printer;
+pub mod serial;
+pub mod sgb;
+pub mod trace;
+
+pub use cartridge::Cartridge;
+pub use debugger::Debugger;
+pub use error::EmulationError;
+pub use gameboy::{Event, GameBoy, IllegalOpcodePolicy, Model};
+pub use joypad::Button;
@@ -0,0 +1,195 @@
+use gameboy::GameBoy;
+use interrupt::Interrupt;
+
+/**
+ * Two Game Boys connected by a link cable inside a single process.
+ *
+ * The machine which is behind in clock cycles runs the next instruction, so
+ * both stay within one instruction of each other. Afterwards every clock pulse
+ * of the master shifts one bit in each direction. This makes link sessions
+ * fully deterministic and replayable.
+ *
+ * The slave always shifts in the bits of the master, but only finishes
+ * its own transfer (and requests the serial interrupt) when it was waiting
+ * for one on the external clock.
+ */
+pub struct LinkedGameBoys {
+    pub left: GameBoy,
+    pub right: GameBoy,
+    steps: u64
+}
+
+impl LinkedGameBoys {
+    pub fn new(mut left: GameBoy, mut right: GameBoy) -> LinkedGameBoys {
+        left.serial.connect_link();
+        right.serial.connect_link();
+        LinkedGameBoys {
+            left,
+            right,
+            steps: 0
+        }
+    }
+
+    /**
+     * Runs one instruction on the Game Boy which is behind in clock cycles, the left one
+     * on a tie, and services the link afterwards.
+     *
+     * Returns false as soon as one of them stopped executing.
+     */
+    pub fn step(&mut self) -> bool {
+        let result = if self.left.cycles <= self.right.cycles {
+            self.left.step()
+        }else {
+            self.right.step()
+        };
+        self.exchange();
+        self.steps += 1;
+        result.is_ok()
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /**
+     * Disconnects the cable and hands back both Game Boys.
+     */
+    pub fn split(mut self) -> (GameBoy, GameBoy) {
+        self.left.serial.disconnect();
+        self.right.serial.disconnect();
+        (self.left, self.right)
+    }
+
+    fn exchange(&mut self) {
+        if self.left.serial.is_master() {
+            transfer(&mut self.left, &mut self.right);
+        }else if self.right.serial.is_master() {
+            transfer(&mut self.right, &mut self.left);
+        }
+    }
+}
+
+fn transfer(master: &mut GameBoy, slave: &mut GameBoy) {
+    for _ in 0..master.serial.take_clocks() {
+        if !master.serial.is_master() {
+            break;
+        }
+        let sent = master.serial.output();
+        let received = slave.serial.output();
+        if master.serial.shift(received) {
+            master.request_interrupt(Interrupt::SERIAL);
+        }
+        if slave.serial.shift(sent) {
+            slave.request_interrupt(Interrupt::SERIAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gameboy::GameBoy;
+    use interrupt;
+    use serial;
+
+    fn boot(program: &[u8]) -> GameBoy {
+        let mut gb = GameBoy::new();
+        gb.load_firmware(program);
+        gb
+    }
+
+    fn run(link: &mut LinkedGameBoys, cycles: u64) {
+        while link.left.cycles < cycles || link.right.cycles < cycles {
+            assert!(link.step());
+        }
+    }
+
+    // LD A,0x42; LDH (SB),A; LD A,0x81; LDH (SC),A; XOR A; JR Z,-2
+    const MASTER: [u8; 11] = [0x3E, 0x42, 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, 0xAF, 0x28, 0xFE];
+    // LD A,0x99; LDH (SB),A; LD A,0x80; LDH (SC),A; XOR A; JR Z,-2
+    const SLAVE: [u8; 11] = [0x3E, 0x99, 0xE0, 0x01, 0x3E, 0x80, 0xE0, 0x02, 0xAF, 0x28, 0xFE];
+    /// Enough for the programs and a transfer of 8 bits
+    const TRANSFER_CYCLES: u64 = 64 + 8 * serial::BIT_CYCLES as u64;
+
+    #[test]
+    fn it_should_exchange_bytes_between_master_and_slave() {
+        let mut link = LinkedGameBoys::new(boot(&MASTER), boot(&SLAVE));
+        run(&mut link, TRANSFER_CYCLES);
+        assert_eq!(link.left.read(serial::SB), 0x99);
+        assert_eq!(link.right.read(serial::SB), 0x42);
+    }
+
+    #[test]
+    fn it_should_finish_the_transfer_on_both_sides() {
+        let mut link = LinkedGameBoys::new(boot(&MASTER), boot(&SLAVE));
+        run(&mut link, TRANSFER_CYCLES);
+        assert_eq!(link.left.read(serial::SC) & 0x80, 0);
+        assert_eq!(link.right.read(serial::SC) & 0x80, 0);
+        assert_eq!(link.left.read(interrupt::IF), Interrupt::SERIAL.bits());
+        assert_eq!(link.right.read(interrupt::IF), Interrupt::SERIAL.bits());
+    }
+
+    #[test]
+    fn it_should_shift_the_bits_at_the_serial_clock_rate() {
+        let mut link = LinkedGameBoys::new(boot(&MASTER), boot(&SLAVE));
+        run(&mut link, 64 + 4 * serial::BIT_CYCLES as u64);
+        assert_ne!(link.left.read(serial::SC) & 0x80, 0);
+        assert_ne!(link.right.read(serial::SC) & 0x80, 0);
+        // the upper nibble of each byte was shifted into the other side
+        assert_eq!(link.left.read(serial::SB), 0x29);
+        assert_eq!(link.right.read(serial::SB), 0x94);
+        assert_eq!(link.left.read(interrupt::IF), 0);
+    }
+
+    #[test]
+    fn it_should_run_both_game_boys_for_the_same_cycles() {
+        // LD A,0x42 repeated, 8 cycles each
+        let slow = [0x3E; 0x20];
+        let mut link = LinkedGameBoys::new(boot(&slow), boot(&[0x00; 0x20]));
+        run(&mut link, 64);
+        assert_eq!(link.left.cycles, 64);
+        assert_eq!(link.right.cycles, 64);
+    }
+
+    #[test]
+    fn it_should_work_with_the_master_on_the_right() {
+        let mut link = LinkedGameBoys::new(boot(&SLAVE), boot(&MASTER));
+        run(&mut link, TRANSFER_CYCLES);
+        assert_eq!(link.left.read(serial::SB), 0x42);
+        assert_eq!(link.right.read(serial::SB), 0x99);
+    }
+
+    #[test]
+    fn it_should_not_finish_the_transfer_of_an_idle_slave() {
+        // LD A,0x99; LDH (SB),A; XOR A; JR Z,-2
+        let idle = [0x3E, 0x99, 0xE0, 0x01, 0xAF, 0x28, 0xFE];
+        let mut link = LinkedGameBoys::new(boot(&MASTER), boot(&idle));
+        run(&mut link, TRANSFER_CYCLES);
+        assert_eq!(link.left.read(serial::SB), 0x99);
+        assert_eq!(link.right.read(serial::SB), 0x42);
+        assert_eq!(link.right.read(interrupt::IF), 0);
+    }
+
+    #[test]
+    fn it_should_keep_a_transfer_pending_until_the_link_is_serviced() {
+        let mut gb = boot(&MASTER);
+        gb.serial.connect_link();
+        for _ in 0..4 {
+            gb.step().unwrap();
+        }
+        assert!(gb.serial.is_master());
+    }
+
+    #[test]
+    fn it_should_replay_deterministically() {
+        let mut first = LinkedGameBoys::new(boot(&MASTER), boot(&SLAVE));
+        let mut second = LinkedGameBoys::new(boot(&MASTER), boot(&SLAVE));
+        run(&mut first, TRANSFER_CYCLES);
+        run(&mut second, TRANSFER_CYCLES);
+        let (first_left, first_right) = first.split();
+        let (second_left, second_right) = second.split();
+        assert_eq!(&first_left.ram[..], &second_left.ram[..]);
+        assert_eq!(&first_right.ram[..], &second_right.ram[..]);
+        assert_eq!(first_left.serial.read_data(), second_left.serial.read_data());
+    }
+}
@@ -0,0 +1,564 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use crc32fast::Hasher;
+use error::EmulationError;
+use gameboy::{GameBoy, IllegalOpcodePolicy, Model};
+use joypad::Button;
+
+const HEADER: &str = "gb-rs movie 1";
+
+const MODELS: [(Model, &str); 4] = [(Model::Dmg, "dmg"), (Model::Mgb, "mgb"), (Model::Cgb, "cgb"), (Model::Sgb, "sgb")];
+const BOOTS: [(bool, &str); 2] = [(true, "boot-rom"), (false, "skip")];
+const POLICIES: [(IllegalOpcodePolicy, &str); 3] = [
+    (IllegalOpcodePolicy::LockUp, "lock-up"),
+    (IllegalOpcodePolicy::Break, "break"),
+    (IllegalOpcodePolicy::Abort, "abort")
+];
+
+/**
+ * Joypad state which became active at the start of `frame`.
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Input {
+    pub frame: u64,
+    /// See `Joypad::state`
+    pub buttons: u8
+}
+
+/**
+ * Hashes of the machine state at the end of a movie.
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Checkpoint {
+    pub frame: u64,
+    pub framebuffer: u32,
+    pub ram: u32
+}
+
+impl Checkpoint {
+    pub fn capture(gb: &GameBoy) -> Checkpoint {
+        Checkpoint {
+            frame: gb.frame(),
+            framebuffer: framebuffer_hash(gb),
+            ram: ram_hash(gb)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MovieError {
+    Parse(usize, String),
+    RomMismatch { expected: u32, actual: u32 },
+    /// The model, boot or illegal opcode policy differs from the one the movie was recorded with
+    StartMismatch { setting: &'static str, expected: &'static str, actual: &'static str },
+    FramebufferMismatch { expected: u32, actual: u32 },
+    RamMismatch { expected: u32, actual: u32 },
+    MissingCheckpoint,
+    Stopped(u64, EmulationError)
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Parse(line, msg) => write!(f, "line {}: {}", line, msg),
+            MovieError::RomMismatch { expected, actual } =>
+                write!(f, "movie was recorded with rom {:08X}, loaded rom is {:08X}", expected, actual),
+            MovieError::StartMismatch { setting, expected, actual } =>
+                write!(f, "movie was recorded with {} {}, the emulation uses {}", setting, expected, actual),
+            MovieError::FramebufferMismatch { expected, actual } =>
+                write!(f, "framebuffer hash {:08X} differs from the recorded {:08X}", actual, expected),
+            MovieError::RamMismatch { expected, actual } =>
+                write!(f, "ram hash {:08X} differs from the recorded {:08X}", actual, expected),
+            MovieError::MissingCheckpoint => write!(f, "movie contains no end checkpoint"),
+            MovieError::Stopped(frame, err) => write!(f, "emulation stopped at frame {}: {}", frame, err)
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+impl From<MovieError> for io::Error {
+    fn from(err: MovieError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+    }
+}
+
+/**
+ * Recording of all joypad state changes.
+ *
+ * Movies always start at power on of the given model, in the boot rom or at the
+ * cartridge entry point, optionally with the given cartridge RAM.
+ * The text format looks like this:
+ *
+ * ```text
+ * gb-rs movie 1
+ * rom 1A2B3C4D
+ * model cgb
+ * boot skip
+ * illegal-opcodes lock-up
+ * sram 00FF00FF...
+ * input 12 10
+ * input 20 00
+ * end 600 framebuffer 89ABCDEF ram 01234567
+ * ```
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    pub rom: u32,
+    pub model: Model,
+    /// Whether the movie starts in the boot rom instead of the cartridge entry point
+    pub boot_rom: bool,
+    pub illegal_opcodes: IllegalOpcodePolicy,
+    pub sram: Vec<u8>,
+    pub inputs: Vec<Input>,
+    pub end: Option<Checkpoint>
+}
+
+impl Movie {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Movie> {
+        let movie = fs::read_to_string(path)?;
+        Ok(movie.parse()?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "rom {:08X}", self.rom)?;
+        writeln!(f, "model {}", name(&MODELS, self.model))?;
+        writeln!(f, "boot {}", name(&BOOTS, self.boot_rom))?;
+        writeln!(f, "illegal-opcodes {}", name(&POLICIES, self.illegal_opcodes))?;
+        if !self.sram.is_empty() {
+            write!(f, "sram ")?;
+            for byte in &self.sram {
+                write!(f, "{:02X}", byte)?;
+            }
+            writeln!(f)?;
+        }
+        for input in &self.inputs {
+            writeln!(f, "input {} {:02X}", input.frame, input.buttons)?;
+        }
+        if let Some(end) = self.end {
+            writeln!(f, "end {} framebuffer {:08X} ram {:08X}", end.frame, end.framebuffer, end.ram)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Movie {
+    type Err = MovieError;
+
+    fn from_str(movie: &str) -> Result<Movie, MovieError> {
+        let mut lines = movie.lines().enumerate();
+        match lines.next() {
+            Some((_, HEADER)) => {},
+            _ => return Err(MovieError::Parse(1, "not a gb-rs movie".to_owned()))
+        }
+        let (mut model, mut boot_rom, mut illegal_opcodes) = (None, None, None);
+        let mut result = Movie {
+            rom: 0,
+            model: Model::Dmg,
+            boot_rom: false,
+            illegal_opcodes: IllegalOpcodePolicy::LockUp,
+            sram: Vec::new(),
+            inputs: Vec::new(),
+            end: None
+        };
+        for (i, line) in lines {
+            let error = |msg: &str| MovieError::Parse(i + 1, msg.to_owned());
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [] => {},
+                ["rom", hash] => result.rom = u32::from_str_radix(hash, 16).map_err(|_| error("invalid rom hash"))?,
+                ["model", name] => model = Some(value(&MODELS, name).ok_or_else(|| error("unknown model"))?),
+                ["boot", name] => boot_rom = Some(value(&BOOTS, name).ok_or_else(|| error("unknown boot"))?),
+                ["illegal-opcodes", name] =>
+                    illegal_opcodes = Some(value(&POLICIES, name).ok_or_else(|| error("unknown illegal opcode policy"))?),
+                ["sram", data] => result.sram = parse_hex(data).ok_or_else(|| error("invalid sram"))?,
+                ["input", frame, buttons] => {
+                    let frame = frame.parse().map_err(|_| error("invalid frame"))?;
+                    let buttons = u8::from_str_radix(buttons, 16).map_err(|_| error("invalid buttons"))?;
+                    result.inputs.push(Input {
+                        frame,
+                        buttons
+                    });
+                },
+                ["end", frame, "framebuffer", framebuffer, "ram", ram] => {
+                    result.end = Some(Checkpoint {
+                        frame: frame.parse().map_err(|_| error("invalid frame"))?,
+                        framebuffer: u32::from_str_radix(framebuffer, 16).map_err(|_| error("invalid framebuffer hash"))?,
+                        ram: u32::from_str_radix(ram, 16).map_err(|_| error("invalid ram hash"))?
+                    });
+                },
+                _ => return Err(error("unknown entry"))
+            }
+        }
+        let missing = |entry: &str| MovieError::Parse(1, format!("missing {} entry", entry));
+        result.model = model.ok_or_else(|| missing("model"))?;
+        result.boot_rom = boot_rom.ok_or_else(|| missing("boot"))?;
+        result.illegal_opcodes = illegal_opcodes.ok_or_else(|| missing("illegal-opcodes"))?;
+        Ok(result)
+    }
+}
+
+/**
+ * Records joypad changes while the Game Boy runs.
+ *
+ * Presses and releases are buffered and only applied at the start of the next frame,
+ * which keeps the recording deterministic regardless of when the host delivered them.
+ * `update` has to be called before every step.
+ */
+pub struct Recorder {
+    movie: Movie,
+    frame: u64,
+    buttons: u8
+}
+
+impl Recorder {
+    pub fn new(gb: &GameBoy) -> Recorder {
+        Recorder {
+            movie: Movie {
+                rom: rom_hash(gb),
+                model: gb.model,
+                boot_rom: gb.is_booting(),
+                illegal_opcodes: gb.illegal_opcode_policy,
+                sram: gb.cartridge.as_ref().map(|c| c.ram().to_vec()).unwrap_or_default(),
+                inputs: Vec::new(),
+                end: None
+            },
+            frame: gb.frame(),
+            buttons: gb.joypad.state()
+        }
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.buttons |= button.mask();
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.buttons &= !button.mask();
+    }
+
+    pub fn update(&mut self, gb: &mut GameBoy) {
+        if gb.frame() == self.frame {
+            return;
+        }
+        self.frame = gb.frame();
+        if self.buttons != gb.joypad.state() {
+            apply(gb, self.buttons);
+            self.movie.inputs.push(Input {
+                frame: self.frame,
+                buttons: self.buttons
+            });
+        }
+    }
+
+    /**
+     * Runs until the current frame is finished and stores the end checkpoint.
+     */
+    pub fn finish(mut self, gb: &mut GameBoy) -> Movie {
+        let frame = gb.frame();
+        while gb.frame() == frame && gb.step().is_ok() {}
+        self.movie.end = Some(Checkpoint::capture(gb));
+        self.movie
+    }
+}
+
+/**
+ * Replays the inputs of a movie, `update` has to be called before every step.
+ */
+pub struct Player {
+    movie: Movie,
+    next: usize
+}
+
+impl Player {
+    /**
+     * Checks the movie was recorded with the inserted cartridge and the same start state
+     * and restores the cartridge RAM.
+     */
+    pub fn new(movie: Movie, gb: &mut GameBoy) -> Result<Player, MovieError> {
+        let actual = rom_hash(gb);
+        if actual != movie.rom {
+            return Err(MovieError::RomMismatch {
+                expected: movie.rom,
+                actual
+            });
+        }
+        check("model", &MODELS, movie.model, gb.model)?;
+        check("boot", &BOOTS, movie.boot_rom, gb.is_booting())?;
+        check("illegal opcode policy", &POLICIES, movie.illegal_opcodes, gb.illegal_opcode_policy)?;
+        if let Some(ref mut cartridge) = gb.cartridge {
+            cartridge.load_ram(&movie.sram);
+        }
+        Ok(Player {
+            movie,
+            next: 0
+        })
+    }
+
+    pub fn update(&mut self, gb: &mut GameBoy) {
+        while let Some(input) = self.movie.inputs.get(self.next) {
+            if input.frame > gb.frame() {
+                break;
+            }
+            apply(gb, input.buttons);
+            self.next += 1;
+        }
+    }
+
+    pub fn is_finished(&self, gb: &GameBoy) -> bool {
+        match self.movie.end {
+            Some(end) => gb.frame() >= end.frame,
+            None => self.next >= self.movie.inputs.len()
+        }
+    }
+
+    /**
+     * Compares the current state against the end checkpoint of the movie.
+     */
+    pub fn verify(&self, gb: &GameBoy) -> Result<(), MovieError> {
+        let end = self.movie.end.ok_or(MovieError::MissingCheckpoint)?;
+        let actual = Checkpoint::capture(gb);
+        if actual.framebuffer != end.framebuffer {
+            return Err(MovieError::FramebufferMismatch {
+                expected: end.framebuffer,
+                actual: actual.framebuffer
+            });
+        }
+        if actual.ram != end.ram {
+            return Err(MovieError::RamMismatch {
+                expected: end.ram,
+                actual: actual.ram
+            });
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Plays the whole movie and verifies the final state.
+ */
+pub fn verify(movie: Movie, gb: &mut GameBoy) -> Result<(), MovieError> {
+    let mut player = Player::new(movie, gb)?;
+    while !player.is_finished(gb) {
+        player.update(gb);
+        if let Err(err) = gb.step() {
+            return Err(MovieError::Stopped(gb.frame(), err));
+        }
+    }
+    player.verify(gb)
+}
+
+fn check<T: Copy + PartialEq>(setting: &'static str, names: &[(T, &'static str)], expected: T, actual: T)
+    -> Result<(), MovieError> {
+    if expected == actual {
+        return Ok(());
+    }
+    Err(MovieError::StartMismatch {
+        setting,
+        expected: name(names, expected),
+        actual: name(names, actual)
+    })
+}
+
+fn name<T: Copy + PartialEq>(names: &[(T, &'static str)], value: T) -> &'static str {
+    names.iter().find(|(v, _)| *v == value).map_or("", |(_, name)| name)
+}
+
+fn value<T: Copy>(names: &[(T, &str)], name: &str) -> Option<T> {
+    names.iter().find(|(_, n)| *n == name).map(|(value, _)| *value)
+}
+
+fn apply(gb: &mut GameBoy, buttons: u8) {
+    for button in Button::ALL.iter() {
+        let pressed = buttons & button.mask() != 0;
+        if pressed && !gb.joypad.is_pressed(*button) {
+            gb.press(*button);
+        }else if !pressed && gb.joypad.is_pressed(*button) {
+            gb.release(*button);
+        }
+    }
+}
+
+fn parse_hex(data: &str) -> Option<Vec<u8>> {
+    if data.len() & 1 != 0 {
+        return None;
+    }
+    (0..data.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(data.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+pub fn rom_hash(gb: &GameBoy) -> u32 {
+    gb.cartridge.as_ref().map(|c| crc32fast::hash(c.rom())).unwrap_or(0)
+}
+
+/**
+ * Hash of the RGB555 screen as shown on the display, independent of the color correction.
+ */
+pub fn framebuffer_hash(gb: &GameBoy) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    for color in gb.screen().colors {
+        hasher.update(&[*color as u8, (*color >> 8) as u8]);
+    }
+    hasher.finalize()
+}
+
+/**
+ * Hash of all work RAM banks, high RAM and cartridge RAM.
+ *
+ * The memory is hashed directly, reads through the bus would see OAM DMA and trigger watchpoints.
+ */
+pub fn ram_hash(gb: &GameBoy) -> u32 {
+    let mut hasher = Hasher::new();
+    hasher.update(gb.wram());
+    hasher.update(gb.hram());
+    if let Some(ref cartridge) = gb.cartridge {
+        hasher.update(cartridge.ram());
+    }
+    hasher.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dma;
+    use gameboy::{GameBoy, Model, SVBK};
+
+    // select buttons and copy P1 into 0xC000 forever
+    const PROGRAM: [u8; 13] = [
+        0x01, 0x00, 0xFF, // LD BC,0xFF00
+        0x21, 0x00, 0xC0, // LD HL,0xC000
+        0x3E, 0x10, // LD A,0x10
+        0x02, // LD (BC),A
+        0x0A, // LD A,(BC)
+        0x77, // LD (HL),A
+        0x20, 0xFC // JR NZ,-4
+    ];
+
+    fn boot() -> GameBoy {
+        let mut gb = GameBoy::new();
+        gb.load_firmware(&PROGRAM);
+        gb
+    }
+
+    fn run_until(gb: &mut GameBoy, recorder: &mut Recorder, frame: u64) {
+        while gb.frame() < frame {
+            recorder.update(gb);
+            gb.step().unwrap();
+        }
+    }
+
+    fn record() -> Movie {
+        let mut gb = boot();
+        let mut recorder = Recorder::new(&gb);
+        run_until(&mut gb, &mut recorder, 2);
+        recorder.press(Button::A);
+        recorder.press(Button::Start);
+        run_until(&mut gb, &mut recorder, 4);
+        recorder.release(Button::Start);
+        run_until(&mut gb, &mut recorder, 6);
+        recorder.finish(&mut gb)
+    }
+
+    #[test]
+    fn it_should_record_input_changes_with_their_frame() {
+        let movie = record();
+        assert_eq!(movie.inputs, vec![
+            Input { frame: 2, buttons: 0x90 },
+            Input { frame: 4, buttons: 0x10 }
+        ]);
+        assert_eq!(movie.end.unwrap().frame, 7);
+    }
+
+    #[test]
+    fn it_should_verify_a_played_back_movie() {
+        let movie = record();
+        let mut gb = boot();
+        assert_eq!(verify(movie, &mut gb), Ok(()));
+        assert_eq!(gb.read(0xC000), 0xDE);
+    }
+
+    #[test]
+    fn it_should_fail_verification_when_the_state_differs() {
+        let mut movie = record();
+        movie.inputs.pop();
+        let mut gb = boot();
+        match verify(movie, &mut gb) {
+            Err(MovieError::RamMismatch { .. }) => {},
+            result => panic!("unexpected result {:?}", result)
+        }
+    }
+
+    #[test]
+    fn it_should_roundtrip_the_text_format() {
+        let mut movie = record();
+        movie.sram = vec![0x00, 0xAB];
+        let parsed: Movie = movie.to_string().parse().unwrap();
+        assert_eq!(parsed, movie);
+    }
+
+    #[test]
+    fn it_should_hash_every_wram_bank_without_bus_reads() {
+        let mut gb = GameBoy::with_model(Model::Cgb);
+        let hash = ram_hash(&gb);
+        gb.write(SVBK, 7);
+        gb.write(0xD000, 0x42);
+        gb.write(SVBK, 1);
+        assert_ne!(ram_hash(&gb), hash);
+
+        let hash = ram_hash(&gb);
+        gb.write(dma::DMA, 0xC0);
+        assert_eq!(ram_hash(&gb), hash);
+    }
+
+    #[test]
+    fn it_should_reject_unknown_entries() {
+        let result = "gb-rs movie 1\nturbo 1".parse::<Movie>();
+        assert_eq!(result, Err(MovieError::Parse(2, "unknown entry".to_owned())));
+    }
+
+    #[test]
+    fn it_should_reject_movies_for_other_roms() {
+        let mut movie = record();
+        movie.rom = 0x1234;
+        let mut gb = boot();
+        assert!(Player::new(movie, &mut gb).is_err());
+    }
+
+    #[test]
+    fn it_should_reject_movies_with_another_start_state() {
+        let movie = record();
+        assert!(movie.boot_rom);
+        let mut gb = GameBoy::with_model(Model::Cgb);
+        gb.load_firmware(&PROGRAM);
+        let expected = MovieError::StartMismatch { setting: "model", expected: "dmg", actual: "cgb" };
+        assert_eq!(Player::new(movie.clone(), &mut gb).err(), Some(expected));
+
+        let mut gb = GameBoy::new();
+        gb.skip_boot();
+        assert!(matches!(Player::new(movie.clone(), &mut gb), Err(MovieError::StartMismatch { setting: "boot", .. })));
+
+        let mut gb = boot();
+        gb.illegal_opcode_policy = IllegalOpcodePolicy::Abort;
+        assert!(matches!(Player::new(movie, &mut gb), Err(MovieError::StartMismatch { .. })));
+    }
+
+    #[test]
+    fn it_should_require_the_start_state() {
+        let result = "gb-rs movie 1\nrom 1A2B3C4D\nmodel dmg\nboot skip".parse::<Movie>();
+        assert_eq!(result, Err(MovieError::Parse(1, "missing illegal-opcodes entry".to_owned())));
+        let result = "gb-rs movie 1\nmodel gbc".parse::<Movie>();
+        assert_eq!(result, Err(MovieError::Parse(2, "unknown model".to_owned())));
+    }
+}
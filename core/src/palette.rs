@@ -0,0 +1,140 @@
+pub const BCPS: u16 = 0xFF68;
+pub const BCPD: u16 = 0xFF69;
+pub const OCPS: u16 = 0xFF6A;
+pub const OCPD: u16 = 0xFF6B;
+
+const AUTO_INCREMENT: u8 = 0b1000_0000;
+const INDEX: u8 = 0b0011_1111;
+
+/// Shades of the DMG as RGB555, from white to black
+pub const DMG_COLORS: [u16; 4] = [0x7FFF, 0x56B5, 0x294A, 0x0000];
+
+/**
+ * CGB palette memory
+ *
+ * Holds 8 palettes with 4 little endian RGB555 colors each. It is accessed through
+ * a specification register (BCPS/OCPS) selecting the byte in bits 5-0, bit 7 increments
+ * the index after every write to the data register (BCPD/OCPD).
+ */
+pub struct PaletteRam {
+    data: [u8; 64],
+    index: u8,
+    auto_increment: bool
+}
+
+impl Default for PaletteRam {
+    fn default() -> PaletteRam {
+        PaletteRam {
+            data: [0; 64],
+            index: 0,
+            auto_increment: false
+        }
+    }
+}
+
+impl PaletteRam {
+    pub fn read_spec(&self) -> u8 {
+        let increment = if self.auto_increment { AUTO_INCREMENT } else { 0 };
+        0b0100_0000 | increment | self.index
+    }
+
+    pub fn write_spec(&mut self, value: u8) {
+        self.index = value & INDEX;
+        self.auto_increment = value & AUTO_INCREMENT != 0;
+    }
+
+    pub fn read_data(&self) -> u8 {
+        self.data[self.index as usize]
+    }
+
+    pub fn write_data(&mut self, value: u8) {
+        self.data[self.index as usize] = value;
+        if self.auto_increment {
+            self.index = (self.index + 1) & INDEX;
+        }
+    }
+
+    /**
+     * RGB555 value of `color` (0-3) in `palette` (0-7).
+     */
+    pub fn color(&self, palette: usize, color: u8) -> u16 {
+        let offset = palette * 8 + color as usize * 2;
+        (self.data[offset] as u16 | (self.data[offset + 1] as u16) << 8) & 0x7FFF
+    }
+}
+
+/**
+ * Conversion of RGB555 colors to 24 bit RGB.
+ */
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum ColorCorrection {
+    /// Scales every channel linearly, colors look more saturated than on the real LCD
+    #[default]
+    Raw,
+    /// Mixes the channels and darkens them to approximate the colors of the CGB LCD
+    Lcd
+}
+
+impl ColorCorrection {
+    pub fn rgb(self, color: u16) -> [u8; 3] {
+        let r = (color & 0x1F) as u32;
+        let g = (color >> 5 & 0x1F) as u32;
+        let b = (color >> 10 & 0x1F) as u32;
+        match self {
+            ColorCorrection::Raw => [scale(r), scale(g), scale(b)],
+            ColorCorrection::Lcd => [
+                curve(r * 26 + g * 4 + b * 2),
+                curve(g * 24 + b * 8),
+                curve(r * 6 + g * 4 + b * 22)
+            ]
+        }
+    }
+}
+
+fn scale(channel: u32) -> u8 {
+    (channel << 3 | channel >> 2) as u8
+}
+
+fn curve(channel: u32) -> u8 {
+    (channel.min(960) >> 2) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_increment_the_index_after_writes() {
+        let mut palettes = PaletteRam::default();
+        palettes.write_spec(0x82);
+        palettes.write_data(0x1F);
+        palettes.write_data(0x00);
+        assert_eq!(palettes.read_spec(), 0xC4);
+        assert_eq!(palettes.color(0, 1), 0x001F);
+    }
+
+    #[test]
+    fn it_should_not_increment_without_auto_increment() {
+        let mut palettes = PaletteRam::default();
+        palettes.write_spec(0x3F);
+        palettes.write_data(0x12);
+        assert_eq!(palettes.read_spec(), 0x7F);
+        assert_eq!(palettes.read_data(), 0x12);
+    }
+
+    #[test]
+    fn it_should_wrap_around_the_index() {
+        let mut palettes = PaletteRam::default();
+        palettes.write_spec(0xBF);
+        palettes.write_data(0xFF);
+        assert_eq!(palettes.read_spec(), 0xC0);
+    }
+
+    #[test]
+    fn it_should_convert_colors_to_rgb() {
+        assert_eq!(ColorCorrection::Raw.rgb(0x7FFF), [0xFF, 0xFF, 0xFF]);
+        assert_eq!(ColorCorrection::Raw.rgb(0x001F), [0xFF, 0x00, 0x00]);
+        assert_eq!(ColorCorrection::Lcd.rgb(0x7FFF), [240, 240, 240]);
+        assert_eq!(ColorCorrection::Lcd.rgb(0x0000), [0, 0, 0]);
+    }
+}
@@ -0,0 +1,767 @@
+use interrupt::Interrupt;
+use palette::{ColorCorrection, PaletteRam, BCPS, BCPD, OCPS, OCPD, DMG_COLORS};
+use png;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+pub const WIDTH: usize = 160;
+pub const HEIGHT: usize = 144;
+
+/**
+ * A frame as shown on the display: RGB555 colors, row by row.
+ */
+#[derive(Debug, Clone, Copy)]
+pub struct Screen<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub colors: &'a [u16]
+}
+
+impl<'a> Screen<'a> {
+    /**
+     * 24 bit RGB pixels of the frame, converted with the given color correction.
+     */
+    pub fn rgb(&self, correction: ColorCorrection) -> Vec<u8> {
+        self.colors.iter()
+            .flat_map(|color| correction.rgb(*color))
+            .collect()
+    }
+
+    pub fn save(&self, path: &Path, correction: ColorCorrection) -> io::Result<()> {
+        let file = File::create(path)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.rgb(correction))?;
+        Ok(())
+    }
+}
+
+pub const LCDC: u16 = 0xFF40;
+pub const STAT: u16 = 0xFF41;
+pub const SCY: u16 = 0xFF42;
+pub const SCX: u16 = 0xFF43;
+pub const LY: u16 = 0xFF44;
+pub const LYC: u16 = 0xFF45;
+pub const BGP: u16 = 0xFF47;
+pub const OBP0: u16 = 0xFF48;
+pub const OBP1: u16 = 0xFF49;
+pub const WY: u16 = 0xFF4A;
+pub const WX: u16 = 0xFF4B;
+pub const VBK: u16 = 0xFF4F;
+
+const VRAM_BANK_SIZE: usize = 0x2000;
+
+const OAM_SCAN_CYCLES: u32 = 80;
+const DRAWING_CYCLES: u32 = 172;
+const HBLANK_CYCLES: u32 = 204;
+const LINE_CYCLES: u32 = 456;
+const LINES: u8 = 154;
+pub const FRAME_CYCLES: u32 = LINE_CYCLES * LINES as u32;
+
+const SPRITES_PER_LINE: usize = 10;
+
+bitflags! {
+    #[derive(Default)]
+    pub struct Control: u8 {
+        const BG_ENABLE = 0b0000_0001;
+        const OBJ_ENABLE = 0b0000_0010;
+        const OBJ_SIZE = 0b0000_0100;
+        const BG_TILE_MAP = 0b0000_1000;
+        const TILE_DATA = 0b0001_0000;
+        const WINDOW_ENABLE = 0b0010_0000;
+        const WINDOW_TILE_MAP = 0b0100_0000;
+        const LCD_ENABLE = 0b1000_0000;
+    }
+}
+
+bitflags! {
+    #[derive(Default)]
+    struct StatInterrupts: u8 {
+        const HBLANK = 0b0000_1000;
+        const VBLANK = 0b0001_0000;
+        const OAM = 0b0010_0000;
+        const LYC = 0b0100_0000;
+    }
+}
+
+bitflags! {
+    /**
+     * Attributes of background tiles (CGB VRAM bank 1) and sprites (OAM byte 3).
+     */
+    #[derive(Default)]
+    struct Attributes: u8 {
+        const PALETTE = 0b0000_0111;
+        const BANK = 0b0000_1000;
+        const DMG_PALETTE = 0b0001_0000;
+        const X_FLIP = 0b0010_0000;
+        const Y_FLIP = 0b0100_0000;
+        const PRIORITY = 0b1000_0000;
+    }
+}
+
+impl Attributes {
+    fn palette(self) -> usize {
+        (self & Attributes::PALETTE).bits() as usize
+    }
+}
+
+/**
+ * Background or window pixel with the attributes of its tile.
+ */
+#[derive(Default, Copy, Clone)]
+struct Pixel {
+    color: u8,
+    attributes: Attributes
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Drawing = 3
+}
+
+/**
+ * Pixel Processing Unit
+ *
+ * Renders one scanline at the end of the drawing mode into a framebuffer of 2 bit shades
+ * (color numbers in CGB mode) and a screen of RGB555 colors.
+ */
+pub struct Ppu {
+    cgb: bool,
+    vram: Vec<u8>,
+    vram_bank: usize,
+    oam: [u8; 0xA0],
+    control: Control,
+    stat: StatInterrupts,
+    scy: u8,
+    scx: u8,
+    ly: u8,
+    lyc: u8,
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    wy: u8,
+    wx: u8,
+    bg_palettes: PaletteRam,
+    obj_palettes: PaletteRam,
+    mode: Mode,
+    cycles: u32,
+    stat_line: bool,
+    window_line: u8,
+    framebuffer: Vec<u8>,
+    screen: Vec<u16>,
+    frame: u64,
+    /// How screenshots convert the colors of the screen
+    pub color_correction: ColorCorrection
+}
+
+impl Default for Ppu {
+    fn default() -> Ppu {
+        Ppu::new(false)
+    }
+}
+
+impl Ppu {
+    /**
+     * The CGB has a second VRAM bank selectable through VBK.
+     */
+    pub fn new(cgb: bool) -> Ppu {
+        let banks = if cgb { 2 } else { 1 };
+        Ppu {
+            cgb,
+            vram: vec![0; banks * VRAM_BANK_SIZE],
+            vram_bank: 0,
+            oam: [0; 0xA0],
+            control: Control::empty(),
+            stat: StatInterrupts::empty(),
+            scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            bgp: 0,
+            obp0: 0,
+            obp1: 0,
+            wy: 0,
+            wx: 0,
+            bg_palettes: PaletteRam::default(),
+            obj_palettes: PaletteRam::default(),
+            mode: Mode::HBlank,
+            cycles: 0,
+            stat_line: false,
+            window_line: 0,
+            framebuffer: vec![0; WIDTH * HEIGHT],
+            screen: vec![DMG_COLORS[0]; WIDTH * HEIGHT],
+            frame: 0,
+            color_correction: ColorCorrection::default()
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0x9FFF => self.vram[self.vram_offset(addr)],
+            0xFE00..=0xFE9F => self.oam[addr as usize - 0xFE00],
+            LCDC => self.control.bits(),
+            STAT => {
+                let coincidence = if self.ly == self.lyc { 0b0000_0100 } else { 0 };
+                0b1000_0000 | self.stat.bits() | coincidence | self.mode as u8
+            },
+            SCY => self.scy,
+            SCX => self.scx,
+            LY => self.ly,
+            LYC => self.lyc,
+            BGP => self.bgp,
+            OBP0 => self.obp0,
+            OBP1 => self.obp1,
+            WY => self.wy,
+            WX => self.wx,
+            VBK if self.cgb => 0b1111_1110 | self.vram_bank as u8,
+            BCPS if self.cgb => self.bg_palettes.read_spec(),
+            BCPD if self.cgb && !self.is_drawing() => self.bg_palettes.read_data(),
+            OCPS if self.cgb => self.obj_palettes.read_spec(),
+            OCPD if self.cgb && !self.is_drawing() => self.obj_palettes.read_data(),
+            _ => 0xFF
+        }
+    }
+
+    /**
+     * Returns the interrupts requested by the write.
+     */
+    pub fn write(&mut self, addr: u16, value: u8) -> Interrupt {
+        match addr {
+            0x8000..=0x9FFF => {
+                let offset = self.vram_offset(addr);
+                self.vram[offset] = value;
+            },
+            0xFE00..=0xFE9F => self.oam[addr as usize - 0xFE00] = value,
+            LCDC => self.write_control(Control::from_bits_truncate(value)),
+            STAT => self.stat = StatInterrupts::from_bits_truncate(value),
+            SCY => self.scy = value,
+            SCX => self.scx = value,
+            LY => {},
+            LYC => self.lyc = value,
+            BGP => self.bgp = value,
+            OBP0 => self.obp0 = value,
+            OBP1 => self.obp1 = value,
+            WY => self.wy = value,
+            WX => self.wx = value,
+            VBK if self.cgb => self.vram_bank = (value & 0b0000_0001) as usize,
+            BCPS if self.cgb => self.bg_palettes.write_spec(value),
+            BCPD if self.cgb && !self.is_drawing() => self.bg_palettes.write_data(value),
+            OCPS if self.cgb => self.obj_palettes.write_spec(value),
+            OCPD if self.cgb && !self.is_drawing() => self.obj_palettes.write_data(value),
+            _ => {}
+        }
+        self.check_stat_line()
+    }
+
+    fn vram_offset(&self, addr: u16) -> usize {
+        self.vram_bank * VRAM_BANK_SIZE + addr as usize - 0x8000
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /**
+     * Palette memory is inaccessible while the PPU reads it.
+     */
+    fn is_drawing(&self) -> bool {
+        self.control.contains(Control::LCD_ENABLE) && self.mode == Mode::Drawing
+    }
+
+    /**
+     * Number of frames since power on.
+     *
+     * While the LCD is disabled a frame still passes every `FRAME_CYCLES`.
+     */
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /**
+     * 2 bit shades (0 = white, 3 = black) of the last rendered frame, row by row.
+     *
+     * In CGB mode these are the color numbers before applying the palettes.
+     */
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    /**
+     * RGB555 colors of the last rendered frame, row by row.
+     */
+    pub fn screen(&self) -> &[u16] {
+        &self.screen
+    }
+
+    /**
+     * Advances the PPU by the given amount of clock cycles.
+     *
+     * Returns the requested interrupts.
+     */
+    pub fn step(&mut self, cycles: u32) -> Interrupt {
+        let mut interrupts = Interrupt::empty();
+        self.cycles += cycles;
+        if !self.control.contains(Control::LCD_ENABLE) {
+            if self.cycles >= FRAME_CYCLES {
+                self.cycles -= FRAME_CYCLES;
+                self.frame += 1;
+            }
+            return interrupts;
+        }
+        loop {
+            match self.mode {
+                Mode::OamScan if self.cycles >= OAM_SCAN_CYCLES => {
+                    self.cycles -= OAM_SCAN_CYCLES;
+                    self.mode = Mode::Drawing;
+                },
+                Mode::Drawing if self.cycles >= DRAWING_CYCLES => {
+                    self.cycles -= DRAWING_CYCLES;
+                    self.render_line();
+                    self.mode = Mode::HBlank;
+                },
+                Mode::HBlank if self.cycles >= HBLANK_CYCLES => {
+                    self.cycles -= HBLANK_CYCLES;
+                    self.ly += 1;
+                    if self.ly as usize == HEIGHT {
+                        self.mode = Mode::VBlank;
+                        self.frame += 1;
+                        interrupts.insert(Interrupt::VBLANK);
+                    }else {
+                        self.mode = Mode::OamScan;
+                    }
+                },
+                Mode::VBlank if self.cycles >= LINE_CYCLES => {
+                    self.cycles -= LINE_CYCLES;
+                    self.ly += 1;
+                    if self.ly == LINES {
+                        self.ly = 0;
+                        self.window_line = 0;
+                        self.mode = Mode::OamScan;
+                    }
+                },
+                _ => break
+            }
+            interrupts.insert(self.check_stat_line());
+        }
+        interrupts
+    }
+
+    fn write_control(&mut self, control: Control) {
+        let enabled = self.control.contains(Control::LCD_ENABLE);
+        self.control = control;
+        if enabled && !control.contains(Control::LCD_ENABLE) {
+            self.ly = 0;
+            self.cycles = 0;
+            self.window_line = 0;
+            self.mode = Mode::HBlank;
+        }else if !enabled && control.contains(Control::LCD_ENABLE) {
+            self.cycles = 0;
+            self.mode = Mode::OamScan;
+        }
+    }
+
+    /**
+     * The STAT interrupt is requested on the rising edge of all enabled sources.
+     */
+    fn check_stat_line(&mut self) -> Interrupt {
+        let mode = match self.mode {
+            Mode::HBlank => self.stat.contains(StatInterrupts::HBLANK),
+            Mode::VBlank => self.stat.contains(StatInterrupts::VBLANK),
+            Mode::OamScan => self.stat.contains(StatInterrupts::OAM),
+            Mode::Drawing => false
+        };
+        let coincidence = self.stat.contains(StatInterrupts::LYC) && self.ly == self.lyc;
+        let line = self.control.contains(Control::LCD_ENABLE) && (mode || coincidence);
+        let rising = line && !self.stat_line;
+        self.stat_line = line;
+        if rising {
+            Interrupt::LCD_STAT
+        }else {
+            Interrupt::empty()
+        }
+    }
+
+    fn render_line(&mut self) {
+        let mut background = [Pixel::default(); WIDTH];
+        // on CGB the background can't be disabled, the bit only removes its priority over sprites
+        if self.cgb || self.control.contains(Control::BG_ENABLE) {
+            self.render_background(&mut background);
+            if self.control.contains(Control::WINDOW_ENABLE) {
+                self.render_window(&mut background);
+            }
+        }
+        let line = self.ly as usize * WIDTH;
+        for (x, pixel) in background.iter().enumerate() {
+            if self.cgb {
+                let color = self.bg_palettes.color(pixel.attributes.palette(), pixel.color);
+                self.plot(line + x, pixel.color, color);
+            }else {
+                let shade = shade(self.bgp, pixel.color);
+                self.plot(line + x, shade, DMG_COLORS[shade as usize]);
+            }
+        }
+        if self.control.contains(Control::OBJ_ENABLE) {
+            self.render_sprites(&background);
+        }
+    }
+
+    fn plot(&mut self, offset: usize, shade: u8, color: u16) {
+        self.framebuffer[offset] = shade;
+        self.screen[offset] = color;
+    }
+
+    fn render_background(&self, pixels: &mut [Pixel; WIDTH]) {
+        let map = if self.control.contains(Control::BG_TILE_MAP) { 0x1C00 } else { 0x1800 };
+        let y = self.ly.wrapping_add(self.scy);
+        for (x, pixel) in pixels.iter_mut().enumerate() {
+            let x = (x as u8).wrapping_add(self.scx);
+            *pixel = self.tile_map_pixel(map, x, y);
+        }
+    }
+
+    fn render_window(&mut self, pixels: &mut [Pixel; WIDTH]) {
+        if self.ly < self.wy || self.wx > 166 {
+            return;
+        }
+        let map = if self.control.contains(Control::WINDOW_TILE_MAP) { 0x1C00 } else { 0x1800 };
+        let start = self.wx.saturating_sub(7) as usize;
+        for (x, pixel) in pixels.iter_mut().enumerate().skip(start) {
+            let window_x = (x + 7 - self.wx as usize) as u8;
+            *pixel = self.tile_map_pixel(map, window_x, self.window_line);
+        }
+        self.window_line += 1;
+    }
+
+    /**
+     * On CGB the attributes of a tile map entry are stored at the same offset in VRAM bank 1.
+     */
+    fn tile_map_pixel(&self, map: usize, x: u8, y: u8) -> Pixel {
+        let index = map + (y as usize / 8) * 32 + x as usize / 8;
+        let tile = self.vram[index];
+        let attributes = if self.cgb {
+            Attributes::from_bits_truncate(self.vram[VRAM_BANK_SIZE + index])
+        }else {
+            Attributes::empty()
+        };
+        let mut address = if self.control.contains(Control::TILE_DATA) {
+            tile as usize * 16
+        }else {
+            (0x1000 + (tile as i8 as isize) * 16) as usize
+        };
+        if attributes.contains(Attributes::BANK) {
+            address += VRAM_BANK_SIZE;
+        }
+        let tile_x = if attributes.contains(Attributes::X_FLIP) { 7 - x % 8 } else { x % 8 };
+        let tile_y = if attributes.contains(Attributes::Y_FLIP) { 7 - y % 8 } else { y % 8 };
+        Pixel {
+            color: self.tile_color(address, tile_x, tile_y),
+            attributes
+        }
+    }
+
+    fn tile_color(&self, address: usize, x: u8, y: u8) -> u8 {
+        let low = self.vram[address + y as usize * 2];
+        let high = self.vram[address + y as usize * 2 + 1];
+        let bit = 7 - x;
+        ((high >> bit) & 1) << 1 | ((low >> bit) & 1)
+    }
+
+    fn render_sprites(&mut self, background: &[Pixel; WIDTH]) {
+        let height = if self.control.contains(Control::OBJ_SIZE) { 16 } else { 8 };
+        let ly = self.ly as i16;
+        let mut sprites: Vec<(usize, [u8; 4])> = self.oam
+            .chunks(4)
+            .map(|sprite| [sprite[0], sprite[1], sprite[2], sprite[3]])
+            .enumerate()
+            .filter(|(_, sprite)| {
+                let y = sprite[0] as i16 - 16;
+                ly >= y && ly < y + height
+            })
+            .take(SPRITES_PER_LINE)
+            .collect();
+        // on DMG lower x coordinates are drawn on top, on equal coordinates the first one in OAM wins,
+        // the CGB only uses the position in OAM
+        if !self.cgb {
+            sprites.sort_by_key(|(index, sprite)| (sprite[1], *index));
+        }
+
+        let line = self.ly as usize * WIDTH;
+        for (_, sprite) in sprites.iter().rev() {
+            let y = sprite[0] as i16 - 16;
+            let x = sprite[1] as i16 - 8;
+            let attributes = Attributes::from_bits_truncate(sprite[3]);
+            let mut tile = sprite[2];
+            let mut row = (ly - y) as u8;
+            if attributes.contains(Attributes::Y_FLIP) {
+                row = height as u8 - 1 - row;
+            }
+            if height == 16 {
+                tile &= 0xFE;
+            }
+            let mut address = tile as usize * 16;
+            if self.cgb && attributes.contains(Attributes::BANK) {
+                address += VRAM_BANK_SIZE;
+            }
+            for column in 0..8 {
+                let screen_x = x + column as i16;
+                if screen_x < 0 || screen_x >= WIDTH as i16 {
+                    continue;
+                }
+                let tile_x = if attributes.contains(Attributes::X_FLIP) { 7 - column } else { column };
+                let color = self.tile_color(address, tile_x, row);
+                if color == 0 {
+                    continue;
+                }
+                let pixel = background[screen_x as usize];
+                if pixel.color != 0 && self.is_behind_background(attributes, pixel.attributes) {
+                    continue;
+                }
+                let offset = line + screen_x as usize;
+                if self.cgb {
+                    let rgb = self.obj_palettes.color(attributes.palette(), color);
+                    self.plot(offset, color, rgb);
+                }else {
+                    let palette = if attributes.contains(Attributes::DMG_PALETTE) { self.obp1 } else { self.obp0 };
+                    let shade = shade(palette, color);
+                    self.plot(offset, shade, DMG_COLORS[shade as usize]);
+                }
+            }
+        }
+    }
+
+    /**
+     * On CGB a cleared BG_ENABLE bit puts all sprites on top, otherwise either
+     * the sprite or the background tile can request to draw the background over the sprite.
+     */
+    fn is_behind_background(&self, sprite: Attributes, background: Attributes) -> bool {
+        if self.cgb {
+            self.control.contains(Control::BG_ENABLE)
+                && (sprite.contains(Attributes::PRIORITY) || background.contains(Attributes::PRIORITY))
+        }else {
+            sprite.contains(Attributes::PRIORITY)
+        }
+    }
+}
+
+fn shade(palette: u8, color: u8) -> u8 {
+    (palette >> (color * 2)) & 0b11
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled() -> Ppu {
+        let mut ppu = Ppu::default();
+        ppu.write(LCDC, 0x91);
+        ppu.write(BGP, 0xE4);
+        ppu
+    }
+
+    #[test]
+    fn it_should_advance_lines() {
+        let mut ppu = enabled();
+        ppu.step(LINE_CYCLES * 3);
+        assert_eq!(ppu.read(LY), 3);
+        assert_eq!(ppu.mode(), Mode::OamScan);
+    }
+
+    #[test]
+    fn it_should_request_vblank_after_144_lines() {
+        let mut ppu = enabled();
+        let interrupts = ppu.step(LINE_CYCLES * 144);
+        assert!(interrupts.contains(Interrupt::VBLANK));
+        assert_eq!(ppu.mode(), Mode::VBlank);
+        assert_eq!(ppu.frame(), 1);
+    }
+
+    #[test]
+    fn it_should_wrap_around_after_154_lines() {
+        let mut ppu = enabled();
+        ppu.step(FRAME_CYCLES);
+        assert_eq!(ppu.read(LY), 0);
+    }
+
+    #[test]
+    fn it_should_count_frames_while_disabled() {
+        let mut ppu = Ppu::default();
+        ppu.step(FRAME_CYCLES);
+        assert_eq!(ppu.frame(), 1);
+        assert_eq!(ppu.read(LY), 0);
+    }
+
+    #[test]
+    fn it_should_request_stat_on_lyc_match() {
+        let mut ppu = enabled();
+        ppu.write(LYC, 2);
+        ppu.write(STAT, 0x40);
+        let interrupts = ppu.step(LINE_CYCLES * 2);
+        assert!(interrupts.contains(Interrupt::LCD_STAT));
+        assert_eq!(ppu.read(STAT) & 0b0000_0100, 0b0000_0100);
+    }
+
+    #[test]
+    fn it_should_switch_vram_banks_on_cgb() {
+        let mut ppu = Ppu::new(true);
+        ppu.write(0x8000, 0x11);
+        ppu.write(VBK, 1);
+        assert_eq!(ppu.read(0x8000), 0x00);
+        ppu.write(0x8000, 0x22);
+        assert_eq!(ppu.read(VBK), 0xFF);
+        ppu.write(VBK, 0);
+        assert_eq!(ppu.read(0x8000), 0x11);
+    }
+
+    #[test]
+    fn it_should_ignore_vbk_on_dmg() {
+        let mut ppu = Ppu::default();
+        ppu.write(VBK, 1);
+        ppu.write(0x8000, 0x11);
+        assert_eq!(ppu.read(VBK), 0xFF);
+        assert_eq!(ppu.read(0x8000), 0x11);
+    }
+
+    #[test]
+    fn it_should_render_background_tiles() {
+        let mut ppu = enabled();
+        // tile 1: first row fully color 3
+        ppu.write(0x8010, 0xFF);
+        ppu.write(0x8011, 0xFF);
+        ppu.write(0x9800, 0x01);
+        ppu.step(LINE_CYCLES);
+        assert_eq!(&ppu.framebuffer()[0..9], &[3, 3, 3, 3, 3, 3, 3, 3, 0]);
+    }
+
+    fn cgb() -> Ppu {
+        let mut ppu = Ppu::new(true);
+        ppu.write(LCDC, 0x93);
+        // background palette 2, color 1 = red, object palette 0 color 1 = blue, palette 1 color 1 = green
+        ppu.write(BCPS, 0x80 | (2 * 8 + 2));
+        ppu.write(BCPD, 0x1F);
+        ppu.write(BCPD, 0x00);
+        ppu.write(OCPS, 0x82);
+        ppu.write(OCPD, 0x00);
+        ppu.write(OCPD, 0x7C);
+        ppu.write(OCPS, 0x8A);
+        ppu.write(OCPD, 0xE0);
+        ppu.write(OCPD, 0x03);
+        // tile 1: first row color 1 in bank 0, tile 1 in bank 1: first row color 2
+        ppu.write(0x8010, 0xFF);
+        ppu.write(VBK, 1);
+        ppu.write(0x8011, 0xFF);
+        ppu.write(VBK, 0);
+        ppu
+    }
+
+    #[test]
+    fn it_should_access_palette_memory_on_cgb() {
+        let mut ppu = Ppu::new(true);
+        ppu.write(BCPS, 0x80);
+        ppu.write(BCPD, 0x12);
+        ppu.write(BCPD, 0x34);
+        ppu.write(BCPS, 0x01);
+        assert_eq!(ppu.read(BCPD), 0x34);
+        assert_eq!(ppu.read(BCPS), 0x41);
+    }
+
+    #[test]
+    fn it_should_ignore_palette_memory_on_dmg() {
+        let mut ppu = Ppu::default();
+        ppu.write(OCPS, 0x80);
+        assert_eq!(ppu.read(OCPS), 0xFF);
+        assert_eq!(ppu.read(OCPD), 0xFF);
+    }
+
+    #[test]
+    fn it_should_render_background_attributes_on_cgb() {
+        let mut ppu = cgb();
+        ppu.write(0x9800, 0x01);
+        ppu.write(VBK, 1);
+        // palette 2, tile from bank 0, horizontally flipped
+        ppu.write(0x9800, 0b0010_0010);
+        ppu.write(0x9801, 0b0010_1010);
+        ppu.write(VBK, 0);
+        ppu.write(0x9801, 0x01);
+        ppu.step(LINE_CYCLES);
+        assert_eq!(ppu.screen()[0], 0x001F);
+        assert_eq!(ppu.framebuffer()[0], 1);
+        assert_eq!(ppu.framebuffer()[8], 2);
+    }
+
+    #[test]
+    fn it_should_convert_the_screen_with_the_color_correction() {
+        let mut ppu = cgb();
+        ppu.write(0x9800, 0x01);
+        ppu.write(VBK, 1);
+        ppu.write(0x9800, 0b0000_0010);
+        ppu.write(VBK, 0);
+        ppu.step(LINE_CYCLES);
+        let screen = Screen { width: WIDTH, height: HEIGHT, colors: ppu.screen() };
+        assert_eq!(&screen.rgb(ColorCorrection::Raw)[..3], &[0xFF, 0x00, 0x00]);
+        assert_eq!(&screen.rgb(ColorCorrection::Lcd)[..3], &[0xC9, 0x00, 0x2E]);
+    }
+
+    #[test]
+    fn it_should_draw_sprites_by_oam_index_on_cgb() {
+        let mut ppu = cgb();
+        // the second sprite is further left but the first one has the higher priority
+        ppu.write(0xFE00, 16);
+        ppu.write(0xFE01, 8 + 4);
+        ppu.write(0xFE02, 1);
+        ppu.write(0xFE03, 0);
+        ppu.write(0xFE04, 16);
+        ppu.write(0xFE05, 8);
+        ppu.write(0xFE06, 1);
+        ppu.write(0xFE07, 1);
+        ppu.step(LINE_CYCLES);
+        assert_eq!(ppu.screen()[3], 0x03E0);
+        assert_eq!(ppu.screen()[4], 0x7C00);
+    }
+
+    #[test]
+    fn it_should_draw_the_background_over_sprites_with_priority_on_cgb() {
+        let mut ppu = cgb();
+        ppu.write(0x9800, 0x01);
+        ppu.write(VBK, 1);
+        ppu.write(0x9800, 0b1000_0010);
+        ppu.write(VBK, 0);
+        ppu.write(0xFE00, 16);
+        ppu.write(0xFE01, 8);
+        ppu.write(0xFE02, 1);
+        ppu.step(LINE_CYCLES);
+        assert_eq!(ppu.screen()[0], 0x001F);
+
+        // without BG_ENABLE sprites are always on top
+        let mut ppu = cgb();
+        ppu.write(LCDC, 0x92);
+        ppu.write(0x9800, 0x01);
+        ppu.write(VBK, 1);
+        ppu.write(0x9800, 0b1000_0010);
+        ppu.write(VBK, 0);
+        ppu.write(0xFE00, 16);
+        ppu.write(0xFE01, 8);
+        ppu.write(0xFE02, 1);
+        ppu.step(LINE_CYCLES);
+        assert_eq!(ppu.screen()[0], 0x7C00);
+    }
+
+    #[test]
+    fn it_should_render_sprites() {
+        let mut ppu = enabled();
+        ppu.write(LCDC, 0x93);
+        ppu.write(OBP0, 0xE4);
+        ppu.write(0x8010, 0xFF);
+        ppu.write(0x8011, 0x00);
+        ppu.write(0xFE00, 16);
+        ppu.write(0xFE01, 8 + 4);
+        ppu.write(0xFE02, 1);
+        ppu.step(LINE_CYCLES);
+        assert_eq!(&ppu.framebuffer()[3..6], &[0, 1, 1]);
+    }
+}
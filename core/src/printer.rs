@@ -0,0 +1,478 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use png;
+use serial::SerialDevice;
+
+pub const WIDTH: usize = 160;
+const TILES_PER_ROW: usize = WIDTH / 8;
+const BYTES_PER_TILE: usize = 16;
+const BYTES_PER_TILE_ROW: usize = TILES_PER_ROW * BYTES_PER_TILE;
+/// Height of a single paper feed of the margin settings
+const FEED_HEIGHT: usize = 8;
+/// The printer RAM can hold 9 data packets (160x144 pixels)
+const BUFFER_SIZE: usize = 0x280 * 9;
+
+const MAGIC: [u8; 2] = [0x88, 0x33];
+const ALIVE: u8 = 0x81;
+/// Number of status requests the printer reports itself as busy after printing
+const BUSY_STATUS_REQUESTS: u8 = 2;
+
+const SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+bitflags! {
+    #[derive(Default)]
+    pub struct Status: u8 {
+        const CHECKSUM_ERROR = 0b0000_0001;
+        const PRINTING = 0b0000_0010;
+        const IMAGE_DATA_FULL = 0b0000_0100;
+        const UNPROCESSED_DATA = 0b0000_1000;
+        const PACKET_ERROR = 0b0001_0000;
+        const PAPER_JAM = 0b0010_0000;
+        const OTHER_ERROR = 0b0100_0000;
+        const LOW_BATTERY = 0b1000_0000;
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Command {
+    Initialize,
+    Print,
+    Data,
+    Status,
+    Unknown(u8)
+}
+
+impl From<u8> for Command {
+    fn from(command: u8) -> Command {
+        match command {
+            0x01 => Command::Initialize,
+            0x02 => Command::Print,
+            0x04 => Command::Data,
+            0x0F => Command::Status,
+            _ => Command::Unknown(command)
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum State {
+    Magic(usize),
+    Command,
+    Compression,
+    Length(usize),
+    Data,
+    Checksum(usize),
+    Alive,
+    Status
+}
+
+/**
+ * A single printed image in 2 bit shades.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Printout {
+    pub height: usize,
+    pub pixels: Vec<u8>
+}
+
+impl Printout {
+    /**
+     * Converts the shades into 8 bit grayscale pixels.
+     */
+    pub fn grayscale(&self) -> Vec<u8> {
+        self.pixels.iter()
+            .map(|shade| SHADES[*shade as usize])
+            .collect()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let file = File::create(path)?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), WIDTH as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.grayscale())?;
+        Ok(())
+    }
+}
+
+/**
+ * Game Boy Printer
+ *
+ * Packets are sent as `0x88 0x33 command compression length(LE) data checksum(LE)`
+ * followed by two bytes for which the printer answers with 0x81 and its status.
+ *
+ * Every print command is written as a PNG image into the output directory.
+ * The exposure setting is ignored.
+ */
+pub struct Printer {
+    directory: PathBuf,
+    state: State,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    packet: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+    status: Status,
+    busy: u8,
+    buffer: Vec<u8>,
+    printouts: usize
+}
+
+impl Printer {
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Printer {
+        Printer {
+            directory: directory.into(),
+            state: State::Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            packet: Vec::new(),
+            checksum: 0,
+            received_checksum: 0,
+            status: Status::empty(),
+            busy: 0,
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+            printouts: 0
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    /**
+     * Number of images printed so far.
+     */
+    pub fn printouts(&self) -> usize {
+        self.printouts
+    }
+
+    fn receive(&mut self, byte: u8) -> u8 {
+        match self.state {
+            State::Magic(i) => {
+                self.state = if byte == MAGIC[i] {
+                    if i == 0 { State::Magic(1) } else { State::Command }
+                }else if byte == MAGIC[0] {
+                    State::Magic(1)
+                }else {
+                    State::Magic(0)
+                };
+                0x00
+            },
+            State::Command => {
+                self.command = byte;
+                self.checksum = byte as u16;
+                self.state = State::Compression;
+                0x00
+            },
+            State::Compression => {
+                self.compressed = byte & 0x01 != 0;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.state = State::Length(0);
+                0x00
+            },
+            State::Length(i) => {
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                if i == 0 {
+                    self.length = byte as u16;
+                    self.state = State::Length(1);
+                }else {
+                    self.length |= (byte as u16) << 8;
+                    self.packet.clear();
+                    self.state = if self.length == 0 { State::Checksum(0) } else { State::Data };
+                }
+                0x00
+            },
+            State::Data => {
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.packet.push(byte);
+                if self.packet.len() == self.length as usize {
+                    self.state = State::Checksum(0);
+                }
+                0x00
+            },
+            State::Checksum(i) => {
+                if i == 0 {
+                    self.received_checksum = byte as u16;
+                    self.state = State::Checksum(1);
+                }else {
+                    self.received_checksum |= (byte as u16) << 8;
+                    self.state = State::Alive;
+                }
+                0x00
+            },
+            State::Alive => {
+                self.state = State::Status;
+                ALIVE
+            },
+            State::Status => {
+                self.state = State::Magic(0);
+                if self.received_checksum == self.checksum {
+                    self.status.remove(Status::CHECKSUM_ERROR);
+                    self.execute();
+                }else {
+                    self.status.insert(Status::CHECKSUM_ERROR);
+                }
+                self.status.bits()
+            }
+        }
+    }
+
+    fn execute(&mut self) {
+        match Command::from(self.command) {
+            Command::Initialize => {
+                self.buffer.clear();
+                self.busy = 0;
+                self.status = Status::empty();
+            },
+            Command::Data => {
+                let data = if self.compressed {
+                    decompress(&self.packet)
+                }else {
+                    self.packet.clone()
+                };
+                let free = BUFFER_SIZE - self.buffer.len();
+                self.buffer.extend(data.into_iter().take(free));
+                self.status.set(Status::UNPROCESSED_DATA, !self.buffer.is_empty());
+                self.status.set(Status::IMAGE_DATA_FULL, self.buffer.len() == BUFFER_SIZE);
+            },
+            Command::Print => {
+                if self.packet.len() < 4 {
+                    self.status.insert(Status::PACKET_ERROR);
+                    return;
+                }
+                let margins = self.packet[1];
+                let palette = self.packet[2];
+                let printout = render(&self.buffer, palette, margins);
+                // nothing to feed, an image without lines can't be written
+                if printout.height > 0 {
+                    let path = self.directory.join(format!("print-{:04}.png", self.printouts));
+                    if let Err(err) = printout.save(&path) {
+                        warn!("Unable to write printout {}: {}", path.display(), err);
+                        self.status.insert(Status::OTHER_ERROR);
+                    }
+                    self.printouts += 1;
+                }
+                self.buffer.clear();
+                self.busy = BUSY_STATUS_REQUESTS;
+                self.status.remove(Status::UNPROCESSED_DATA | Status::IMAGE_DATA_FULL);
+                self.status.insert(Status::PRINTING);
+            },
+            Command::Status => {
+                if self.busy > 0 {
+                    self.busy -= 1;
+                }else {
+                    self.status.remove(Status::PRINTING);
+                }
+            },
+            Command::Unknown(_) => self.status.insert(Status::PACKET_ERROR)
+        }
+    }
+}
+
+impl SerialDevice for Printer {
+    fn exchange(&mut self, data: u8) -> u8 {
+        self.receive(data)
+    }
+}
+
+/**
+ * Run length decoding of compressed data packets.
+ *
+ * A control byte with bit 7 set repeats the following byte `(n & 0x7F) + 2` times,
+ * otherwise the next `n + 1` bytes are copied as is.
+ */
+fn decompress(packet: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut i = 0;
+    while i < packet.len() {
+        let control = packet[i];
+        i += 1;
+        if control & 0x80 != 0 {
+            let count = (control & 0x7F) as usize + 2;
+            if let Some(byte) = packet.get(i) {
+                let len = data.len();
+                data.resize(len + count, *byte);
+            }
+            i += 1;
+        }else {
+            let count = control as usize + 1;
+            let end = (i + count).min(packet.len());
+            data.extend_from_slice(&packet[i..end]);
+            i = end;
+        }
+    }
+    data
+}
+
+/**
+ * Decodes the 2bpp tile data in the buffer into shades.
+ *
+ * The upper nibble of `margins` are the feeds before the image, the lower nibble the feeds after it.
+ */
+fn render(buffer: &[u8], palette: u8, margins: u8) -> Printout {
+    let before = (margins >> 4) as usize * FEED_HEIGHT;
+    let after = (margins & 0x0F) as usize * FEED_HEIGHT;
+    let tile_rows = buffer.len() / BYTES_PER_TILE_ROW;
+    let image_height = tile_rows * 8;
+    let height = before + image_height + after;
+    let mut pixels = vec![0; WIDTH * height];
+
+    for tile_row in 0..tile_rows {
+        for tile in 0..TILES_PER_ROW {
+            let offset = tile_row * BYTES_PER_TILE_ROW + tile * BYTES_PER_TILE;
+            for line in 0..8 {
+                let low = buffer[offset + line * 2];
+                let high = buffer[offset + line * 2 + 1];
+                let y = before + tile_row * 8 + line;
+                for bit in 0..8 {
+                    let color = ((high >> (7 - bit)) & 1) << 1 | ((low >> (7 - bit)) & 1);
+                    let shade = (palette >> (color * 2)) & 0b11;
+                    pixels[y * WIDTH + tile * 8 + bit] = shade;
+                }
+            }
+        }
+    }
+
+    Printout {
+        height,
+        pixels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use byteorder::{ByteOrder, LittleEndian};
+
+    fn checksum(packet: &[u8]) -> u16 {
+        packet.iter().fold(0u16, |sum, byte| sum.wrapping_add(*byte as u16))
+    }
+
+    fn packet(command: u8, compression: u8, data: &[u8]) -> Vec<u8> {
+        let mut body = vec![command, compression, 0, 0];
+        LittleEndian::write_u16(&mut body[2..4], data.len() as u16);
+        body.extend_from_slice(data);
+        let sum = checksum(&body);
+        let mut packet = MAGIC.to_vec();
+        packet.extend(body);
+        packet.push(sum as u8);
+        packet.push((sum >> 8) as u8);
+        packet.push(0x00);
+        packet.push(0x00);
+        packet
+    }
+
+    fn send(printer: &mut Printer, packet: &[u8]) -> (u8, u8) {
+        let responses: Vec<u8> = packet.iter()
+            .map(|byte| printer.exchange(*byte))
+            .collect();
+        (responses[responses.len() - 2], responses[responses.len() - 1])
+    }
+
+    fn output_directory(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("gb-rs-printer-{}", name));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn it_should_answer_alive_and_status() {
+        let mut printer = Printer::new(env::temp_dir());
+        let (alive, status) = send(&mut printer, &packet(0x01, 0, &[]));
+        assert_eq!(alive, 0x81);
+        assert_eq!(status, 0x00);
+    }
+
+    #[test]
+    fn it_should_report_checksum_errors() {
+        let mut printer = Printer::new(env::temp_dir());
+        let mut data = packet(0x01, 0, &[]);
+        data[6] = 0xFF;
+        let (_, status) = send(&mut printer, &data);
+        assert_eq!(status, Status::CHECKSUM_ERROR.bits());
+    }
+
+    #[test]
+    fn it_should_report_unprocessed_data() {
+        let mut printer = Printer::new(env::temp_dir());
+        let (_, status) = send(&mut printer, &packet(0x04, 0, &[0; 0x280]));
+        assert_eq!(status, Status::UNPROCESSED_DATA.bits());
+    }
+
+    #[test]
+    fn it_should_decompress_runs_and_literals() {
+        let data = decompress(&[0x81, 0xAB, 0x01, 0x12, 0x34]);
+        assert_eq!(data, vec![0xAB, 0xAB, 0xAB, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn it_should_decode_tiles_with_the_palette() {
+        let mut buffer = vec![0; BYTES_PER_TILE_ROW];
+        // first row of the first tile: colors 3, 2, 1, 0, 0, 0, 0, 0
+        buffer[0] = 0b1010_0000;
+        buffer[1] = 0b1100_0000;
+        let printout = render(&buffer, 0xE4, 0x00);
+        assert_eq!(printout.height, 8);
+        assert_eq!(&printout.pixels[0..4], &[3, 2, 1, 0]);
+
+        let inverted = render(&buffer, 0x1B, 0x00);
+        assert_eq!(&inverted.pixels[0..4], &[0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn it_should_add_margins() {
+        let buffer = vec![0xFF; BYTES_PER_TILE_ROW];
+        let printout = render(&buffer, 0xE4, 0x12);
+        assert_eq!(printout.height, FEED_HEIGHT + 8 + 2 * FEED_HEIGHT);
+        assert_eq!(printout.pixels[0], 0);
+        assert_eq!(printout.pixels[FEED_HEIGHT * WIDTH], 3);
+    }
+
+    #[test]
+    fn it_should_write_a_png_for_every_print() {
+        let directory = output_directory("print");
+        let mut printer = Printer::new(directory.clone());
+        send(&mut printer, &packet(0x01, 0, &[]));
+        send(&mut printer, &packet(0x04, 0, &[0xFF; 0x280]));
+        send(&mut printer, &packet(0x04, 0, &[]));
+        let (_, status) = send(&mut printer, &packet(0x02, 0, &[0x01, 0x00, 0xE4, 0x40]));
+        assert!(Status::from_bits_truncate(status).contains(Status::PRINTING));
+        assert_eq!(printer.printouts(), 1);
+
+        let decoder = png::Decoder::new(File::open(directory.join("print-0000.png")).unwrap());
+        let reader = decoder.read_info().unwrap();
+        assert_eq!(reader.info().width, 160);
+        assert_eq!(reader.info().height, 16);
+    }
+
+    #[test]
+    fn it_should_skip_empty_printouts() {
+        let directory = output_directory("empty");
+        let mut printer = Printer::new(directory.clone());
+        let (_, status) = send(&mut printer, &packet(0x02, 0, &[0x01, 0x00, 0xE4, 0x40]));
+        assert!(!Status::from_bits_truncate(status).contains(Status::OTHER_ERROR));
+        assert_eq!(printer.printouts(), 0);
+        assert!(!directory.join("print-0000.png").exists());
+    }
+
+    #[test]
+    fn it_should_finish_printing_after_some_status_requests() {
+        let directory = output_directory("status");
+        let mut printer = Printer::new(directory);
+        send(&mut printer, &packet(0x04, 0, &[0; 0x280]));
+        send(&mut printer, &packet(0x02, 0, &[0x01, 0x00, 0xE4, 0x40]));
+        for _ in 0..BUSY_STATUS_REQUESTS {
+            let (_, status) = send(&mut printer, &packet(0x0F, 0, &[]));
+            assert_eq!(status, Status::PRINTING.bits());
+        }
+        let (_, status) = send(&mut printer, &packet(0x0F, 0, &[]));
+        assert_eq!(status, 0x00);
+    }
+}
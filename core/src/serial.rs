@@ -0,0 +1,257 @@
+pub const SB: u16 = 0xFF01;
+pub const SC: u16 = 0xFF02;
+
+const TRANSFER_START: u8 = 0b1000_0000;
+const INTERNAL_CLOCK: u8 = 0b0000_0001;
+
+/// The internal clock shifts one bit every 512 clock cycles (8192 Hz)
+pub const BIT_CYCLES: u32 = 512;
+
+/**
+ * Something plugged into the link port.
+ *
+ * The Game Boy drives the clock, so every transfer is initiated by it:
+ * `data` is the byte shifted out of SB, the returned byte is shifted in.
+ */
+pub trait SerialDevice {
+    fn exchange(&mut self, data: u8) -> u8;
+}
+
+enum Connection {
+    Disconnected,
+    Device(Box<dyn SerialDevice>),
+    Link
+}
+
+/**
+ * Serial port (SB 0xFF01, SC 0xFF02)
+ *
+ * SB is shifted out with the most significant bit first while the received bits are shifted in,
+ * a transfer ends after 8 bits.
+ */
+pub struct Serial {
+    data: u8,
+    control: u8,
+    connection: Connection,
+    /// Byte the connected device sends during the current transfer
+    incoming: u8,
+    /// Bits shifted during the current transfer
+    bits: u8,
+    cycles: u32,
+    /// Clock pulses of a linked master which `link::LinkedGameBoys` didn't shift yet
+    clocks: u32
+}
+
+impl Default for Serial {
+    fn default() -> Serial {
+        Serial {
+            data: 0,
+            control: 0,
+            connection: Connection::Disconnected,
+            incoming: 0xFF,
+            bits: 0,
+            cycles: 0,
+            clocks: 0
+        }
+    }
+}
+
+impl Serial {
+    pub fn connect(&mut self, device: Box<dyn SerialDevice>) {
+        self.connection = Connection::Device(device);
+    }
+
+    /**
+     * Leaves shifting the bits to an external coordinator which takes the clock pulses
+     * of the master, see `link::LinkedGameBoys`.
+     */
+    pub fn connect_link(&mut self) {
+        self.connection = Connection::Link;
+    }
+
+    pub fn disconnect(&mut self) {
+        self.connection = Connection::Disconnected;
+    }
+
+    pub fn read_data(&self) -> u8 {
+        self.data
+    }
+
+    pub fn read_control(&self) -> u8 {
+        // unused bits always read as 1
+        self.control | 0b0111_1110
+    }
+
+    pub fn write_data(&mut self, data: u8) {
+        self.data = data;
+    }
+
+    /**
+     * Starts a transfer when bit 7 is set, a device answers with the byte it shifts in bit by bit.
+     */
+    pub fn write_control(&mut self, control: u8) {
+        self.control = control & (TRANSFER_START | INTERNAL_CLOCK);
+        self.bits = 0;
+        self.cycles = 0;
+        self.clocks = 0;
+        if !self.is_master() {
+            return;
+        }
+        self.incoming = match self.connection {
+            Connection::Disconnected | Connection::Link => 0xFF,
+            Connection::Device(ref mut device) => device.exchange(self.data)
+        };
+    }
+
+    pub fn is_transfer_requested(&self) -> bool {
+        self.control & TRANSFER_START != 0
+    }
+
+    /**
+     * A transfer was requested using the internal clock, this side drives the exchange.
+     */
+    pub fn is_master(&self) -> bool {
+        self.is_transfer_requested() && self.control & INTERNAL_CLOCK != 0
+    }
+
+    /**
+     * Advances the internal clock by the given amount of CPU clock cycles.
+     *
+     * Returns true when the transfer finished and the serial interrupt should be requested.
+     */
+    pub fn step(&mut self, cycles: u32) -> bool {
+        if !self.is_master() {
+            return false;
+        }
+        self.cycles += cycles;
+        let mut finished = false;
+        while self.cycles >= BIT_CYCLES && self.is_master() {
+            self.cycles -= BIT_CYCLES;
+            if let Connection::Link = self.connection {
+                self.clocks += 1;
+            }else {
+                let received = self.incoming & 0x80 != 0;
+                self.incoming <<= 1;
+                finished = self.shift(received);
+            }
+        }
+        finished
+    }
+
+    /**
+     * Hands over the clock pulses of a linked master since the last call.
+     */
+    pub fn take_clocks(&mut self) -> u32 {
+        let clocks = self.clocks;
+        self.clocks = 0;
+        clocks
+    }
+
+    /**
+     * The bit which is shifted out next.
+     */
+    pub fn output(&self) -> bool {
+        self.data & 0x80 != 0
+    }
+
+    /**
+     * Shifts in `received` on a clock pulse.
+     *
+     * SB shifts even without a requested transfer, but only a requested one ends after 8 bits.
+     * Returns true when this finished the transfer.
+     */
+    pub fn shift(&mut self, received: bool) -> bool {
+        self.data = self.data << 1 | received as u8;
+        if !self.is_transfer_requested() {
+            return false;
+        }
+        self.bits += 1;
+        if self.bits < 8 {
+            return false;
+        }
+        self.bits = 0;
+        self.control &= !TRANSFER_START;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Echo;
+
+    impl SerialDevice for Echo {
+        fn exchange(&mut self, data: u8) -> u8 {
+            data.wrapping_add(1)
+        }
+    }
+
+    fn transfer(serial: &mut Serial) -> bool {
+        serial.write_data(0x42);
+        serial.write_control(0x81);
+        serial.step(8 * BIT_CYCLES)
+    }
+
+    #[test]
+    fn it_should_receive_0xff_when_disconnected() {
+        let mut serial = Serial::default();
+        let finished = transfer(&mut serial);
+        assert!(finished);
+        assert_eq!(serial.read_data(), 0xFF);
+        assert!(!serial.is_transfer_requested());
+    }
+
+    #[test]
+    fn it_should_exchange_with_the_connected_device() {
+        let mut serial = Serial::default();
+        serial.connect(Box::new(Echo));
+        transfer(&mut serial);
+        assert_eq!(serial.read_data(), 0x43);
+    }
+
+    #[test]
+    fn it_should_shift_one_bit_per_clock() {
+        let mut serial = Serial::default();
+        serial.connect(Box::new(Echo));
+        serial.write_data(0x42);
+        serial.write_control(0x81);
+        assert!(!serial.step(BIT_CYCLES - 1));
+        assert_eq!(serial.read_data(), 0x42);
+        assert!(!serial.step(1));
+        // 0x42 << 1 with the top bit of 0x43 shifted in
+        assert_eq!(serial.read_data(), 0x84);
+        assert!(!serial.step(6 * BIT_CYCLES));
+        assert!(serial.is_transfer_requested());
+        assert!(serial.step(BIT_CYCLES));
+        assert_eq!(serial.read_data(), 0x43);
+    }
+
+    #[test]
+    fn it_should_wait_for_the_external_clock() {
+        let mut serial = Serial::default();
+        serial.write_control(0x80);
+        let finished = serial.step(8 * BIT_CYCLES);
+        assert!(!finished);
+        assert!(serial.is_transfer_requested());
+        assert!(!serial.is_master());
+    }
+
+    #[test]
+    fn it_should_leave_linked_clocks_to_the_coordinator() {
+        let mut serial = Serial::default();
+        serial.connect_link();
+        let finished = transfer(&mut serial);
+        assert!(!finished);
+        assert!(serial.is_master());
+        assert_eq!(serial.read_data(), 0x42);
+        assert_eq!(serial.take_clocks(), 8);
+        assert_eq!(serial.take_clocks(), 0);
+    }
+
+    #[test]
+    fn it_should_read_unused_control_bits_as_set() {
+        let serial = Serial::default();
+        assert_eq!(serial.read_control(), 0x7E);
+    }
+}
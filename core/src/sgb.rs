@@ -0,0 +1,601 @@
+use ppu::{self, Ppu};
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 224;
+
+/// Position of the Game Boy screen inside the border
+const SCREEN_X: usize = 48;
+const SCREEN_Y: usize = 40;
+
+const PACKET_SIZE: usize = 16;
+const PACKET_BITS: usize = PACKET_SIZE * 8;
+/// Attributes are set for each 8x8 cell of the Game Boy screen
+const CELLS_X: usize = ppu::WIDTH / 8;
+const CELLS_Y: usize = ppu::HEIGHT / 8;
+const TRANSFER_SIZE: usize = 0x1000;
+const BORDER_TILES: usize = 256;
+const BORDER_MAP: usize = 32 * 28;
+
+const PAL01: u8 = 0x00;
+const PAL23: u8 = 0x01;
+const PAL03: u8 = 0x02;
+const PAL12: u8 = 0x03;
+const ATTR_BLK: u8 = 0x04;
+const ATTR_LIN: u8 = 0x05;
+const ATTR_DIV: u8 = 0x06;
+const ATTR_CHR: u8 = 0x07;
+const PAL_SET: u8 = 0x0A;
+const PAL_TRN: u8 = 0x0B;
+const MLT_REQ: u8 = 0x11;
+const CHR_TRN: u8 = 0x13;
+const PCT_TRN: u8 = 0x14;
+const MASK_EN: u8 = 0x17;
+
+/**
+ * Data the SGB copies from the Game Boy screen at the next VBlank.
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Transfer {
+    /// Border tiles, false for tiles 0x00-0x7F, true for 0x80-0xFF
+    Tiles(bool),
+    /// Border map and palettes
+    Border,
+    /// System palettes selectable with PAL_SET
+    Palettes
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Mask {
+    None,
+    Freeze,
+    Black,
+    Color0
+}
+
+/**
+ * Super Game Boy
+ *
+ * The Game Boy sends command packets of 16 bytes by pulsing the select lines of the joypad port:
+ * both lines low resets the transfer, P14 low sends a 0 and P15 low a 1 bit, LSB first.
+ * A stop bit follows every packet. The lower 3 bits of the first byte contain the number of packets
+ * of the command, the upper 5 bits the command itself.
+ *
+ * The commands color the 2 bit shades of the Game Boy screen with 4 palettes assigned per 8x8 cell
+ * and draw a border around it, the result is a 256x224 RGB555 framebuffer.
+ */
+pub struct Sgb {
+    /// Last value written to P1
+    joypad: u8,
+    receiving: bool,
+    packet: [u8; PACKET_SIZE],
+    bit: usize,
+    command: Vec<u8>,
+    palettes: [[u16; 4]; 4],
+    system_palettes: Vec<u16>,
+    attributes: [u8; CELLS_X * CELLS_Y],
+    border_tiles: Vec<u8>,
+    border_map: Vec<u16>,
+    border_palettes: [[u16; 16]; 4],
+    transfer: Option<Transfer>,
+    mask: Mask,
+    players: u8,
+    player: u8,
+    framebuffer: Vec<u16>
+}
+
+impl Default for Sgb {
+    fn default() -> Sgb {
+        Sgb {
+            joypad: 0x30,
+            receiving: false,
+            packet: [0; PACKET_SIZE],
+            bit: 0,
+            command: Vec::new(),
+            palettes: [[0x7FFF, 0x56B5, 0x294A, 0x0000]; 4],
+            system_palettes: vec![0; 512 * 4],
+            attributes: [0; CELLS_X * CELLS_Y],
+            border_tiles: vec![0; BORDER_TILES * 32],
+            border_map: vec![0; BORDER_MAP],
+            border_palettes: [[0; 16]; 4],
+            transfer: None,
+            mask: Mask::None,
+            players: 1,
+            player: 0,
+            framebuffer: vec![0; WIDTH * HEIGHT]
+        }
+    }
+}
+
+impl Sgb {
+    /**
+     * Receives the packet bits and selects the joypad in multiplayer mode.
+     */
+    pub fn write_joypad(&mut self, value: u8) {
+        let value = value & 0x30;
+        let previous = self.joypad;
+        self.joypad = value;
+        match value {
+            0x00 => {
+                self.receiving = true;
+                self.bit = 0;
+                self.packet = [0; PACKET_SIZE];
+            },
+            0x10 | 0x20 if previous == 0x30 && self.receiving => self.receive_bit(value == 0x10),
+            0x30 if previous & 0x20 == 0 && !self.receiving && self.players > 1 => {
+                self.player = (self.player + 1) % self.players;
+            },
+            _ => {}
+        }
+    }
+
+    /**
+     * With MLT_REQ the lower nibble contains the selected joypad when no buttons are selected.
+     * Only the first joypad is connected.
+     */
+    pub fn read_joypad(&self, value: u8) -> u8 {
+        if self.players > 1 && value & 0x30 == 0x30 {
+            (value & 0xF0) | (0x0F - self.player)
+        }else if self.player != 0 {
+            value | 0x0F
+        }else {
+            value
+        }
+    }
+
+    fn receive_bit(&mut self, bit: bool) {
+        if self.bit == PACKET_BITS {
+            // stop bit
+            self.receiving = false;
+            let packet = self.packet;
+            self.receive_packet(&packet);
+            return;
+        }
+        if bit {
+            self.packet[self.bit / 8] |= 1 << (self.bit % 8);
+        }
+        self.bit += 1;
+    }
+
+    fn receive_packet(&mut self, packet: &[u8; PACKET_SIZE]) {
+        self.command.extend_from_slice(packet);
+        let packets = (self.command[0] & 0b111).max(1) as usize;
+        if self.command.len() >= packets * PACKET_SIZE {
+            let command = ::std::mem::take(&mut self.command);
+            self.execute(&command);
+        }
+    }
+
+    fn execute(&mut self, data: &[u8]) {
+        match data[0] >> 3 {
+            PAL01 => self.set_palettes(0, 1, &data[1..]),
+            PAL23 => self.set_palettes(2, 3, &data[1..]),
+            PAL03 => self.set_palettes(0, 3, &data[1..]),
+            PAL12 => self.set_palettes(1, 2, &data[1..]),
+            ATTR_BLK => self.attribute_blocks(&data[1..]),
+            ATTR_LIN => self.attribute_lines(&data[1..]),
+            ATTR_DIV => self.attribute_division(data[1], data[2] as usize),
+            ATTR_CHR => self.attribute_cells(&data[1..]),
+            PAL_SET => {
+                for (palette, number) in data[1..9].chunks(2).enumerate() {
+                    let number = (number[0] as usize | (number[1] as usize) << 8) & 0x1FF;
+                    for color in 0..4 {
+                        self.palettes[palette][color] = self.system_palettes[number * 4 + color];
+                    }
+                }
+                if data[9] & 0b0100_0000 != 0 {
+                    self.mask = Mask::None;
+                }
+            },
+            PAL_TRN => self.transfer = Some(Transfer::Palettes),
+            MLT_REQ => {
+                self.players = match data[1] & 0b11 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1
+                };
+                self.player = 0;
+            },
+            CHR_TRN => self.transfer = Some(Transfer::Tiles(data[1] & 1 != 0)),
+            PCT_TRN => self.transfer = Some(Transfer::Border),
+            MASK_EN => {
+                self.mask = match data[1] & 0b11 {
+                    1 => Mask::Freeze,
+                    2 => Mask::Black,
+                    3 => Mask::Color0,
+                    _ => Mask::None
+                };
+            },
+            _ => {}
+        }
+    }
+
+    /**
+     * Color 0 is shared by all palettes.
+     */
+    fn set_palettes(&mut self, first: usize, second: usize, data: &[u8]) {
+        let colors: Vec<u16> = data[..14]
+            .chunks(2)
+            .map(|color| (color[0] as u16 | (color[1] as u16) << 8) & 0x7FFF)
+            .collect();
+        for palette in self.palettes.iter_mut() {
+            palette[0] = colors[0];
+        }
+        self.palettes[first][1..].copy_from_slice(&colors[1..4]);
+        self.palettes[second][1..].copy_from_slice(&colors[4..7]);
+    }
+
+    /**
+     * Data sets of 6 bytes: control, palettes, x1, y1, x2, y2.
+     */
+    fn attribute_blocks(&mut self, data: &[u8]) {
+        let sets = (data[0] as usize).min(18);
+        for set in data[1..].chunks_exact(6).take(sets) {
+            let control = set[0] & 0b111;
+            let inside = set[1] & 0b11;
+            let border = set[1] >> 2 & 0b11;
+            let outside = set[1] >> 4 & 0b11;
+            // when only the inside or outside is changed the border takes the same palette
+            let border = match control {
+                0b001 => Some(inside),
+                0b100 => Some(outside),
+                _ if control & 0b010 != 0 => Some(border),
+                _ => None
+            };
+            let (x1, y1, x2, y2) = (set[2] as usize, set[3] as usize, set[4] as usize, set[5] as usize);
+            for y in 0..CELLS_Y {
+                for x in 0..CELLS_X {
+                    let palette = if x > x1 && x < x2 && y > y1 && y < y2 {
+                        if control & 0b001 != 0 { Some(inside) } else { None }
+                    }else if x >= x1 && x <= x2 && y >= y1 && y <= y2 {
+                        border
+                    }else if control & 0b100 != 0 {
+                        Some(outside)
+                    }else {
+                        None
+                    };
+                    if let Some(palette) = palette {
+                        self.attributes[y * CELLS_X + x] = palette;
+                    }
+                }
+            }
+        }
+    }
+
+    /**
+     * Each byte sets a row (bit 7 set) or column to the palette in bit 6-5.
+     */
+    fn attribute_lines(&mut self, data: &[u8]) {
+        let lines = data[0] as usize;
+        for line in data[1..].iter().take(lines) {
+            let index = (line & 0x1F) as usize;
+            let palette = line >> 5 & 0b11;
+            if line & 0x80 != 0 {
+                if index < CELLS_Y {
+                    for x in 0..CELLS_X {
+                        self.attributes[index * CELLS_X + x] = palette;
+                    }
+                }
+            }else if index < CELLS_X {
+                for y in 0..CELLS_Y {
+                    self.attributes[y * CELLS_X + index] = palette;
+                }
+            }
+        }
+    }
+
+    /**
+     * Divides the screen at a row (bit 6 set) or column into two palettes and the line itself.
+     */
+    fn attribute_division(&mut self, control: u8, position: usize) {
+        let after = control & 0b11;
+        let before = control >> 2 & 0b11;
+        let line = control >> 4 & 0b11;
+        let horizontal = control & 0b0100_0000 != 0;
+        for y in 0..CELLS_Y {
+            for x in 0..CELLS_X {
+                let cell = if horizontal { y } else { x };
+                self.attributes[y * CELLS_X + x] = if cell < position {
+                    before
+                }else if cell == position {
+                    line
+                }else {
+                    after
+                };
+            }
+        }
+    }
+
+    /**
+     * Sets the palettes of consecutive cells, 2 bits each starting with the MSB.
+     */
+    fn attribute_cells(&mut self, data: &[u8]) {
+        let (mut x, mut y) = (data[0] as usize, data[1] as usize);
+        let count = (data[2] as usize | (data[3] as usize) << 8).min(CELLS_X * CELLS_Y);
+        let vertical = data[4] & 1 != 0;
+        for i in 0..count {
+            let byte = match data.get(5 + i / 4) {
+                Some(byte) => *byte,
+                None => break
+            };
+            if x >= CELLS_X || y >= CELLS_Y {
+                break;
+            }
+            self.attributes[y * CELLS_X + x] = byte >> (6 - (i % 4) * 2) & 0b11;
+            if vertical {
+                y += 1;
+                if y == CELLS_Y {
+                    y = 0;
+                    x += 1;
+                }
+            }else {
+                x += 1;
+                if x == CELLS_X {
+                    x = 0;
+                    y += 1;
+                }
+            }
+        }
+    }
+
+    /**
+     * Finishes pending VRAM transfers and renders the frame.
+     */
+    pub fn vblank(&mut self, ppu: &Ppu) {
+        if let Some(transfer) = self.transfer.take() {
+            let data = vram_transfer(ppu);
+            match transfer {
+                Transfer::Tiles(high) => {
+                    let start = if high { TRANSFER_SIZE } else { 0 };
+                    self.border_tiles[start..start + TRANSFER_SIZE].copy_from_slice(&data);
+                },
+                Transfer::Border => {
+                    for (i, entry) in data[..BORDER_MAP * 2].chunks(2).enumerate() {
+                        self.border_map[i] = entry[0] as u16 | (entry[1] as u16) << 8;
+                    }
+                    for (i, color) in data[0x800..0x880].chunks(2).enumerate() {
+                        self.border_palettes[i / 16][i % 16] = (color[0] as u16 | (color[1] as u16) << 8) & 0x7FFF;
+                    }
+                },
+                Transfer::Palettes => {
+                    for (i, color) in data.chunks(2).enumerate() {
+                        self.system_palettes[i] = (color[0] as u16 | (color[1] as u16) << 8) & 0x7FFF;
+                    }
+                }
+            }
+        }
+        self.render(ppu.framebuffer());
+    }
+
+    fn render(&mut self, shades: &[u8]) {
+        let backdrop = self.palettes[0][0];
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let screen = (SCREEN_X..SCREEN_X + ppu::WIDTH).contains(&x) && (SCREEN_Y..SCREEN_Y + ppu::HEIGHT).contains(&y);
+                let offset = y * WIDTH + x;
+                if screen {
+                    let (x, y) = (x - SCREEN_X, y - SCREEN_Y);
+                    match self.mask {
+                        Mask::None => {
+                            let palette = self.attributes[(y / 8) * CELLS_X + x / 8] as usize;
+                            self.framebuffer[offset] = self.palettes[palette][shades[y * ppu::WIDTH + x] as usize];
+                        },
+                        Mask::Freeze => {},
+                        Mask::Black => self.framebuffer[offset] = 0,
+                        Mask::Color0 => self.framebuffer[offset] = backdrop
+                    }
+                }else {
+                    self.framebuffer[offset] = self.border_color(x, y).unwrap_or(backdrop);
+                }
+            }
+        }
+    }
+
+    /**
+     * Border tiles use the 4 bit planar SNES format, color 0 is transparent.
+     */
+    fn border_color(&self, x: usize, y: usize) -> Option<u16> {
+        let entry = self.border_map[(y / 8) * 32 + x / 8];
+        let tile = (entry & 0xFF) as usize;
+        let palette = ((entry >> 10 & 0b111) as usize).checked_sub(4)?;
+        let column = if entry & 0x4000 != 0 { x % 8 } else { 7 - x % 8 };
+        let row = if entry & 0x8000 != 0 { 7 - y % 8 } else { y % 8 };
+        let data = &self.border_tiles[tile * 32..tile * 32 + 32];
+        let color = [data[row * 2], data[row * 2 + 1], data[16 + row * 2], data[16 + row * 2 + 1]]
+            .iter()
+            .enumerate()
+            .fold(0, |color, (plane, byte)| color | ((byte >> column) & 1) << plane);
+        if color == 0 {
+            None
+        }else {
+            Some(self.border_palettes[palette][color as usize])
+        }
+    }
+
+    /**
+     * 256x224 RGB555 colors of the last frame including the border, row by row.
+     */
+    pub fn framebuffer(&self) -> &[u16] {
+        &self.framebuffer
+    }
+}
+
+/**
+ * VRAM transfers copy the 4KB of tile data the Game Boy displays in order on the next frame,
+ * games put tiles 0-255 of the selected tile data area on the screen for this.
+ */
+fn vram_transfer(ppu: &Ppu) -> Vec<u8> {
+    let start = if ppu.read(ppu::LCDC) & 0b0001_0000 != 0 { 0x8000 } else { 0x8800 };
+    (0..TRANSFER_SIZE as u16).map(|i| ppu.read(start + i)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(sgb: &mut Sgb, packet: &[u8]) {
+        let mut data = [0; PACKET_SIZE];
+        data[..packet.len()].copy_from_slice(packet);
+        sgb.write_joypad(0x00);
+        sgb.write_joypad(0x30);
+        for i in 0..PACKET_BITS {
+            let bit = data[i / 8] >> (i % 8) & 1 != 0;
+            sgb.write_joypad(if bit { 0x10 } else { 0x20 });
+            sgb.write_joypad(0x30);
+        }
+        sgb.write_joypad(0x20);
+        sgb.write_joypad(0x30);
+    }
+
+    fn command(command: u8, packets: u8) -> u8 {
+        command << 3 | packets
+    }
+
+    fn attribute(sgb: &Sgb, x: usize, y: usize) -> u8 {
+        sgb.attributes[y * CELLS_X + x]
+    }
+
+    #[test]
+    fn it_should_set_palettes() {
+        let mut sgb = Sgb::default();
+        send(&mut sgb, &[command(PAL12, 1), 0x1F, 0x00, 0x01, 0x00, 0x02, 0x00, 0x03, 0x00, 0x04, 0x00, 0x05, 0x00, 0x06, 0x00]);
+        assert_eq!(sgb.palettes[0][0], 0x001F);
+        assert_eq!(sgb.palettes[3][0], 0x001F);
+        assert_eq!(sgb.palettes[1], [0x001F, 1, 2, 3]);
+        assert_eq!(sgb.palettes[2], [0x001F, 4, 5, 6]);
+    }
+
+    #[test]
+    fn it_should_ignore_packets_without_stop_bit() {
+        let mut sgb = Sgb::default();
+        sgb.write_joypad(0x00);
+        sgb.write_joypad(0x30);
+        sgb.write_joypad(0x10);
+        sgb.write_joypad(0x00);
+        assert!(sgb.command.is_empty());
+        assert_eq!(sgb.packet[0], 0);
+    }
+
+    #[test]
+    fn it_should_set_attribute_blocks() {
+        let mut sgb = Sgb::default();
+        // inside palette 1, border palette 2 for cells 1,1 to 3,3
+        send(&mut sgb, &[command(ATTR_BLK, 1), 1, 0b011, 0b1001, 1, 1, 3, 3]);
+        assert_eq!(attribute(&sgb, 2, 2), 1);
+        assert_eq!(attribute(&sgb, 1, 2), 2);
+        assert_eq!(attribute(&sgb, 3, 3), 2);
+        assert_eq!(attribute(&sgb, 4, 4), 0);
+    }
+
+    #[test]
+    fn it_should_use_the_inside_palette_for_the_border_of_blocks() {
+        let mut sgb = Sgb::default();
+        send(&mut sgb, &[command(ATTR_BLK, 1), 1, 0b001, 0b11, 1, 1, 3, 3]);
+        assert_eq!(attribute(&sgb, 1, 1), 3);
+        assert_eq!(attribute(&sgb, 2, 2), 3);
+    }
+
+    #[test]
+    fn it_should_ignore_attribute_blocks_beyond_the_data() {
+        let mut sgb = Sgb::default();
+        // one packet holds two data sets and a partial third one
+        send(&mut sgb, &[command(ATTR_BLK, 1), 18, 0b001, 0b01, 0, 0, 2, 2, 0b001, 0b10, 4, 4, 6, 6, 0b001, 0b11]);
+        assert_eq!(attribute(&sgb, 1, 1), 1);
+        assert_eq!(attribute(&sgb, 5, 5), 2);
+        assert_eq!(attribute(&sgb, 10, 10), 0);
+    }
+
+    #[test]
+    fn it_should_set_attribute_lines() {
+        let mut sgb = Sgb::default();
+        send(&mut sgb, &[command(ATTR_LIN, 1), 2, 0x80 | 0x20 | 5, 0x40 | 7]);
+        assert_eq!(attribute(&sgb, 0, 5), 1);
+        assert_eq!(attribute(&sgb, 7, 0), 2);
+        assert_eq!(attribute(&sgb, 7, 5), 2);
+    }
+
+    #[test]
+    fn it_should_divide_attributes() {
+        let mut sgb = Sgb::default();
+        send(&mut sgb, &[command(ATTR_DIV, 1), 0b0110_0111, 10]);
+        assert_eq!(attribute(&sgb, 0, 9), 1);
+        assert_eq!(attribute(&sgb, 0, 10), 2);
+        assert_eq!(attribute(&sgb, 0, 11), 3);
+    }
+
+    #[test]
+    fn it_should_set_attributes_of_cells() {
+        let mut sgb = Sgb::default();
+        send(&mut sgb, &[command(ATTR_CHR, 1), 18, 0, 3, 0, 0, 0b0110_1100]);
+        assert_eq!(attribute(&sgb, 18, 0), 1);
+        assert_eq!(attribute(&sgb, 19, 0), 2);
+        assert_eq!(attribute(&sgb, 0, 1), 3);
+    }
+
+    #[test]
+    fn it_should_receive_commands_with_multiple_packets() {
+        let mut sgb = Sgb::default();
+        let mut first = [0u8; PACKET_SIZE];
+        first[0] = command(ATTR_LIN, 2);
+        first[1] = 15;
+        for line in first[2..].iter_mut() {
+            *line = 0x80 | 0x20;
+        }
+        send(&mut sgb, &first);
+        assert_eq!(attribute(&sgb, 0, 0), 0);
+        send(&mut sgb, &[0x80 | 0x40 | 17]);
+        assert_eq!(attribute(&sgb, 0, 0), 1);
+        assert_eq!(attribute(&sgb, 0, 17), 2);
+    }
+
+    #[test]
+    fn it_should_select_joypads_in_multiplayer_mode() {
+        let mut sgb = Sgb::default();
+        send(&mut sgb, &[command(MLT_REQ, 1), 1]);
+        assert_eq!(sgb.read_joypad(0xFF), 0xFF);
+        sgb.write_joypad(0x20);
+        sgb.write_joypad(0x10);
+        sgb.write_joypad(0x30);
+        assert_eq!(sgb.read_joypad(0xFF), 0xFE);
+        assert_eq!(sgb.read_joypad(0xEE), 0xEF);
+    }
+
+    #[test]
+    fn it_should_render_the_border() {
+        let mut sgb = Sgb::default();
+        let mut ppu = Ppu::default();
+        ppu.write(ppu::LCDC, 0x10);
+        // tile 1: first pixel color 5
+        ppu.write(0x8020, 0x80);
+        ppu.write(0x8030, 0x80);
+        send(&mut sgb, &[command(CHR_TRN, 1), 0]);
+        sgb.vblank(&ppu);
+
+        // map entry 0: tile 1, palette 4, palette 4 color 5 = red
+        for i in 0..TRANSFER_SIZE as u16 {
+            ppu.write(0x8000 + i, 0);
+        }
+        ppu.write(0x8000, 0x01);
+        ppu.write(0x8001, 0x10);
+        ppu.write(0x880A, 0x1F);
+        send(&mut sgb, &[command(PCT_TRN, 1)]);
+        sgb.vblank(&ppu);
+
+        assert_eq!(sgb.framebuffer()[0], 0x001F);
+        assert_eq!(sgb.framebuffer()[1], sgb.palettes[0][0]);
+    }
+
+    #[test]
+    fn it_should_color_the_screen_with_the_cell_palettes() {
+        let mut sgb = Sgb::default();
+        send(&mut sgb, &[command(PAL01, 1), 0, 0, 0x01, 0, 0, 0, 0, 0, 0x1F, 0]);
+        send(&mut sgb, &[command(ATTR_DIV, 1), 0b0000_0001, 1]);
+        sgb.render(&[1; ppu::WIDTH * ppu::HEIGHT]);
+        let screen = SCREEN_Y * WIDTH + SCREEN_X;
+        assert_eq!(sgb.framebuffer()[screen], 0x0001);
+        assert_eq!(sgb.framebuffer()[screen + 16], 0x001F);
+
+        send(&mut sgb, &[command(MASK_EN, 1), 2]);
+        sgb.render(&[1; ppu::WIDTH * ppu::HEIGHT]);
+        assert_eq!(sgb.framebuffer()[screen + 16], 0x0000);
+    }
+}
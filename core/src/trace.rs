@@ -0,0 +1,215 @@
+use cpu::register::Flags;
+use gameboy::GameBoy;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/**
+ * CPU state before an instruction, a line of the gameboy-doctor format, e.g.
+ * `A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02`.
+ */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Entry {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    /// The memory at PC
+    pub pcmem: [u8; 4]
+}
+
+impl Entry {
+    pub fn capture(gb: &GameBoy) -> Entry {
+        let register = &gb.register;
+        let pc = register.pc;
+        let mut pcmem = [0; 4];
+        for (i, byte) in pcmem.iter_mut().enumerate() {
+            *byte = gb.read(pc.wrapping_add(i as u16));
+        }
+        Entry {
+            a: register.a,
+            f: register.f.bits(),
+            b: register.b,
+            c: register.c,
+            d: register.d,
+            e: register.e,
+            h: register.h,
+            l: register.l,
+            sp: register.sp,
+            pc,
+            pcmem
+        }
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} \
+                   PCMEM:{:02X},{:02X},{:02X},{:02X}",
+               self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l, self.sp, self.pc,
+               self.pcmem[0], self.pcmem[1], self.pcmem[2], self.pcmem[3])
+    }
+}
+
+/**
+ * A trace line as `NAME:VALUE` fields, e.g. one written by another emulator.
+ *
+ * Lines may lack fields or have additional ones, only the fields of both lines are compared. Text after
+ * a `;` is a comment.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    fields: Vec<(String, String)>
+}
+
+impl Record {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields.iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /**
+     * The fields of both records with different values, in the order of this record.
+     */
+    pub fn diff(&self, actual: &Record) -> Vec<Difference> {
+        self.fields.iter()
+            .filter_map(|(field, expected)| {
+                let value = actual.get(field)?;
+                if same_value(expected, value) {
+                    None
+                }else {
+                    Some(Difference {
+                        field: field.to_uppercase(),
+                        expected: expected.clone(),
+                        actual: value.to_string()
+                    })
+                }
+            })
+            .collect()
+    }
+}
+
+/**
+ * Numbers are compared by value, so case and leading zeros don't matter.
+ */
+fn same_value(expected: &str, actual: &str) -> bool {
+    match (u32::from_str_radix(expected, 16), u32::from_str_radix(actual, 16)) {
+        (Ok(expected), Ok(actual)) => expected == actual,
+        _ => expected.eq_ignore_ascii_case(actual)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NoFields;
+
+impl fmt::Display for NoFields {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "no fields in the trace line")
+    }
+}
+
+impl Error for NoFields {}
+
+impl FromStr for Record {
+    type Err = NoFields;
+
+    fn from_str(line: &str) -> Result<Record, NoFields> {
+        let line = line.split(';').next().unwrap_or_default();
+        let fields: Vec<(String, String)> = line.split_whitespace()
+            .filter_map(|field| {
+                let index = field.find(':')?;
+                Some((field[..index].to_string(), field[index + 1..].to_string()))
+            })
+            .collect();
+        if fields.is_empty() {
+            Err(NoFields)
+        }else {
+            Ok(Record { fields })
+        }
+    }
+}
+
+/**
+ * A field with different values in two traces.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub field: String,
+    pub expected: String,
+    pub actual: String
+}
+
+impl Difference {
+    /**
+     * The flags which differ when this is the F register.
+     */
+    pub fn flags(&self) -> Vec<(&'static str, bool)> {
+        if self.field != "F" {
+            return Vec::new();
+        }
+        let (expected, actual) = match (u8::from_str_radix(&self.expected, 16), u8::from_str_radix(&self.actual, 16)) {
+            (Ok(expected), Ok(actual)) => (Flags::from_bits_truncate(expected), Flags::from_bits_truncate(actual)),
+            _ => return Vec::new()
+        };
+        [("Z", Flags::Z), ("N", Flags::N), ("H", Flags::H), ("C", Flags::C)].iter()
+            .filter(|&&(_, flag)| expected.contains(flag) != actual.contains(flag))
+            .map(|&(name, flag)| (name, expected.contains(flag)))
+            .collect()
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} is {} instead of {}", self.field, self.actual, self.expected)?;
+        let flags: Vec<String> = self.flags()
+            .iter()
+            .map(|&(name, expected)| format!("{} {}", name, if expected { "not set" } else { "set" }))
+            .collect();
+        if !flags.is_empty() {
+            write!(f, " ({})", flags.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_write_the_gameboy_doctor_format() {
+        let mut gb = GameBoy::new();
+        gb.skip_boot();
+        gb.register.pc = 0xC000;
+        for (i, &byte) in [0x00, 0xC3, 0x13, 0x02].iter().enumerate() {
+            gb.write(0xC000 + i as u16, byte);
+        }
+        assert_eq!(Entry::capture(&gb).to_string(),
+                   "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:C000 PCMEM:00,C3,13,02");
+    }
+
+    #[test]
+    fn it_should_compare_the_common_fields() {
+        let expected: Record = "A:01 F:B0 B:00 SP:FFFE PC:0100 PCMEM:00,C3,13,02".parse().unwrap();
+        let actual: Record = "a:01 f:b0 b:0 PC:0100 LY:00 ; Main".parse().unwrap();
+        assert!(expected.diff(&actual).is_empty());
+        assert!("; comment".parse::<Record>().is_err());
+    }
+
+    #[test]
+    fn it_should_name_the_differing_flags() {
+        let expected: Record = "A:01 F:A0 PC:0150".parse().unwrap();
+        let actual: Record = "A:01 F:80 PC:0150".parse().unwrap();
+        let differences = expected.diff(&actual);
+        assert_eq!(differences.len(), 1);
+        assert_eq!(differences[0].flags(), vec![("H", true)]);
+        assert_eq!(differences[0].to_string(), "F is 80 instead of A0 (H not set)");
+    }
+}
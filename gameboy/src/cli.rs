@@ -0,0 +1,378 @@
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use gb_core::{IllegalOpcodePolicy, Model};
+use gb_core::debugger::breakpoint::{Breakpoint, Location};
+use gb_core::debugger::expression::ParseError;
+use gb_core::debugger::symbols::Symbols;
+use gb_core::debugger::watchpoint::Watchpoint;
+use gb_core::palette::ColorCorrection;
+use log::LevelFilter;
+use trace::Trigger;
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(name = "gb-rs", version, about = "Game Boy emulator")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+
+    /// Verbosity of the messages printed to stderr
+    #[arg(long, global = true, value_enum, default_value_t = LogLevel::Warn)]
+    pub log_level: LogLevel
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the ROM without any output
+    Run {
+        #[command(flatten)]
+        emulation: Emulation,
+        /// Replay a movie and fail unless the screen and RAM at its end match the recording
+        #[arg(long, value_name = "MOVIE", conflicts_with = "play")]
+        verify: Option<PathBuf>
+    },
+    /// Run the ROM in the terminal interface
+    Gui {
+        #[command(flatten)]
+        emulation: Emulation,
+        /// Key bindings with one `button = key` pair per line
+        #[arg(long, value_name = "FILE")]
+        bindings: Option<PathBuf>,
+        /// Pause at `[bank:]address|symbol [if condition]`, e.g. `03:4A10 if A == 0x3F && [HL] != 0`
+        #[arg(long = "break", value_name = "BREAKPOINT", value_parser = breakpoint)]
+        breakpoints: Vec<String>,
+        /// Pause after accesses of `start[-end] [r|w|rw] [== value]`, e.g. `C000-C0FF w == 0`
+        #[arg(long = "watch", value_name = "WATCHPOINT")]
+        watchpoints: Vec<Watchpoint>,
+        /// Record the joypad input into a movie, written when quitting
+        #[arg(long, value_name = "MOVIE", conflicts_with = "play")]
+        record: Option<PathBuf>
+    },
+    /// Debug the ROM with commands like `break`, `step` or `x/16xb`, see `help` in the debugger
+    Debug {
+        #[command(flatten)]
+        emulation: Emulation,
+        /// Commands to run instead of reading them from the terminal, one per line
+        #[arg(long, value_name = "FILE")]
+        script: Option<PathBuf>
+    },
+    /// Debug the ROM with GDB over the remote serial protocol
+    Gdb {
+        #[command(flatten)]
+        emulation: Emulation,
+        /// Local TCP port to wait for GDB on
+        #[arg(long, default_value_t = 2159)]
+        port: u16
+    },
+    /// Write the CPU state before every instruction in the gameboy-doctor format
+    Trace(Tracing),
+    /// Find the first line where a trace differs from a reference trace
+    #[command(name = "tracediff")]
+    TraceDiff {
+        /// Trace of another emulator, e.g. a gameboy-doctor log
+        reference: PathBuf,
+        /// Trace to check, both may be compressed with gzip
+        trace: PathBuf,
+        /// Lines shown before and after the divergence
+        #[arg(long, default_value_t = 5)]
+        context: usize
+    },
+    /// Print the code and data of the ROM with labels
+    Disasm {
+        rom: PathBuf,
+        /// RGBDS `.sym` or `.map` file naming the labels, defaults to the one next to the ROM
+        #[arg(long, value_name = "FILE")]
+        symbols: Option<PathBuf>
+    },
+    /// Print the cartridge header
+    Info {
+        rom: PathBuf
+    }
+}
+
+/**
+ * Options of every subcommand which emulates the ROM.
+ */
+#[derive(Debug, Args)]
+pub struct Emulation {
+    pub rom: PathBuf,
+
+    /// Boot ROM to run before the cartridge, the boot is skipped without one
+    #[arg(long, value_name = "FILE")]
+    pub boot_rom: Option<PathBuf>,
+
+    /// Hardware to emulate, detected from the cartridge header by default
+    #[arg(long, value_enum)]
+    pub model: Option<ModelArg>,
+
+    /// Start at the cartridge entry point even when a boot ROM is given
+    #[arg(long)]
+    pub skip_boot: bool,
+
+    /// Stop after this many frames
+    #[arg(long, value_name = "FRAMES")]
+    pub frames: Option<u64>,
+
+    /// Directory of the battery backed saves, defaults to the directory of the ROM
+    #[arg(long, value_name = "DIR")]
+    pub save_dir: Option<PathBuf>,
+
+    /// Multiple of the real hardware speed, 0 runs as fast as possible
+    #[arg(long, default_value_t = 1.0)]
+    pub speed: f64,
+
+    /// What happens when the CPU executes one of the illegal opcodes
+    #[arg(long, value_enum, default_value_t = IllegalOpcodeArg::LockUp)]
+    pub illegal_opcodes: IllegalOpcodeArg,
+
+    /// RGBDS `.sym` or `.map` file, defaults to the one next to the ROM
+    #[arg(long, value_name = "FILE")]
+    pub symbols: Option<PathBuf>,
+
+    /// Replay the joypad input of a movie recorded with `gui --record`, starting at power on
+    #[arg(long, value_name = "MOVIE")]
+    pub play: Option<PathBuf>,
+
+    /// How the RGB555 colors of the screen are converted for screenshots
+    #[arg(long, value_enum, default_value_t = ColorCorrectionArg::Raw)]
+    pub color_correction: ColorCorrectionArg,
+
+    /// Write the last frame as a PNG once the emulation ends
+    #[arg(long, value_name = "FILE")]
+    pub screenshot: Option<PathBuf>,
+
+    /// Attach a Game Boy Printer to the link port, writing every printout as a PNG into this directory
+    #[arg(long, value_name = "DIR")]
+    pub printer: Option<PathBuf>
+}
+
+#[derive(Debug, Args)]
+pub struct Tracing {
+    #[command(flatten)]
+    pub emulation: Emulation,
+
+    /// File to write the trace to instead of stdout, compressed when it ends with `.gz`
+    #[arg(long, short, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    /// Start tracing at a breakpoint like `Main.loop if A == 0`, or at `frame N`
+    #[arg(long, value_name = "TRIGGER", value_parser = trigger)]
+    pub start: Option<String>,
+
+    /// Stop tracing and the emulation at a breakpoint, or at `frame N`
+    #[arg(long, value_name = "TRIGGER", value_parser = trigger)]
+    pub stop: Option<String>,
+
+    /// Compress the trace with gzip
+    #[arg(long)]
+    pub compress: bool,
+
+    /// Append the symbol at PC to every line, which gameboy-doctor doesn't accept
+    #[arg(long)]
+    pub annotate: bool
+}
+
+/**
+ * Checks the syntax of a breakpoint, symbols are resolved once the symbol file is loaded.
+ */
+fn breakpoint(breakpoint: &str) -> Result<String, ParseError> {
+    Breakpoint::parse(breakpoint, &any_symbol(breakpoint)).map(|_| breakpoint.to_string())
+}
+
+fn trigger(trigger: &str) -> Result<String, ParseError> {
+    Trigger::parse(trigger, &any_symbol(trigger)).map(|_| trigger.to_string())
+}
+
+/**
+ * Resolves the location of the breakpoint whatever symbol it names.
+ */
+fn any_symbol(breakpoint: &str) -> Symbols {
+    let location = breakpoint.split(" if ").next().unwrap_or_default().trim();
+    let mut symbols = Symbols::default();
+    symbols.add(location, Location::new(0));
+    symbols
+}
+
+#[derive(Debug, Copy, Clone, ValueEnum)]
+pub enum ModelArg {
+    Dmg,
+    Mgb,
+    Cgb,
+    Sgb
+}
+
+impl From<ModelArg> for Model {
+    fn from(model: ModelArg) -> Model {
+        match model {
+            ModelArg::Dmg => Model::Dmg,
+            ModelArg::Mgb => Model::Mgb,
+            ModelArg::Cgb => Model::Cgb,
+            ModelArg::Sgb => Model::Sgb
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, ValueEnum)]
+pub enum IllegalOpcodeArg {
+    /// Freeze the CPU like the hardware does
+    LockUp,
+    /// Stop in front of the opcode, the debuggers pause there so the state can be fixed, run and trace end
+    Break,
+    /// Stop the emulation
+    Abort
+}
+
+impl From<IllegalOpcodeArg> for IllegalOpcodePolicy {
+    fn from(policy: IllegalOpcodeArg) -> IllegalOpcodePolicy {
+        match policy {
+            IllegalOpcodeArg::LockUp => IllegalOpcodePolicy::LockUp,
+            IllegalOpcodeArg::Break => IllegalOpcodePolicy::Break,
+            IllegalOpcodeArg::Abort => IllegalOpcodePolicy::Abort
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, ValueEnum)]
+pub enum ColorCorrectionArg {
+    /// Scale the channels linearly
+    Raw,
+    /// Approximate the colors of the CGB LCD
+    Lcd
+}
+
+impl From<ColorCorrectionArg> for ColorCorrection {
+    fn from(correction: ColorCorrectionArg) -> ColorCorrection {
+        match correction {
+            ColorCorrectionArg::Raw => ColorCorrection::Raw,
+            ColorCorrectionArg::Lcd => ColorCorrection::Lcd
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, ValueEnum)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> LevelFilter {
+        match level {
+            LogLevel::Off => LevelFilter::Off,
+            LogLevel::Error => LevelFilter::Error,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Trace => LevelFilter::Trace
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_verify_the_definition() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn it_should_parse_emulation_options() {
+        let cli = Cli::try_parse_from(["gb-rs", "run", "rom.gb", "--model", "mgb", "--frames", "60", "--speed", "0",
+                                      "--color-correction", "lcd", "--screenshot", "last.png",
+                                      "--printer", "prints"]).unwrap();
+        match cli.command {
+            Command::Run { emulation, .. } => {
+                assert_eq!(emulation.rom, PathBuf::from("rom.gb"));
+                assert_eq!(Model::from(emulation.model.unwrap()), Model::Mgb);
+                assert_eq!(emulation.frames, Some(60));
+                assert_eq!(emulation.speed, 0.0);
+                assert_eq!(ColorCorrection::from(emulation.color_correction), ColorCorrection::Lcd);
+                assert_eq!(emulation.screenshot, Some(PathBuf::from("last.png")));
+                assert_eq!(emulation.printer, Some(PathBuf::from("prints")));
+            },
+            command => panic!("unexpected command {:?}", command)
+        }
+    }
+
+    #[test]
+    fn it_should_parse_breakpoints() {
+        let cli = Cli::try_parse_from(["gb-rs", "gui", "rom.gb", "--break", "0150", "--break", "03:4A10 if A == 1", "--watch", "C000 rw"]).unwrap();
+        match cli.command {
+            Command::Gui { breakpoints, watchpoints, .. } => {
+                assert_eq!(breakpoints.len(), 2);
+                assert_eq!(watchpoints.len(), 1);
+            },
+            command => panic!("unexpected command {:?}", command)
+        }
+        assert!(Cli::try_parse_from(["gb-rs", "gui", "rom.gb", "--break", "0150 if A =="]).is_err());
+        assert!(Cli::try_parse_from(["gb-rs", "gui", "rom.gb", "--break", "Main.loop if A == 1"]).is_ok());
+    }
+
+    #[test]
+    fn it_should_parse_trace_options() {
+        let cli = Cli::try_parse_from(["gb-rs", "trace", "rom.gb", "-o", "trace.log.gz", "--start", "Main", "--stop", "frame 60"]).unwrap();
+        match cli.command {
+            Command::Trace(tracing) => {
+                assert_eq!(tracing.output, Some(PathBuf::from("trace.log.gz")));
+                assert_eq!(tracing.start.as_deref(), Some("Main"));
+                assert_eq!(tracing.stop.as_deref(), Some("frame 60"));
+            },
+            command => panic!("unexpected command {:?}", command)
+        }
+        assert!(Cli::try_parse_from(["gb-rs", "trace", "rom.gb", "--stop", "frame sixty"]).is_err());
+    }
+
+    #[test]
+    fn it_should_parse_debug_scripts() {
+        let cli = Cli::try_parse_from(["gb-rs", "debug", "rom.gb", "--script", "break.txt"]).unwrap();
+        match cli.command {
+            Command::Debug { emulation, script } => {
+                assert_eq!(emulation.rom, PathBuf::from("rom.gb"));
+                assert_eq!(script, Some(PathBuf::from("break.txt")));
+            },
+            command => panic!("unexpected command {:?}", command)
+        }
+    }
+
+    #[test]
+    fn it_should_parse_movie_options() {
+        let cli = Cli::try_parse_from(["gb-rs", "run", "rom.gb", "--verify", "intro.movie"]).unwrap();
+        match cli.command {
+            Command::Run { emulation, verify } => {
+                assert_eq!(emulation.play, None);
+                assert_eq!(verify, Some(PathBuf::from("intro.movie")));
+            },
+            command => panic!("unexpected command {:?}", command)
+        }
+        let cli = Cli::try_parse_from(["gb-rs", "gui", "rom.gb", "--record", "intro.movie"]).unwrap();
+        match cli.command {
+            Command::Gui { record, .. } => assert_eq!(record, Some(PathBuf::from("intro.movie"))),
+            command => panic!("unexpected command {:?}", command)
+        }
+        assert!(Cli::try_parse_from(["gb-rs", "gui", "rom.gb", "--record", "a.movie", "--play", "b.movie"]).is_err());
+    }
+
+    #[test]
+    fn it_should_parse_trace_diffs() {
+        let cli = Cli::try_parse_from(["gb-rs", "tracediff", "reference.log", "trace.log.gz", "--context", "2"]).unwrap();
+        match cli.command {
+            Command::TraceDiff { reference, trace, context } => {
+                assert_eq!(reference, PathBuf::from("reference.log"));
+                assert_eq!(trace, PathBuf::from("trace.log.gz"));
+                assert_eq!(context, 2);
+            },
+            command => panic!("unexpected command {:?}", command)
+        }
+    }
+
+    #[test]
+    fn it_should_require_a_rom() {
+        assert!(Cli::try_parse_from(["gb-rs", "info"]).is_err());
+    }
+}
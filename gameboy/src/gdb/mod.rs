@@ -0,0 +1,160 @@
+mod stub;
+
+use self::stub::{Reply, Stub};
+use session::Session;
+use std::error::Error;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+
+/// Instructions between checks for Ctrl+C while running
+const INTERRUPT_POLL: u32 = 4096;
+
+/// Sent by GDB instead of a packet to stop the emulation
+const INTERRUPT: u8 = 0x03;
+
+/**
+ * Waits for GDB on the local port and serves one connection until it detaches.
+ */
+pub fn serve(session: &mut Session, port: u16) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+    println!("Waiting for GDB on {}, connect with `target remote :{}`", listener.local_addr()?, port);
+    let (stream, address) = listener.accept()?;
+    info!("GDB connected from {}", address);
+    let mut connection = Connection::new(stream)?;
+    let mut stub = Stub::default();
+    stub.debugger.pause();
+
+    while let Some(packet) = connection.receive()? {
+        debug!("<- {}", packet);
+        match stub.handle(&mut session.gb, &packet) {
+            Reply::Packet(reply) => connection.send(&reply)?,
+            Reply::Resume(resume) => {
+                stub.start(resume);
+                match run(session, &mut stub, &mut connection)? {
+                    Some(reply) => connection.send(&reply)?,
+                    None => {
+                        connection.send("W00")?;
+                        break;
+                    }
+                }
+            },
+            Reply::Detach => {
+                connection.send("OK")?;
+                break;
+            },
+            Reply::Kill => break
+        }
+    }
+    info!("GDB disconnected");
+    Ok(())
+}
+
+/**
+ * Runs until the stub stops, returns None once the frame limit was reached.
+ */
+fn run(session: &mut Session, stub: &mut Stub, connection: &mut Connection) -> io::Result<Option<String>> {
+    let mut steps: u32 = 0;
+    while !session.is_finished() {
+        session.input.update(&mut session.gb);
+        if let Some(reply) = stub.step(&mut session.gb) {
+            return Ok(Some(reply));
+        }
+        session.pace();
+        steps = steps.wrapping_add(1);
+        if steps.is_multiple_of(INTERRUPT_POLL) && connection.interrupted()? {
+            return Ok(Some(stub.interrupt()));
+        }
+    }
+    Ok(None)
+}
+
+/**
+ * The packet framing `$data#checksum` with the acknowledgements of the remote serial protocol.
+ */
+struct Connection {
+    stream: TcpStream,
+    /// Received bytes which aren't a complete packet yet
+    pending: Vec<u8>
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> io::Result<Connection> {
+        stream.set_nodelay(true)?;
+        Ok(Connection {
+            stream,
+            pending: Vec::new()
+        })
+    }
+
+    /**
+     * The next packet with a valid checksum, None when GDB closed the connection.
+     */
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        loop {
+            if let Some(packet) = self.take_packet()? {
+                return Ok(Some(packet));
+            }
+            let mut buffer = [0; 4096];
+            match self.stream.read(&mut buffer)? {
+                0 => return Ok(None),
+                read => self.pending.extend_from_slice(&buffer[..read])
+            }
+        }
+    }
+
+    fn take_packet(&mut self) -> io::Result<Option<String>> {
+        // acknowledgements and interrupts outside of a packet are skipped
+        let start = match self.pending.iter().position(|&byte| byte == b'$') {
+            Some(start) => start,
+            None => {
+                self.pending.clear();
+                return Ok(None);
+            }
+        };
+        let end = match self.pending[start..].iter().position(|&byte| byte == b'#') {
+            Some(end) if start + end + 2 < self.pending.len() => start + end,
+            _ => return Ok(None)
+        };
+        let packet: Vec<u8> = self.pending.drain(..end + 3).skip(start + 1).collect();
+        let (data, checksum) = packet.split_at(end - start - 1);
+        let valid = std::str::from_utf8(&checksum[1..]).ok()
+            .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+            .is_some_and(|expected| expected == checksum_of(data));
+        if !valid {
+            self.stream.write_all(b"-")?;
+            return Ok(None);
+        }
+        self.stream.write_all(b"+")?;
+        Ok(Some(String::from_utf8_lossy(data).into_owned()))
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        debug!("-> {}", data);
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())?;
+        self.stream.flush()
+    }
+
+    /**
+     * Whether GDB sent Ctrl+C, without waiting for it.
+     */
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut buffer = [0; 4096];
+        let result = self.stream.read(&mut buffer);
+        self.stream.set_nonblocking(false)?;
+        match result {
+            Ok(read) => {
+                let interrupted = buffer[..read].contains(&INTERRUPT);
+                self.pending.extend(buffer[..read].iter().filter(|&&byte| byte != INTERRUPT));
+                Ok(interrupted)
+            },
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err)
+        }
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |checksum: u8, &byte| checksum.wrapping_add(byte))
+}
@@ -0,0 +1,435 @@
+use gb_core::{Debugger, Event, GameBoy};
+use gb_core::cpu::register::Flags;
+use gb_core::debugger::breakpoint::{Breakpoint, Location};
+use gb_core::debugger::watchpoint::{Access, Watchpoint};
+
+/**
+ * The SM83 registers in the order of the `g` packet, GDB doesn't know the architecture otherwise.
+ */
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.gnu.gdb.sm83.cpu">
+    <flags id="sm83_flags" size="1">
+      <field name="C" start="4" end="4"/>
+      <field name="H" start="5" end="5"/>
+      <field name="N" start="6" end="6"/>
+      <field name="Z" start="7" end="7"/>
+    </flags>
+    <reg name="a" bitsize="8" regnum="0"/>
+    <reg name="f" bitsize="8" type="sm83_flags"/>
+    <reg name="b" bitsize="8"/>
+    <reg name="c" bitsize="8"/>
+    <reg name="d" bitsize="8"/>
+    <reg name="e" bitsize="8"/>
+    <reg name="h" bitsize="8"/>
+    <reg name="l" bitsize="8"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+const REGISTERS: usize = 10;
+
+/// Largest packet GDB may send or receive, a memory read replies with two hex digits per byte
+const PACKET_SIZE: usize = 0x4000;
+
+/// Signals of the stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Resume {
+    Step,
+    Continue
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    Packet(String),
+    /// Run the emulation and reply once it stops
+    Resume(Resume),
+    Detach,
+    Kill
+}
+
+/**
+ * Answers the packets of the GDB remote serial protocol, without the framing of the connection.
+ */
+#[derive(Default)]
+pub struct Stub {
+    pub debugger: Debugger
+}
+
+impl Stub {
+    pub fn handle(&mut self, gb: &mut GameBoy, packet: &str) -> Reply {
+        let reply = match packet.split_at(packet.len().min(1)) {
+            ("?", _) => stop(SIGTRAP, ""),
+            ("g", _) => (0..REGISTERS).map(|register| read_register(gb, register)).collect(),
+            ("G", values) => ok(write_registers(gb, values)),
+            ("p", register) => parse_hex(register).map_or_else(error, |register| read_register(gb, register)),
+            ("P", assignment) => ok(write_register(gb, assignment)),
+            ("m", range) => read_memory(gb, range).unwrap_or_else(error),
+            ("M", data) => ok(write_memory(gb, data)),
+            ("Z", point) => ok(self.insert(gb, point)),
+            ("z", point) => ok(self.remove(gb, point)),
+            ("s", "") => return Reply::Resume(Resume::Step),
+            ("c", "") => return Reply::Resume(Resume::Continue),
+            ("D", _) => return Reply::Detach,
+            ("k", _) => return Reply::Kill,
+            ("H", _) => "OK".to_string(),
+            ("q", query) => self.query(query),
+            _ => String::new()
+        };
+        Reply::Packet(reply)
+    }
+
+    pub fn start(&mut self, resume: Resume) {
+        match resume {
+            Resume::Step => self.debugger.step_into(),
+            Resume::Continue => self.debugger.resume()
+        }
+    }
+
+    /**
+     * Executes an instruction and returns the stop reply once the debugger paused.
+     */
+    pub fn step(&mut self, gb: &mut GameBoy) -> Option<String> {
+        let result = self.debugger.step(gb);
+        let mut reply = None;
+        while let Some(event) = gb.poll_event() {
+            reply = reply.or_else(|| Some(self.stop_reply(gb, event)));
+        }
+        if let Some(reply) = reply {
+            self.debugger.pause();
+            return Some(reply);
+        }
+        if result.is_err() {
+            return Some(stop(SIGILL, ""));
+        }
+        if self.debugger.is_paused() {
+            return Some(stop(SIGTRAP, ""));
+        }
+        None
+    }
+
+    /**
+     * GDB sent Ctrl+C while running.
+     */
+    pub fn interrupt(&mut self) -> String {
+        self.debugger.pause();
+        stop(SIGINT, "")
+    }
+
+    fn stop_reply(&self, gb: &GameBoy, event: Event) -> String {
+        match event {
+            Event::LockedUp { .. } => stop(SIGILL, ""),
+            Event::Breakpoint { .. } => stop(SIGTRAP, "swbreak:;"),
+            Event::Watchpoint(hit) => {
+                let kind = match gb.watchpoints.get(hit.id).map(|watchpoint| watchpoint.access) {
+                    Some(Access::ReadWrite) => "awatch",
+                    Some(Access::Read) => "rwatch",
+                    _ => "watch"
+                };
+                stop(SIGTRAP, &format!("{}:{:x};", kind, hit.address))
+            }
+        }
+    }
+
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            return format!("PacketSize={:x};qXfer:features:read+;swbreak+;hwbreak+", PACKET_SIZE);
+        }
+        if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let mut range = range.split(',').map(parse_hex);
+            return match (range.next(), range.next()) {
+                (Some(Some(offset)), Some(Some(length))) => {
+                    let xml = TARGET_XML.as_bytes();
+                    let start = offset.min(xml.len());
+                    let end = offset.saturating_add(length).clamp(start, xml.len());
+                    let more = if end < xml.len() { 'm' } else { 'l' };
+                    let chunk = &xml[start..end];
+                    format!("{}{}", more, String::from_utf8_lossy(chunk))
+                },
+                _ => error()
+            };
+        }
+        match query {
+            "Attached" => "1".to_string(),
+            "C" => "QC1".to_string(),
+            "fThreadInfo" => "m1".to_string(),
+            "sThreadInfo" => "l".to_string(),
+            _ => String::new()
+        }
+    }
+
+    /**
+     * Breakpoints `type,address,kind` and watchpoints `type,address,length`.
+     */
+    fn insert(&mut self, gb: &mut GameBoy, point: &str) -> Option<()> {
+        match parse_point(point)? {
+            (0, address, _) | (1, address, _) => {
+                let location = Location::new(address);
+                if self.debugger.breakpoints.find(location).is_none() {
+                    self.debugger.breakpoints.add(Breakpoint::new(location));
+                }
+            },
+            (kind, address, length) => {
+                let watchpoint = Watchpoint::new(address..=address.wrapping_add(length.max(1) - 1), access(kind)?);
+                gb.watchpoints.add(watchpoint);
+            }
+        }
+        Some(())
+    }
+
+    fn remove(&mut self, gb: &mut GameBoy, point: &str) -> Option<()> {
+        match parse_point(point)? {
+            (0, address, _) | (1, address, _) => {
+                let id = self.debugger.breakpoints.find(Location::new(address))?;
+                self.debugger.breakpoints.remove(id);
+            },
+            (kind, address, length) => {
+                let watchpoint = Watchpoint::new(address..=address.wrapping_add(length.max(1) - 1), access(kind)?);
+                let id = gb.watchpoints.iter()
+                    .find(|(_, existing)| existing.range == watchpoint.range && existing.access == watchpoint.access)
+                    .map(|(id, _)| id)?;
+                gb.watchpoints.remove(id);
+            }
+        }
+        Some(())
+    }
+}
+
+fn stop(signal: u8, reason: &str) -> String {
+    if reason.is_empty() {
+        format!("S{:02x}", signal)
+    }else {
+        format!("T{:02x}{}", signal, reason)
+    }
+}
+
+fn ok(result: Option<()>) -> String {
+    result.map_or_else(error, |_| "OK".to_string())
+}
+
+fn error() -> String {
+    "E01".to_string()
+}
+
+fn parse_hex(number: &str) -> Option<usize> {
+    usize::from_str_radix(number, 16).ok()
+}
+
+fn parse_point(point: &str) -> Option<(usize, u16, u16)> {
+    let mut parts = point.split(',').map(parse_hex);
+    match (parts.next()?, parts.next()?, parts.next()?) {
+        (Some(kind), Some(address), Some(length)) if address <= 0xFFFF && length <= 0xFFFF =>
+            Some((kind, address as u16, length as u16)),
+        _ => None
+    }
+}
+
+fn access(kind: usize) -> Option<Access> {
+    match kind {
+        2 => Some(Access::Write),
+        3 => Some(Access::Read),
+        4 => Some(Access::ReadWrite),
+        _ => None
+    }
+}
+
+/**
+ * Hex encoded in target byte order, which is little endian.
+ */
+fn read_register(gb: &GameBoy, register: usize) -> String {
+    let register_set = &gb.register;
+    let value = match register {
+        0 => register_set.a,
+        1 => register_set.f.bits(),
+        2 => register_set.b,
+        3 => register_set.c,
+        4 => register_set.d,
+        5 => register_set.e,
+        6 => register_set.h,
+        7 => register_set.l,
+        8 => return format!("{:02x}{:02x}", register_set.sp as u8, register_set.sp >> 8),
+        9 => return format!("{:02x}{:02x}", register_set.pc as u8, register_set.pc >> 8),
+        _ => return error()
+    };
+    format!("{:02x}", value)
+}
+
+fn set_register(gb: &mut GameBoy, register: usize, value: u16) -> Option<()> {
+    let register_set = &mut gb.register;
+    match register {
+        0 => register_set.a = value as u8,
+        1 => register_set.f = Flags::from_bits_truncate(value as u8),
+        2 => register_set.b = value as u8,
+        3 => register_set.c = value as u8,
+        4 => register_set.d = value as u8,
+        5 => register_set.e = value as u8,
+        6 => register_set.h = value as u8,
+        7 => register_set.l = value as u8,
+        8 => register_set.sp = value,
+        9 => register_set.pc = value,
+        _ => return None
+    }
+    Some(())
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn little_endian(bytes: &[u8]) -> u16 {
+    bytes.iter().rev().fold(0, |value, &byte| (value << 8) | byte as u16)
+}
+
+fn write_register(gb: &mut GameBoy, assignment: &str) -> Option<()> {
+    let index = assignment.find('=')?;
+    let register = parse_hex(&assignment[..index])?;
+    let value = decode_hex(&assignment[index + 1..])?;
+    set_register(gb, register, little_endian(&value))
+}
+
+fn write_registers(gb: &mut GameBoy, values: &str) -> Option<()> {
+    let bytes = decode_hex(values)?;
+    let mut offset = 0;
+    for register in 0..REGISTERS {
+        let size = if register < 8 { 1 } else { 2 };
+        set_register(gb, register, little_endian(bytes.get(offset..offset + size)?))?;
+        offset += size;
+    }
+    Some(())
+}
+
+fn parse_range(range: &str) -> Option<(u16, usize)> {
+    let mut parts = range.split(',').map(parse_hex);
+    match (parts.next()?, parts.next()?) {
+        (Some(address), Some(length)) if address <= 0xFFFF => Some((address as u16, length)),
+        _ => None
+    }
+}
+
+fn read_memory(gb: &GameBoy, range: &str) -> Option<String> {
+    let (address, length) = parse_range(range)?;
+    if length > PACKET_SIZE / 2 {
+        return None;
+    }
+    Some((0..length)
+        .map(|i| format!("{:02x}", gb.read(address.wrapping_add(i as u16))))
+        .collect())
+}
+
+/**
+ * Writes go through the bus, so writes to ROM select banks like the CPU's would.
+ */
+fn write_memory(gb: &mut GameBoy, data: &str) -> Option<()> {
+    let index = data.find(':')?;
+    let (address, length) = parse_range(&data[..index])?;
+    let bytes = decode_hex(&data[index + 1..])?;
+    if bytes.len() != length {
+        return None;
+    }
+    for (i, byte) in bytes.into_iter().enumerate() {
+        gb.write(address.wrapping_add(i as u16), byte);
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(stub: &mut Stub, gb: &mut GameBoy, packet: &str) -> String {
+        match stub.handle(gb, packet) {
+            Reply::Packet(reply) => reply,
+            reply => panic!("unexpected reply {:?}", reply)
+        }
+    }
+
+    fn run(stub: &mut Stub, gb: &mut GameBoy, resume: Resume) -> String {
+        stub.start(resume);
+        (0..1000).find_map(|_| stub.step(gb)).expect("the emulation didn't stop")
+    }
+
+    fn setup() -> GameBoy {
+        let mut gb = GameBoy::new();
+        gb.skip_boot();
+        gb.register.pc = 0xC000;
+        // INC A; LDH (0x80),A; JR NZ,-5
+        for (i, &byte) in [0x3C, 0xE0, 0x80, 0x20, 0xFB].iter().enumerate() {
+            gb.write(0xC000 + i as u16, byte);
+        }
+        gb
+    }
+
+    #[test]
+    fn it_should_read_and_write_registers() {
+        let mut gb = setup();
+        let mut stub = Stub::default();
+        assert_eq!(packet(&mut stub, &mut gb, "g"), "01b0001300d8014dfeff00c0");
+        assert_eq!(packet(&mut stub, &mut gb, "P8=f0df"), "OK");
+        assert_eq!(gb.register.sp, 0xDFF0);
+        assert_eq!(packet(&mut stub, &mut gb, "p9"), "00c0");
+        assert_eq!(packet(&mut stub, &mut gb, "G0000000000000000000000"), "E01");
+    }
+
+    #[test]
+    fn it_should_read_and_write_memory() {
+        let mut gb = setup();
+        let mut stub = Stub::default();
+        assert_eq!(packet(&mut stub, &mut gb, "mc000,3"), "3ce080");
+        assert_eq!(packet(&mut stub, &mut gb, "Mc200,2:1234"), "OK");
+        assert_eq!(gb.read(0xC201), 0x34);
+        assert_eq!(packet(&mut stub, &mut gb, "Mc200,2:12"), "E01");
+        assert_eq!(packet(&mut stub, &mut gb, "m0,2000").len(), PACKET_SIZE);
+        assert_eq!(packet(&mut stub, &mut gb, "m0,ffffffffffffffff"), "E01");
+    }
+
+    #[test]
+    fn it_should_describe_the_target() {
+        let mut gb = setup();
+        let mut stub = Stub::default();
+        assert!(packet(&mut stub, &mut gb, "qSupported:multiprocess+").contains("qXfer:features:read+"));
+        let start = packet(&mut stub, &mut gb, "qXfer:features:read:target.xml:0,a");
+        assert_eq!(start, "m<?xml vers");
+        let end = packet(&mut stub, &mut gb, "qXfer:features:read:target.xml:a,1000");
+        assert!(end.starts_with("lion=") && end.ends_with("</target>\n"));
+        assert!(packet(&mut stub, &mut gb, "qXfer:features:read:target.xml:1,ffffffffffffffff").ends_with("</target>\n"));
+        assert_eq!(packet(&mut stub, &mut gb, "qXfer:features:read:target.xml:ffffffffffffffff,1"), "l");
+    }
+
+    #[test]
+    fn it_should_stop_at_breakpoints() {
+        let mut gb = setup();
+        let mut stub = Stub::default();
+        assert_eq!(packet(&mut stub, &mut gb, "Z0,c003,1"), "OK");
+        assert_eq!(stub.handle(&mut gb, "c"), Reply::Resume(Resume::Continue));
+        assert_eq!(run(&mut stub, &mut gb, Resume::Continue), "T05swbreak:;");
+        assert_eq!(gb.register.pc, 0xC003);
+        assert_eq!(run(&mut stub, &mut gb, Resume::Step), "S05");
+        assert_eq!(gb.register.pc, 0xC000);
+        assert_eq!(packet(&mut stub, &mut gb, "z0,c003,1"), "OK");
+        assert_eq!(packet(&mut stub, &mut gb, "z0,c003,1"), "E01");
+    }
+
+    #[test]
+    fn it_should_stop_at_watchpoints() {
+        let mut gb = setup();
+        let mut stub = Stub::default();
+        assert_eq!(packet(&mut stub, &mut gb, "Z2,ff80,1"), "OK");
+        assert_eq!(run(&mut stub, &mut gb, Resume::Continue), "T05watch:ff80;");
+        assert_eq!(gb.register.pc, 0xC003);
+        assert_eq!(packet(&mut stub, &mut gb, "z2,ff80,1"), "OK");
+        assert!(gb.watchpoints.is_empty());
+    }
+}
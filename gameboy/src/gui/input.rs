@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use termion::event::Key;
+use gb_core::Button;
+
+/**
+ * Mapping of terminal keys to joypad buttons.
+ *
+ * Bindings can be configured with one `button = key` pair per line, e.g.
+ *
+ * ```text
+ * # comments start with a hash
+ * a = x
+ * start = enter
+ * up = w
+ * ```
+ *
+ * Keys are either a single character or one of
+ * `up`, `down`, `left`, `right`, `enter`, `space`, `tab`, `backspace` and `esc`.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBindings {
+    bindings: HashMap<Key, Button>
+}
+
+impl Default for KeyBindings {
+    fn default() -> KeyBindings {
+        let mut bindings = HashMap::new();
+        bindings.insert(Key::Up, Button::Up);
+        bindings.insert(Key::Down, Button::Down);
+        bindings.insert(Key::Left, Button::Left);
+        bindings.insert(Key::Right, Button::Right);
+        bindings.insert(Key::Char('x'), Button::A);
+        bindings.insert(Key::Char('z'), Button::B);
+        bindings.insert(Key::Char('\n'), Button::Start);
+        bindings.insert(Key::Backspace, Button::Select);
+        KeyBindings {
+            bindings
+        }
+    }
+}
+
+impl KeyBindings {
+    /**
+     * Loads bindings from a file, buttons which are not configured keep their default key.
+     */
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<KeyBindings> {
+        let config = fs::read_to_string(path)?;
+        KeyBindings::parse(&config)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn parse(config: &str) -> Result<KeyBindings, String> {
+        let mut bindings = KeyBindings::default();
+        for (i, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let button = parts.next().map(str::trim).unwrap_or_default();
+            let key = parts.next()
+                .map(str::trim)
+                .ok_or_else(|| format!("line {}: expected `button = key`", i + 1))?;
+            let button = parse_button(button)
+                .ok_or_else(|| format!("line {}: unknown button `{}`", i + 1, button))?;
+            let key = parse_key(key)
+                .ok_or_else(|| format!("line {}: unknown key `{}`", i + 1, key))?;
+            bindings.bind(key, button);
+        }
+        Ok(bindings)
+    }
+
+    /**
+     * Binds `key` to `button`, replacing the previous key of the button.
+     */
+    pub fn bind(&mut self, key: Key, button: Button) {
+        self.bindings.retain(|_, bound| *bound != button);
+        self.bindings.insert(key, button);
+    }
+
+    pub fn button(&self, key: &Key) -> Option<Button> {
+        self.bindings.get(key).cloned()
+    }
+}
+
+fn parse_button(button: &str) -> Option<Button> {
+    match button.to_lowercase().as_str() {
+        "up" => Some(Button::Up),
+        "down" => Some(Button::Down),
+        "left" => Some(Button::Left),
+        "right" => Some(Button::Right),
+        "a" => Some(Button::A),
+        "b" => Some(Button::B),
+        "select" => Some(Button::Select),
+        "start" => Some(Button::Start),
+        _ => None
+    }
+}
+
+fn parse_key(key: &str) -> Option<Key> {
+    match key.to_lowercase().as_str() {
+        "up" => Some(Key::Up),
+        "down" => Some(Key::Down),
+        "left" => Some(Key::Left),
+        "right" => Some(Key::Right),
+        "enter" => Some(Key::Char('\n')),
+        "space" => Some(Key::Char(' ')),
+        "tab" => Some(Key::Char('\t')),
+        "backspace" => Some(Key::Backspace),
+        "esc" => Some(Key::Esc),
+        _ => {
+            let mut chars = key.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(Key::Char(c)),
+                _ => None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_map_arrow_keys_by_default() {
+        let bindings = KeyBindings::default();
+        assert_eq!(bindings.button(&Key::Up), Some(Button::Up));
+        assert_eq!(bindings.button(&Key::Char('\n')), Some(Button::Start));
+    }
+
+    #[test]
+    fn it_should_replace_the_key_of_a_configured_button() {
+        let bindings = KeyBindings::parse("a = k\nstart = space").unwrap();
+        assert_eq!(bindings.button(&Key::Char('k')), Some(Button::A));
+        assert_eq!(bindings.button(&Key::Char('x')), None);
+        assert_eq!(bindings.button(&Key::Char(' ')), Some(Button::Start));
+        assert_eq!(bindings.button(&Key::Char('z')), Some(Button::B));
+    }
+
+    #[test]
+    fn it_should_ignore_comments_and_empty_lines() {
+        let bindings = KeyBindings::parse("# bindings\n\nup = w\n").unwrap();
+        assert_eq!(bindings.button(&Key::Char('w')), Some(Button::Up));
+    }
+
+    #[test]
+    fn it_should_reject_unknown_buttons() {
+        assert!(KeyBindings::parse("turbo = t").is_err());
+    }
+
+    #[test]
+    fn it_should_reject_unknown_keys() {
+        assert!(KeyBindings::parse("a = ctrl").is_err());
+    }
+}
@@ -1 +1,2 @@
+pub mod input;
 pub mod terminal;
\ No newline at end of file
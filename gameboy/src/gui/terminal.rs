@@ -3,55 +3,273 @@ use tui::Terminal;
 use tui::widgets::*;
 use tui::layout::*;
 use tui::style::{Style, Color};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
 use std::io::Result;
-use gameboy::GameBoy;
-use cpu::register::Flags;
+use termion::{async_stdin, AsyncReader};
+use termion::event::Key;
+use termion::input::{Keys, TermRead};
+use gb_core::{Debugger, GameBoy};
+use gb_core::debugger::breakpoint::{Breakpoint, Location};
+use gb_core::debugger::symbols::{self, Symbols};
+use gb_core::cpu::register::Flags;
+use gb_core::disasm::{self, Instruction, Operand};
+use gb_core::Button;
+use gui::input::KeyBindings;
+use movie::Input;
+
+/// Terminals only report key presses, buttons are released after this many emulated frames
+const HOLD_FRAMES: u64 = 10;
+
+/**
+ * Debugger controls, the arrow and page keys move the cursor instead of the joypad while paused.
+ */
+const PAUSE: Key = Key::F(5);
+const NEXT_FRAME: Key = Key::F(6);
+const STEP_INTO: Key = Key::F(7);
+const STEP_OVER: Key = Key::F(8);
+const STEP_OUT: Key = Key::F(9);
+const RUN_TO_CURSOR: Key = Key::F(4);
+const PC_TO_CURSOR: Key = Key::F(3);
+const TOGGLE_BREAKPOINT: Key = Key::F(2);
+const CURSOR_TO_PC: Key = Key::Home;
 
 pub struct Interface {
-    terminal: Terminal<RawBackend>
+    terminal: Terminal<RawBackend>,
+    keys: Keys<AsyncReader>,
+    bindings: KeyBindings,
+    /// Frame at which each pressed button is released
+    held: HashMap<Button, u64>,
+    /// Address of the selected instruction, follows PC when unset
+    cursor: Option<u16>,
+    /// Instructions fitting into the disassembly panel
+    page: usize,
+    /// Last event, shown while paused
+    status: Option<String>
 }
 
 impl Interface {
-    pub fn draw(&mut self, gb: &GameBoy, instructions: &Vec<String>) -> Result<()> {
+    /**
+     * Returns false once the user wants to quit.
+     */
+    pub fn handle_input(&mut self, gb: &mut GameBoy, input: &mut Input, debugger: &mut Debugger) -> Result<bool> {
+        let keys: Vec<_> = self.keys.by_ref().collect();
+        for key in keys {
+            let key = key?;
+            match key {
+                Key::Ctrl('c') => return Ok(false),
+                PAUSE if debugger.is_paused() => debugger.resume(),
+                PAUSE => debugger.pause(),
+                NEXT_FRAME => debugger.run_to_next_frame(gb),
+                STEP_INTO => debugger.step_into(),
+                STEP_OVER => debugger.step_over(gb),
+                STEP_OUT => debugger.step_out(gb),
+                RUN_TO_CURSOR => debugger.run_to(self.selected(gb)),
+                PC_TO_CURSOR if debugger.is_paused() => {
+                    gb.register.pc = self.selected(gb);
+                    self.cursor = None;
+                },
+                TOGGLE_BREAKPOINT => {
+                    let location = Location::new(self.selected(gb));
+                    match debugger.breakpoints.find(location) {
+                        Some(id) => debugger.breakpoints.remove(id),
+                        None => {
+                            debugger.breakpoints.add(Breakpoint::new(location));
+                            None
+                        }
+                    };
+                },
+                Key::Up if debugger.is_paused() => self.scroll(gb, -1),
+                Key::Down if debugger.is_paused() => self.scroll(gb, 1),
+                Key::PageUp if debugger.is_paused() => self.scroll(gb, -(self.page as isize)),
+                Key::PageDown if debugger.is_paused() => self.scroll(gb, self.page as isize),
+                CURSOR_TO_PC => self.cursor = None,
+                key => if let Some(button) = self.bindings.button(&key) {
+                    input.press(gb, button);
+                    self.held.insert(button, gb.frame() + HOLD_FRAMES);
+                }
+            }
+            if !debugger.is_paused() {
+                self.cursor = None;
+                self.status = None;
+            }
+        }
+        let frame = gb.frame();
+        for (button, release) in self.held.iter() {
+            if frame >= *release {
+                input.release(gb, *button);
+            }
+        }
+        self.held.retain(|_, release| frame < *release);
+        Ok(true)
+    }
+
+    /**
+     * Shows the event or error until the emulation is resumed.
+     */
+    pub fn notify<T: Display>(&mut self, message: T) {
+        self.status = Some(message.to_string());
+    }
+
+    pub fn draw(&mut self, gb: &GameBoy, debugger: &Debugger, symbols: &Symbols) -> Result<()> {
         let size = self.terminal.size()?;
+        let cursor = self.cursor;
+        let status = self.status.as_deref();
+        // the borders of the panel take up two lines
+        self.page = size.height.saturating_sub(2).max(1) as usize;
 
         Group::default()
             .direction(Direction::Horizontal)
             .sizes(&[Size::Min(24), Size::Fixed(74)])
             .render(&mut self.terminal, &size, |t, chunks| {
-                build_instructions(t, &chunks[0], instructions);
-                build_sidebar(t, &chunks[1], gb);
+                build_disassembly(t, &chunks[0], gb, debugger, symbols, cursor, status);
+                build_sidebar(t, &chunks[1], gb, debugger, symbols);
             });
 
         self.terminal.draw()
     }
+
+    fn selected(&self, gb: &GameBoy) -> u16 {
+        self.cursor.unwrap_or(gb.register.pc)
+    }
+
+    /**
+     * Moves the cursor by a number of instructions, negative numbers move up.
+     */
+    fn scroll(&mut self, gb: &GameBoy, instructions: isize) {
+        let address = self.selected(gb);
+        let count = instructions.unsigned_abs();
+        self.cursor = Some(if instructions < 0 {
+            disasm::preceding(gb, address, count).first().map_or(address, |instruction| instruction.address)
+        }else {
+            (0..count).fold(address, |address, _| disasm::decode_at(gb, address).next())
+        });
+    }
 }
 
-fn build_instructions(terminal: &mut Terminal<RawBackend>, target: &Rect, instructions: &Vec<String>) {
-    let instructions = instructions.iter()
-        .rev()
-        .take(target.height as usize)
-        .map(|instruction| Item::Data(instruction));
+/**
+ * Whether the condition of a branch holds with the current flags.
+ */
+fn is_taken(gb: &GameBoy, instruction: &Instruction) -> bool {
+    let condition = instruction.operands.iter().filter_map(|operand| match *operand {
+        Operand::Condition(condition) => Some(condition),
+        _ => None
+    }).next();
+    match condition {
+        Some("NZ") => !gb.register.f.contains(Flags::Z),
+        Some("Z") => gb.register.f.contains(Flags::Z),
+        Some("NC") => !gb.register.f.contains(Flags::C),
+        Some("C") => gb.register.f.contains(Flags::C),
+        _ => true
+    }
+}
 
+/**
+ * The instructions around the cursor, or PC while it is not moved, decoded from memory.
+ *
+ * Lines are `cursor breakpoint address  bytes  instruction`, instructions jumped to from within the
+ * panel list where from, and the branch at PC tells whether it is taken. Symbols get a line of
+ * their own and name the operands.
+ */
+fn build_disassembly(terminal: &mut Terminal<RawBackend>, target: &Rect, gb: &GameBoy, debugger: &Debugger,
+                     symbols: &Symbols, cursor: Option<u16>, status: Option<&str>) {
+    let height = target.height.saturating_sub(2) as usize;
+    let pc = gb.register.pc;
+    let selected = cursor.unwrap_or(pc);
+    let mut instructions = disasm::preceding(gb, selected, height / 3);
+    let mut address = selected;
+    while instructions.len() < height {
+        let instruction = disasm::decode_at(gb, address);
+        address = instruction.next();
+        instructions.push(instruction);
+    }
+
+    let mut sources: BTreeMap<u16, Vec<String>> = BTreeMap::new();
+    for instruction in &instructions {
+        if let Some(target) = instruction.target() {
+            sources.entry(target).or_default().push(format!("{:04X}", instruction.address));
+        }
+    }
+    let symbol = |address| symbols.get(symbols::rom_bank(gb, address), address).map(|symbol| symbol.name.clone());
+    let mut lines = Vec::new();
+    for instruction in &instructions {
+        if let Some(name) = symbol(instruction.address) {
+            lines.push((format!("        {}:", name), false));
+        }
+        let line = {
+            let marker = if debugger.is_paused() && instruction.address == selected { '>' } else { ' ' };
+            let breakpoint = if debugger.breakpoints.is_set(instruction.address) { '*' } else { ' ' };
+            let bytes: Vec<String> = (0..instruction.length)
+                .map(|i| format!("{:02X}", gb.read(instruction.address.wrapping_add(i))))
+                .collect();
+            let mut line = format!("{}{}{:04X}  {:<8}  {}", marker, breakpoint, instruction.address,
+                                   bytes.join(" "), instruction.format(symbol));
+            if instruction.address == pc && instruction.target().is_some() {
+                line.push_str(if is_taken(gb, instruction) { "  ; taken" } else { "  ; not taken" });
+            }
+            if let Some(sources) = sources.get(&instruction.address) {
+                line.push_str(&format!("  ; <- {}", sources.join(",")));
+            }
+            line
+        };
+        lines.push((line, instruction.address == pc));
+    }
+
+    let highlight = Style::default().fg(Color::Black).bg(Color::Yellow);
+    let items = lines.iter()
+        .map(|(line, current)| if *current {
+            Item::StyledData(line, &highlight)
+        }else {
+            Item::Data(line)
+        });
+
+    let title = match status {
+        Some(status) if debugger.is_paused() => format!("Disassembly ({})", status),
+        _ if debugger.is_paused() => "Disassembly (paused)".to_string(),
+        _ => "Disassembly".to_string()
+    };
     let block = Block::default()
-        .title("Instructions")
+        .title(&title)
         .borders(Borders::ALL);
-    List::new(instructions)
+    List::new(items)
         .block(block)
-        .start_corner(Corner::BottomLeft)
         .render(terminal, target);
 }
 
-fn build_sidebar(terminal: &mut Terminal<RawBackend>, target: &Rect, gb: &GameBoy) {
+fn build_sidebar(terminal: &mut Terminal<RawBackend>, target: &Rect, gb: &GameBoy, debugger: &Debugger,
+                 symbols: &Symbols) {
     Group::default()
         .direction(Direction::Vertical)
-        .sizes(&[Size::Fixed(5), Size::Min(75)])
+        .sizes(&[Size::Fixed(5), Size::Fixed(8), Size::Min(75)])
         .render(terminal, target, |t, chunks| {
             build_cpu(t, &chunks[0], gb);
-            build_ram(t, &chunks[1], gb);
+            build_call_stack(t, &chunks[1], gb, debugger, symbols);
+            build_ram(t, &chunks[2], gb);
         })
 }
 
+/**
+ * The innermost subroutines first, `bank:address name from caller`.
+ */
+fn build_call_stack(terminal: &mut Terminal<RawBackend>, target: &Rect, gb: &GameBoy, debugger: &Debugger,
+                    symbols: &Symbols) {
+    let lines: Vec<String> = debugger.call_stack()
+        .iter()
+        .rev()
+        .map(|frame| {
+            let function = frame.function;
+            let caller = symbols.name_at(gb, frame.caller).unwrap_or_else(|| format!("{:04X}", frame.caller));
+            match symbols.name(function.bank.unwrap_or(0), function.address) {
+                Some(name) => format!("{} {} from {}", function, name, caller),
+                None => format!("{} from {}", function, caller)
+            }
+        })
+        .collect();
+    List::new(lines.iter().map(Item::Data))
+        .block(Block::default().title("Call stack").borders(Borders::ALL))
+        .render(terminal, target);
+}
+
 fn build_cpu(terminal: &mut Terminal<RawBackend>, target: &Rect, gb: &GameBoy) {
     Group::default()
         .direction(Direction::Horizontal)
@@ -77,9 +295,9 @@ fn build_register(terminal: &mut Terminal<RawBackend>, target: &Rect, gb: &GameB
         format!("0x{:x?}", gb.register.pc),
     ];
     Table::new(
-        header.into_iter(),
+        header.iter(),
         vec![
-            Row::Data(registers.into_iter())
+            Row::Data(registers.iter())
         ].into_iter()
     )
         .block(Block::default().title("Register").borders(Borders::ALL))
@@ -106,9 +324,9 @@ fn build_flags(terminal: &mut Terminal<RawBackend>, target: &Rect, gb: &GameBoy)
         format!("{}", print_flag(gb, Flags::C))
     ];
     Table::new(
-        header.into_iter(),
+        header.iter(),
         vec![
-            Row::Data(registers.into_iter())
+            Row::Data(registers.iter())
         ].into_iter()
     )
         .block(Block::default().title("Flags").borders(Borders::ALL))
@@ -121,15 +339,14 @@ fn build_flags(terminal: &mut Terminal<RawBackend>, target: &Rect, gb: &GameBoy)
 /* TODO: highlight program counter */
 fn build_ram(terminal: &mut Terminal<RawBackend>, target: &Rect, gb: &GameBoy) {
     const COLS: usize = 16;
-    let header = (0..COLS).into_iter().map(|i| format!("0{:X?}", i));
+    let header = (0..COLS).map(|i| format!("0{:X?}", i));
     let widths = [2; COLS];
-    let ram = gb.ram
-        .iter()
-        .map(|d| format!("{:X?}", d))
+    let ram = (0..=0xFFFF)
+        .map(|addr| format!("{:X?}", gb.peek(addr)))
         .collect::<Vec<String>>();
     let data = ram
         .chunks(COLS)
-        .map(|data| Row::Data(data.into_iter()));
+        .map(|data| Row::Data(data.iter()));
     Table::new(
         header,
         data
@@ -141,13 +358,19 @@ fn build_ram(terminal: &mut Terminal<RawBackend>, target: &Rect, gb: &GameBoy) {
         .render(terminal, target);
 }
 
-pub fn build() -> Result<Interface> {
+pub fn build(bindings: KeyBindings) -> Result<Interface> {
     let backend = RawBackend::new()?;
     let mut terminal = Terminal::new(backend)?;
 
     terminal.clear()?;
 
     Ok(Interface {
-        terminal
+        terminal,
+        keys: async_stdin().keys(),
+        bindings,
+        held: HashMap::new(),
+        cursor: None,
+        page: 1,
+        status: None
     })
 }
@@ -1,45 +1,232 @@
-extern crate byteorder;
-#[macro_use]
-extern crate bitflags;
+extern crate gb_core;
 extern crate tui;
+extern crate termion;
+extern crate clap;
 #[macro_use]
-extern crate load_file;
+extern crate log;
+extern crate env_logger;
+extern crate flate2;
+extern crate rustyline;
+extern crate ctrlc;
 
-mod cpu;
-mod gameboy;
+mod cli;
+mod gdb;
 mod gui;
+mod movie;
+mod repl;
+mod session;
+mod trace;
+mod tracediff;
 
-fn main() -> std::io::Result<()> {
-    let args: Vec<String> = std::env::args().collect();
+use clap::Parser;
+use cli::{Cli, Command, Emulation, Tracing};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use gb_core::{Cartridge, Debugger, EmulationError, IllegalOpcodePolicy, Model};
+use gb_core::disasm::listing::Listing;
+use gb_core::debugger::breakpoint::Breakpoint;
+use gb_core::debugger::watchpoint::Watchpoint;
+use gb_core::movie::Movie;
+use movie::Input;
+use session::Session;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::Duration;
+use trace::{Tracer, Trigger};
 
-    let use_gui = args.len() > 1 && args[1] == "gui";
+/// Delay between checks for input while the debugger is paused
+const PAUSED_POLL: Duration = Duration::from_millis(15);
 
-    let mut gb = gameboy::GameBoy::new();
+fn main() {
+    let cli = Cli::parse();
+    env_logger::Builder::new()
+        .filter_level(cli.log_level.into())
+        .init();
 
-    let firmware = load_bytes!("../assets/DMG_ROM.bin");
+    let result = match cli.command {
+        Command::Run { ref emulation, verify: Some(ref movie) } => verify(emulation, movie),
+        Command::Run { ref emulation, verify: None } => run(emulation),
+        Command::Gui { ref emulation, ref bindings, ref breakpoints, ref watchpoints, ref record } =>
+            gui(emulation, bindings.as_deref(), breakpoints, watchpoints, record.as_deref()),
+        Command::Debug { ref emulation, ref script } => debug(emulation, script.as_deref()),
+        Command::Gdb { ref emulation, port } => gdb(emulation, port),
+        Command::Trace(ref options) => trace(options),
+        Command::TraceDiff { ref reference, ref trace, context } => trace_diff(reference, trace, context),
+        Command::Disasm { ref rom, ref symbols } => disasm(rom, symbols.as_deref()),
+        Command::Info { ref rom } => info(rom)
+    };
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
 
-    gb.load_firmware(firmware);
+fn run(options: &Emulation) -> Result<(), Box<dyn Error>> {
+    let mut session = Session::load(options)?;
+    let result = emulate(&mut session, |_| Ok(true));
+    session.save()?;
+    result
+}
 
-    let mut instructions = Vec::new();
-    let mut tui = if use_gui {
-        Some(gui::terminal::build()?)
+/**
+ * Replays the movie as fast as possible, the cartridge RAM isn't saved so the check can be repeated.
+ */
+fn verify(options: &Emulation, movie: &Path) -> Result<(), Box<dyn Error>> {
+    let mut session = Session::load(options)?;
+    gb_core::movie::verify(Movie::load(movie)?, &mut session.gb)?;
+    println!("{} matches after {} frames", movie.display(), session.gb.frame());
+    Ok(())
+}
+
+fn trace(options: &Tracing) -> Result<(), Box<dyn Error>> {
+    let mut session = Session::load(&options.emulation)?;
+    let start = options.start.as_ref().map(|start| Trigger::parse(start, &session.symbols)).transpose()?;
+    let stop = options.stop.as_ref().map(|stop| Trigger::parse(stop, &session.symbols)).transpose()?;
+    let mut tracer = Tracer::new(start, stop, options.annotate);
+    let out: Box<dyn Write> = match options.output {
+        Some(ref path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout())
+    };
+    let out = BufWriter::new(out);
+    let gzip = options.output.as_ref().and_then(|path| path.extension()).is_some_and(|extension| extension == "gz");
+    let compress = options.compress || gzip;
+    let result = if compress {
+        let mut out = GzEncoder::new(out, Compression::default());
+        let result = emulate(&mut session, |session| Ok(tracer.trace(&session.gb, &session.symbols, &mut out)?));
+        out.finish()?.flush()?;
+        result
     }else {
-        println!("{:?}", gb);
-        None
+        let mut out = out;
+        let result = emulate(&mut session, |session| Ok(tracer.trace(&session.gb, &session.symbols, &mut out)?));
+        out.flush()?;
+        result
     };
+    session.save()?;
+    result
+}
+
+fn trace_diff(reference: &Path, trace: &Path, context: usize) -> Result<(), Box<dyn Error>> {
+    let stdout = io::stdout();
+    let line = tracediff::compare(tracediff::open(reference)?, tracediff::open(trace)?, context, &mut stdout.lock())?;
+    match line {
+        Some(line) => Err(format!("the trace diverges at line {} of the reference", line).into()),
+        None => Ok(())
+    }
+}
 
-    while let Some(instruction) = gb.next() {
-        if let Some(ref mut tui) = tui {
-            tui.draw(&gb, &instructions)?;
-        }else {
-            println!("{:?}", instruction);
+/**
+ * Steps until the frame limit is reached, the emulation stops or `before` returns false.
+ *
+ * Breaking at an illegal opcode ends the emulation without an error, there is no debugger to resume it.
+ */
+fn emulate<F>(session: &mut Session, mut before: F) -> Result<(), Box<dyn Error>>
+    where F: FnMut(&Session) -> Result<bool, Box<dyn Error>> {
+    while !session.is_finished() && before(session)? {
+        session.input.update(&mut session.gb);
+        let breaks = session.gb.illegal_opcode_policy == IllegalOpcodePolicy::Break;
+        match session.gb.step() {
+            Ok(_) => {},
+            Err(err @ EmulationError::IllegalOpcode { .. }) if breaks => {
+                warn!("Stopped in front of the {}", err);
+                break;
+            },
+            Err(err) => return Err(err.into())
         }
-        instructions.push(format!("{:?}", instruction));
-        instruction.exec(&mut gb);
-        if !use_gui {
-            println!("{:?}", gb);
+        while let Some(event) = session.gb.poll_event() {
+            warn!("{}", event);
         }
+        session.pace();
+    }
+    Ok(())
+}
+
+fn gui(options: &Emulation, bindings: Option<&Path>, breakpoints: &[String], watchpoints: &[Watchpoint],
+       record: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let mut session = Session::load(options)?;
+    if let Some(path) = record {
+        session.input = Input::record(path, &session.gb);
     }
+    let bindings = match bindings {
+        Some(path) => gui::input::KeyBindings::load(path)?,
+        None => gui::input::KeyBindings::default()
+    };
+    let mut tui = gui::terminal::build(bindings)?;
+    let mut debugger = Debugger::default();
+    for breakpoint in breakpoints {
+        debugger.breakpoints.add(Breakpoint::parse(breakpoint, &session.symbols)?);
+    }
+    for watchpoint in watchpoints {
+        session.gb.watchpoints.add(watchpoint.clone());
+    }
+
+    let mut result = Ok(());
+    let mut frame = None;
+    while !session.is_finished() && tui.handle_input(&mut session.gb, &mut session.input, &mut debugger)? {
+        // redrawing after every instruction would slow down the emulation to a crawl
+        if debugger.is_paused() || frame != Some(session.gb.frame()) {
+            tui.draw(&session.gb, &debugger, &session.symbols)?;
+            frame = Some(session.gb.frame());
+        }
+        session.input.update(&mut session.gb);
+        let breaks = session.gb.illegal_opcode_policy == IllegalOpcodePolicy::Break;
+        match debugger.step(&mut session.gb) {
+            Ok(Some(_)) => {},
+            Ok(None) => thread::sleep(PAUSED_POLL),
+            // the debugger paused in front of the opcode, PC can be moved past it before resuming
+            Err(err @ EmulationError::IllegalOpcode { .. }) if breaks => tui.notify(err),
+            Err(err) => {
+                result = Err(err.into());
+                break;
+            }
+        }
+        while let Some(event) = session.gb.poll_event() {
+            tui.notify(event);
+        }
+        session.pace();
+    }
+    mem::replace(&mut session.input, Input::Live).finish(&mut session.gb)?;
+    session.save()?;
+    result
+}
+
+fn debug(options: &Emulation, script: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let mut session = Session::load(options)?;
+    let result = repl::debug(&mut session, script);
+    session.save()?;
+    result
+}
+
+fn gdb(options: &Emulation, port: u16) -> Result<(), Box<dyn Error>> {
+    let mut session = Session::load(options)?;
+    let result = gdb::serve(&mut session, port);
+    session.save()?;
+    result
+}
+
+/**
+ * Lists the code reachable from the entry point and the interrupt vectors, everything else as data.
+ */
+fn disasm(rom: &Path, symbols: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let cartridge = Cartridge::new(fs::read(rom)?)?;
+    let symbols = session::load_symbols(rom, symbols)?;
+    let stdout = io::stdout();
+    Listing::new(cartridge.rom(), &symbols).write(&mut stdout.lock())?;
+    Ok(())
+}
 
+fn info(rom: &Path) -> Result<(), Box<dyn Error>> {
+    let cartridge = Cartridge::new(fs::read(rom)?)?;
+    println!("Title: {}", cartridge.title());
+    println!("Model: {:?}", Model::detect(&cartridge));
+    println!("MBC:   {:?}", cartridge.mbc());
+    println!("ROM:   {} KiB", cartridge.rom().len() / 1024);
+    println!("RAM:   {} KiB", cartridge.ram().len() / 1024);
+    println!("CGB:   {:?}", cartridge.cgb_support());
+    println!("SGB:   {}", cartridge.sgb_support());
     Ok(())
 }
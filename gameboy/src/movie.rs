@@ -0,0 +1,76 @@
+use gb_core::{Button, GameBoy};
+use gb_core::movie::{Movie, Player, Recorder};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/**
+ * Where the joypad input of a session comes from.
+ */
+pub enum Input {
+    /// Pressed in the frontend
+    Live,
+    /// Pressed in the frontend and written to the movie file once finished
+    Recording(Recorder, PathBuf),
+    /// Replayed from a movie, presses in the frontend are ignored
+    Playing(Player)
+}
+
+impl Input {
+    /**
+     * Checks the movie was recorded with the inserted cartridge and restores its cartridge RAM.
+     */
+    pub fn play(path: &Path, gb: &mut GameBoy) -> io::Result<Input> {
+        let movie = Movie::load(path)?;
+        Ok(Input::Playing(Player::new(movie, gb)?))
+    }
+
+    pub fn record(path: &Path, gb: &GameBoy) -> Input {
+        Input::Recording(Recorder::new(gb), path.to_path_buf())
+    }
+
+    /**
+     * Whether the cartridge RAM came from a movie instead of the save.
+     */
+    pub fn is_playing(&self) -> bool {
+        matches!(self, Input::Playing(_))
+    }
+
+    pub fn press(&mut self, gb: &mut GameBoy, button: Button) {
+        match self {
+            Input::Live => gb.press(button),
+            Input::Recording(recorder, _) => recorder.press(button),
+            Input::Playing(_) => {}
+        }
+    }
+
+    pub fn release(&mut self, gb: &mut GameBoy, button: Button) {
+        match self {
+            Input::Live => gb.release(button),
+            Input::Recording(recorder, _) => recorder.release(button),
+            Input::Playing(_) => {}
+        }
+    }
+
+    /**
+     * Applies the buttons of the current frame, has to be called before every step.
+     */
+    pub fn update(&mut self, gb: &mut GameBoy) {
+        match self {
+            Input::Live => {},
+            Input::Recording(recorder, _) => recorder.update(gb),
+            Input::Playing(player) => player.update(gb)
+        }
+    }
+
+    /**
+     * Writes the recording after running to the end of the current frame.
+     */
+    pub fn finish(self, gb: &mut GameBoy) -> io::Result<()> {
+        if let Input::Recording(recorder, path) = self {
+            let movie = recorder.finish(gb);
+            info!("Writing movie {} with {} inputs", path.display(), movie.inputs.len());
+            movie.save(path)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,196 @@
+use gb_core::debugger::watchpoint::Watchpoint;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Instructions listed by `disasm` without a count
+const DISASSEMBLY_LENGTH: usize = 10;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Format {
+    Hex,
+    Decimal
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Unit {
+    Byte,
+    /// Two bytes, little endian
+    Word
+}
+
+/**
+ * A line of the debugger, addresses and values are kept as text until they're resolved with the symbols.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Lists the breakpoints without one
+    Break(Option<String>),
+    /// Lists the watchpoints without one
+    Watch(Option<Watchpoint>),
+    Delete(usize),
+    Unwatch(usize),
+    Step(usize),
+    Next(usize),
+    Finish,
+    Continue,
+    Registers,
+    Examine { count: usize, format: Format, unit: Unit, address: String },
+    Set { target: String, value: String },
+    Disassemble { address: Option<String>, count: usize },
+    Backtrace,
+    /// Shows the selected frame without a number
+    Frame(Option<usize>),
+    Help,
+    Quit
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandError {
+    Unknown(String),
+    MissingArgument(&'static str),
+    InvalidArgument(String)
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::Unknown(command) => write!(f, "unknown command `{}`, try `help`", command),
+            CommandError::MissingArgument(argument) => write!(f, "missing {}", argument),
+            CommandError::InvalidArgument(argument) => write!(f, "invalid argument `{}`", argument)
+        }
+    }
+}
+
+impl Error for CommandError {}
+
+pub const HELP: &str = "\
+break [location [if condition]]  set a breakpoint or list them, e.g. `break Main.loop if A == 0`
+watch [start[-end] [r|w|rw]]     set a watchpoint or list them, e.g. `watch C000-C0FF w`
+delete <n>                       remove breakpoint n
+unwatch <n>                      remove watchpoint n
+step [n]                         execute n instructions
+next [n]                         execute n instructions, calls as a whole
+finish                           run until the subroutine returns
+continue                         run until a breakpoint or watchpoint is hit, interrupt with Ctrl+C
+regs                             print the registers
+x/<n><x|d><b|h> <address>        print n bytes or words as hex or decimal, e.g. `x/16xb HL`
+set <register|[address]>=<value> change a register or memory, e.g. `set a=0x10` or `set [C000]=A`
+disasm [address] [n]             list n instructions, from the selected frame by default
+bt                               print the call stack
+frame [n]                        select the frame n of the call stack
+quit                             leave the debugger";
+
+impl FromStr for Command {
+    type Err = CommandError;
+
+    fn from_str(line: &str) -> Result<Command, CommandError> {
+        let line = line.trim();
+        let (name, argument) = match line.find(char::is_whitespace) {
+            Some(index) => (&line[..index], line[index..].trim()),
+            None => (line, "")
+        };
+        let argument = Some(argument).filter(|argument| !argument.is_empty());
+        if let Some(options) = name.strip_prefix("x/").or_else(|| (name == "x").then_some("")) {
+            return examine(options, argument);
+        }
+        let command = match name {
+            "break" | "b" => Command::Break(argument.map(str::to_string)),
+            "watch" => Command::Watch(argument
+                .map(|watchpoint| watchpoint.parse().map_err(|_| CommandError::InvalidArgument(watchpoint.to_string())))
+                .transpose()?),
+            "delete" | "d" => Command::Delete(number(argument.ok_or(CommandError::MissingArgument("breakpoint"))?)?),
+            "unwatch" => Command::Unwatch(number(argument.ok_or(CommandError::MissingArgument("watchpoint"))?)?),
+            "step" | "s" | "stepi" | "si" => Command::Step(argument.map_or(Ok(1), number)?),
+            "next" | "n" | "nexti" | "ni" => Command::Next(argument.map_or(Ok(1), number)?),
+            "finish" | "fin" => Command::Finish,
+            "continue" | "c" => Command::Continue,
+            "regs" | "registers" => Command::Registers,
+            "set" => {
+                let assignment = argument.ok_or(CommandError::MissingArgument("assignment"))?;
+                let index = assignment.find('=').ok_or_else(|| CommandError::InvalidArgument(assignment.to_string()))?;
+                Command::Set {
+                    target: assignment[..index].trim().to_string(),
+                    value: assignment[index + 1..].trim().to_string()
+                }
+            },
+            "disasm" | "disassemble" => {
+                let mut arguments = argument.unwrap_or_default().split_whitespace();
+                Command::Disassemble {
+                    address: arguments.next().map(str::to_string),
+                    count: arguments.next().map_or(Ok(DISASSEMBLY_LENGTH), number)?
+                }
+            },
+            "bt" | "backtrace" | "where" => Command::Backtrace,
+            "frame" | "f" => Command::Frame(argument.map(number).transpose()?),
+            "help" | "h" => Command::Help,
+            "quit" | "q" | "exit" => Command::Quit,
+            _ => return Err(CommandError::Unknown(name.to_string()))
+        };
+        Ok(command)
+    }
+}
+
+fn number(argument: &str) -> Result<usize, CommandError> {
+    argument.parse().map_err(|_| CommandError::InvalidArgument(argument.to_string()))
+}
+
+/**
+ * Options like gdb's `x/16xb`, the count first and then the format and unit in any order.
+ */
+fn examine(options: &str, address: Option<&str>) -> Result<Command, CommandError> {
+    let digits = options.find(|c: char| !c.is_ascii_digit()).unwrap_or(options.len());
+    let count = match &options[..digits] {
+        "" => 1,
+        count => number(count)?
+    };
+    let (mut format, mut unit) = (Format::Hex, Unit::Byte);
+    for option in options[digits..].chars() {
+        match option {
+            'x' => format = Format::Hex,
+            'd' | 'u' => format = Format::Decimal,
+            'b' => unit = Unit::Byte,
+            'h' => unit = Unit::Word,
+            _ => return Err(CommandError::InvalidArgument(options.to_string()))
+        }
+    }
+    Ok(Command::Examine {
+        count,
+        format,
+        unit,
+        address: address.ok_or(CommandError::MissingArgument("address"))?.to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_parse_commands() {
+        assert_eq!("break Main.loop if A == 0".parse(), Ok(Command::Break(Some("Main.loop if A == 0".to_string()))));
+        assert_eq!("b".parse(), Ok(Command::Break(None)));
+        assert!(matches!("watch C000-C0FF w".parse(), Ok(Command::Watch(Some(_)))));
+        assert_eq!(" step ".parse(), Ok(Command::Step(1)));
+        assert_eq!("next 5".parse(), Ok(Command::Next(5)));
+        assert_eq!("frame 2".parse(), Ok(Command::Frame(Some(2))));
+        assert_eq!("set a = 0x10".parse(), Ok(Command::Set { target: "a".to_string(), value: "0x10".to_string() }));
+        assert_eq!("disasm Main 4".parse(), Ok(Command::Disassemble { address: Some("Main".to_string()), count: 4 }));
+    }
+
+    #[test]
+    fn it_should_parse_examine_options() {
+        assert_eq!("x/16xb C000".parse(),
+                   Ok(Command::Examine { count: 16, format: Format::Hex, unit: Unit::Byte, address: "C000".to_string() }));
+        assert_eq!("x/hd HL".parse(),
+                   Ok(Command::Examine { count: 1, format: Format::Decimal, unit: Unit::Word, address: "HL".to_string() }));
+        assert_eq!("x/4zb C000".parse::<Command>(), Err(CommandError::InvalidArgument("4zb".to_string())));
+        assert_eq!("x/4".parse::<Command>(), Err(CommandError::MissingArgument("address")));
+    }
+
+    #[test]
+    fn it_should_reject_unknown_commands() {
+        assert_eq!("jump 0150".parse::<Command>(), Err(CommandError::Unknown("jump".to_string())));
+        assert_eq!("step many".parse::<Command>(), Err(CommandError::InvalidArgument("many".to_string())));
+    }
+}
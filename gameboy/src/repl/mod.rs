@@ -0,0 +1,525 @@
+mod command;
+
+use self::command::{Command, Format, Unit, HELP};
+use ctrlc;
+use gb_core::{Debugger, GameBoy};
+use gb_core::cpu::register::Flags;
+use gb_core::debugger::breakpoint::{Breakpoint, Location};
+use gb_core::debugger::expression::{Expression, ParseError};
+use gb_core::debugger::symbols::{self, Symbols};
+use gb_core::debugger::watchpoint::Access;
+use gb_core::disasm;
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+use session::Session;
+use std::convert::TryFrom;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const PROMPT: &str = "(gb) ";
+
+/// File in the home directory keeping the commands of earlier sessions
+const HISTORY: &str = ".gb-rs_history";
+
+/// Values printed per line by `x`
+const VALUES_PER_LINE: usize = 8;
+
+/**
+ * Debugs the session with commands read from the terminal, or from the script until its end.
+ */
+pub fn debug(session: &mut Session, script: Option<&Path>) -> Result<(), Box<dyn Error>> {
+    let mut repl = Repl::new()?;
+    let stdout = io::stdout();
+    writeln!(stdout.lock(), "{}", describe(&session.gb, &session.symbols, session.gb.register.pc))?;
+    match script {
+        Some(script) => batch(&mut repl, session, script),
+        None => interactive(&mut repl, session)
+    }
+}
+
+fn interactive(repl: &mut Repl, session: &mut Session) -> Result<(), Box<dyn Error>> {
+    let mut editor = DefaultEditor::new()?;
+    let history = env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY));
+    if let Some(ref history) = history {
+        // there is no history before the first session
+        let _ = editor.load_history(history);
+    }
+    let mut last = None;
+    loop {
+        let line = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into())
+        };
+        // an empty line repeats the last command like in gdb, e.g. to keep stepping
+        let line = match (line.trim().is_empty(), last.take()) {
+            (true, Some(last)) => last,
+            (true, None) => continue,
+            (false, _) => {
+                editor.add_history_entry(line.as_str())?;
+                line
+            }
+        };
+        let stdout = io::stdout();
+        let result = line.parse::<Command>()
+            .map_err(Box::from)
+            .and_then(|command| repl.execute(session, command, &mut stdout.lock()));
+        match result {
+            Ok(true) => {},
+            Ok(false) => break,
+            Err(err) => eprintln!("error: {}", err)
+        }
+        last = Some(line);
+    }
+    if let Some(ref history) = history {
+        editor.save_history(history)?;
+    }
+    Ok(())
+}
+
+/**
+ * Executes the commands of the script, one per line with `#` starting comments, and stops at the first error.
+ */
+fn batch(repl: &mut Repl, session: &mut Session, script: &Path) -> Result<(), Box<dyn Error>> {
+    let script = fs::read_to_string(script)?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for (index, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        writeln!(out, "{}{}", PROMPT, line)?;
+        let running = line.parse::<Command>()
+            .map_err(Box::from)
+            .and_then(|command| repl.execute(session, command, &mut out))
+            .map_err(|err| format!("line {}: {}", index + 1, err))?;
+        if !running {
+            break;
+        }
+    }
+    Ok(())
+}
+
+struct Repl {
+    debugger: Debugger,
+    /// Frame of the call stack shown by `frame` and `disasm`, 0 is the innermost
+    frame: usize,
+    /// Set by Ctrl+C to pause a running emulation
+    interrupted: Arc<AtomicBool>
+}
+
+impl Repl {
+    fn new() -> Result<Repl, Box<dyn Error>> {
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let handler = interrupted.clone();
+        ctrlc::set_handler(move || handler.store(true, Ordering::SeqCst))?;
+        let mut debugger = Debugger::default();
+        debugger.pause();
+        Ok(Repl {
+            debugger,
+            frame: 0,
+            interrupted
+        })
+    }
+
+    /**
+     * Returns false once the debugger should be left.
+     */
+    fn execute<W: Write>(&mut self, session: &mut Session, command: Command, out: &mut W)
+        -> Result<bool, Box<dyn Error>> {
+        let symbols = &session.symbols;
+        match command {
+            Command::Break(None) => list_breakpoints(&self.debugger, symbols, out)?,
+            Command::Break(Some(breakpoint)) => {
+                let breakpoint = Breakpoint::parse(&breakpoint, symbols)?;
+                let location = breakpoint.location;
+                let id = self.debugger.breakpoints.add(breakpoint);
+                writeln!(out, "Breakpoint {} at {}", id, name(symbols, location))?;
+            },
+            Command::Watch(None) => list_watchpoints(&session.gb, out)?,
+            Command::Watch(Some(watchpoint)) => {
+                let range = format_range(*watchpoint.range.start(), *watchpoint.range.end());
+                let id = session.gb.watchpoints.add(watchpoint);
+                writeln!(out, "Watchpoint {} at {}", id, range)?;
+            },
+            Command::Delete(id) => {
+                self.debugger.breakpoints.remove(id).ok_or_else(|| format!("there is no breakpoint {}", id))?;
+            },
+            Command::Unwatch(id) => {
+                session.gb.watchpoints.remove(id).ok_or_else(|| format!("there is no watchpoint {}", id))?;
+            },
+            Command::Step(count) => {
+                for _ in 0..count {
+                    self.debugger.step_into();
+                    if self.run(session, out)? {
+                        break;
+                    }
+                }
+                self.stopped(session, out)?;
+            },
+            Command::Next(count) => {
+                for _ in 0..count {
+                    self.debugger.step_over(&session.gb);
+                    if self.run(session, out)? {
+                        break;
+                    }
+                }
+                self.stopped(session, out)?;
+            },
+            Command::Finish => {
+                self.debugger.step_out(&session.gb);
+                self.run(session, out)?;
+                self.stopped(session, out)?;
+            },
+            Command::Continue => {
+                self.debugger.resume();
+                self.run(session, out)?;
+                self.stopped(session, out)?;
+            },
+            Command::Registers => print_registers(&session.gb, out)?,
+            Command::Examine { count, format, unit, address } => {
+                let address = resolve(&address, &session.gb, symbols)?;
+                examine(&session.gb, symbols, address, count, format, unit, out)?;
+            },
+            Command::Set { target, value } => assign(&mut session.gb, symbols, &target, &value)?,
+            Command::Disassemble { address, count } => {
+                let address = match address {
+                    Some(address) => resolve(&address, &session.gb, symbols)?,
+                    None => self.frames(&session.gb)[self.frame]
+                };
+                disassemble(&session.gb, symbols, &self.debugger, address, count, out)?;
+            },
+            Command::Backtrace => {
+                for (number, &address) in self.frames(&session.gb).iter().enumerate() {
+                    writeln!(out, "#{:<2} {}", number, describe(&session.gb, symbols, address))?;
+                }
+            },
+            Command::Frame(number) => {
+                let frames = self.frames(&session.gb);
+                let number = number.unwrap_or(self.frame);
+                let address = *frames.get(number).ok_or_else(|| format!("there is no frame {}", number))?;
+                self.frame = number;
+                writeln!(out, "#{:<2} {}", number, describe(&session.gb, symbols, address))?;
+            },
+            Command::Help => writeln!(out, "{}", HELP)?,
+            Command::Quit => return Ok(false)
+        }
+        Ok(true)
+    }
+
+    /**
+     * Steps until the debugger pauses, returns whether it stopped before reaching the requested location.
+     */
+    fn run<W: Write>(&mut self, session: &mut Session, out: &mut W) -> Result<bool, Box<dyn Error>> {
+        self.interrupted.store(false, Ordering::SeqCst);
+        self.frame = 0;
+        let mut stopped = false;
+        while !self.debugger.is_paused() {
+            if session.is_finished() {
+                writeln!(out, "Reached the frame limit")?;
+                self.debugger.pause();
+                return Ok(true);
+            }
+            if self.interrupted.swap(false, Ordering::SeqCst) {
+                writeln!(out, "Interrupted")?;
+                self.debugger.pause();
+                return Ok(true);
+            }
+            session.input.update(&mut session.gb);
+            self.debugger.step(&mut session.gb)?;
+            while let Some(event) = session.gb.poll_event() {
+                writeln!(out, "{}", event)?;
+                stopped = true;
+            }
+            session.pace();
+        }
+        Ok(stopped)
+    }
+
+    fn stopped<W: Write>(&self, session: &Session, out: &mut W) -> io::Result<()> {
+        writeln!(out, "{}", describe(&session.gb, &session.symbols, session.gb.register.pc))
+    }
+
+    /**
+     * The address each frame is at, PC first and then the calls from the innermost subroutine outwards.
+     */
+    fn frames(&self, gb: &GameBoy) -> Vec<u16> {
+        let callers = self.debugger.call_stack().iter().rev().map(|frame| frame.caller);
+        Some(gb.register.pc).into_iter().chain(callers).collect()
+    }
+}
+
+/**
+ * Registers are read as such even when they'd be hex numbers like `BC` as well, anything else is a
+ * location like `C000` or `Main.loop`, or an expression like `[HL]`.
+ */
+fn resolve(address: &str, gb: &GameBoy, symbols: &Symbols) -> Result<u16, ParseError> {
+    let expression = address.parse::<Expression>();
+    match expression {
+        Ok(Expression::Register8(_)) | Ok(Expression::Register16(_)) | Ok(Expression::Flags) |
+        Ok(Expression::ProgramCounter) => expression.map(|expression| expression.evaluate(gb)),
+        _ => Location::parse(address, symbols)
+            .map(|location| location.address)
+            .or_else(|err| expression.map(|expression| expression.evaluate(gb)).map_err(|_| err))
+    }
+}
+
+/**
+ * Values are expressions with decimal numbers unless prefixed like in `0x10`, or the address of a symbol.
+ */
+fn assign(gb: &mut GameBoy, symbols: &Symbols, target: &str, value: &str) -> Result<(), Box<dyn Error>> {
+    let value = match value.parse::<Expression>() {
+        Ok(expression) => expression.evaluate(gb),
+        Err(err) => symbols.find(value).ok_or(err)?.address
+    };
+    let byte = || u8::try_from(value).map_err(|_| format!("{:#X} doesn't fit into {}", value, target));
+    if let Some(address) = target.strip_prefix('[').and_then(|target| target.strip_suffix(']')) {
+        let address = resolve(address, gb, symbols)?;
+        gb.write(address, byte()?);
+        return Ok(());
+    }
+    match target.parse::<Expression>() {
+        Ok(Expression::Register8(register)) => gb.register.write_8bit_register(&register, byte()?),
+        Ok(Expression::Register16(register)) => gb.register.write_16bit_register(&register, value),
+        // the lower 4 bits of F always read as zero
+        Ok(Expression::Flags) => gb.register.f = Flags::from_bits_truncate(byte()?),
+        Ok(Expression::ProgramCounter) => gb.register.pc = value,
+        _ => return Err(format!("can't assign to `{}`", target).into())
+    }
+    Ok(())
+}
+
+/**
+ * The location in the mapped ROM bank with the symbol naming it, e.g. `00:0153 Main.loop`.
+ */
+fn locate(gb: &GameBoy, symbols: &Symbols, address: u16) -> String {
+    let location = Location {
+        bank: Some(symbols::rom_bank(gb, address)),
+        address
+    };
+    match symbols.name_at(gb, address) {
+        Some(name) => format!("{} {}", location, name),
+        None => location.to_string()
+    }
+}
+
+fn name(symbols: &Symbols, location: Location) -> String {
+    let name = location.bank.and_then(|bank| symbols.get(bank, location.address));
+    match name {
+        Some(symbol) => format!("{} {}", location, symbol.name),
+        None => location.to_string()
+    }
+}
+
+/**
+ * The location with the instruction at it, e.g. `00:0153 Main.loop  JR NZ,Main`.
+ */
+fn describe(gb: &GameBoy, symbols: &Symbols, address: u16) -> String {
+    let instruction = disasm::decode_at(gb, address);
+    let label = |address| symbols.get(symbols::rom_bank(gb, address), address).map(|symbol| symbol.name.clone());
+    format!("{}  {}", locate(gb, symbols, address), instruction.format(label))
+}
+
+fn format_range(start: u16, end: u16) -> String {
+    if start == end {
+        format!("{:04X}", start)
+    }else {
+        format!("{:04X}-{:04X}", start, end)
+    }
+}
+
+fn list_breakpoints<W: Write>(debugger: &Debugger, symbols: &Symbols, out: &mut W) -> io::Result<()> {
+    if debugger.breakpoints.is_empty() {
+        return writeln!(out, "No breakpoints");
+    }
+    for (id, breakpoint) in debugger.breakpoints.iter() {
+        write!(out, "{}: {}", id, name(symbols, breakpoint.location))?;
+        if breakpoint.condition.is_some() {
+            write!(out, " (conditional)")?;
+        }
+        if !breakpoint.enabled {
+            write!(out, " (disabled)")?;
+        }
+        writeln!(out, ", hit {} times", breakpoint.hits)?;
+    }
+    Ok(())
+}
+
+fn list_watchpoints<W: Write>(gb: &GameBoy, out: &mut W) -> io::Result<()> {
+    if gb.watchpoints.is_empty() {
+        return writeln!(out, "No watchpoints");
+    }
+    for (id, watchpoint) in gb.watchpoints.iter() {
+        let access = match watchpoint.access {
+            Access::Read => "r",
+            Access::Write => "w",
+            Access::ReadWrite => "rw"
+        };
+        write!(out, "{}: {} {}", id, format_range(*watchpoint.range.start(), *watchpoint.range.end()), access)?;
+        if let Some(value) = watchpoint.value {
+            write!(out, " == {:#04X}", value)?;
+        }
+        if !watchpoint.enabled {
+            write!(out, " (disabled)")?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn print_registers<W: Write>(gb: &GameBoy, out: &mut W) -> io::Result<()> {
+    let register = &gb.register;
+    writeln!(out, "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X}",
+             register.a, register.f.bits(), register.b, register.c, register.d, register.e, register.h,
+             register.l, register.sp, register.pc)?;
+    let flags: Vec<&str> = [("Z", Flags::Z), ("N", Flags::N), ("H", Flags::H), ("C", Flags::C)].iter()
+        .map(|&(name, flag)| if register.f.contains(flag) { name } else { "-" })
+        .collect();
+    writeln!(out, "Flags: {}", flags.join(" "))
+}
+
+/**
+ * Memory as bytes or little endian words like gdb's `x`, with the symbol of the first address of every line.
+ */
+fn examine<W: Write>(gb: &GameBoy, symbols: &Symbols, address: u16, count: usize, format: Format, unit: Unit,
+                     out: &mut W) -> io::Result<()> {
+    let size: u16 = match unit {
+        Unit::Byte => 1,
+        Unit::Word => 2
+    };
+    // the address space wraps around, larger counts would only repeat it
+    let count = count.min(0x10000 / size as usize);
+    let values: Vec<(u16, u16)> = (0..count)
+        .map(|i| {
+            let address = address.wrapping_add((i as u16).wrapping_mul(size));
+            let value = match unit {
+                Unit::Byte => gb.read(address) as u16,
+                Unit::Word => gb.read(address) as u16 | (gb.read(address.wrapping_add(1)) as u16) << 8
+            };
+            (address, value)
+        })
+        .collect();
+    for line in values.chunks(VALUES_PER_LINE) {
+        let address = line[0].0;
+        match symbols.name_at(gb, address) {
+            Some(name) => write!(out, "{:04X} <{}>:", address, name)?,
+            None => write!(out, "{:04X}:", address)?
+        }
+        for &(_, value) in line {
+            match (format, unit) {
+                (Format::Hex, Unit::Byte) => write!(out, " 0x{:02X}", value)?,
+                (Format::Hex, Unit::Word) => write!(out, " 0x{:04X}", value)?,
+                (Format::Decimal, _) => write!(out, " {}", value)?
+            }
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/**
+ * Lists the instructions with their labels, `=>` marks PC and `*` breakpoints.
+ */
+fn disassemble<W: Write>(gb: &GameBoy, symbols: &Symbols, debugger: &Debugger, address: u16, count: usize,
+                         out: &mut W) -> io::Result<()> {
+    let label = |address| symbols.get(symbols::rom_bank(gb, address), address).map(|symbol| symbol.name.clone());
+    let mut address = address;
+    for _ in 0..count {
+        let instruction = disasm::decode_at(gb, address);
+        if let Some(name) = label(instruction.address) {
+            writeln!(out, "{}:", name)?;
+        }
+        let marker = if instruction.address == gb.register.pc { "=>" } else { "  " };
+        let breakpoint = if debugger.breakpoints.is_set(instruction.address) { '*' } else { ' ' };
+        let bytes: Vec<String> = (0..instruction.length)
+            .map(|i| format!("{:02X}", gb.read(instruction.address.wrapping_add(i))))
+            .collect();
+        writeln!(out, "{}{} {:04X}  {:<8}  {}", marker, breakpoint, instruction.address, bytes.join(" "),
+                 instruction.format(label))?;
+        address = instruction.next();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> (GameBoy, Symbols) {
+        let mut gb = GameBoy::new();
+        gb.skip_boot();
+        gb.register.pc = 0xC000;
+        // INC A; LDH (0x80),A; JR NZ,-5
+        for (i, &byte) in [0x3C, 0xE0, 0x80, 0x20, 0xFB].iter().enumerate() {
+            gb.write(0xC000 + i as u16, byte);
+        }
+        let symbols = Symbols::parse_sym("00:C000 Loop\n00:C003 Loop.next\n");
+        (gb, symbols)
+    }
+
+    fn output<F: FnOnce(&mut Vec<u8>) -> io::Result<()>>(write: F) -> String {
+        let mut out = Vec::new();
+        write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn it_should_resolve_addresses() {
+        let (gb, symbols) = setup();
+        assert_eq!(resolve("C003", &gb, &symbols), Ok(0xC003));
+        assert_eq!(resolve("Loop.next", &gb, &symbols), Ok(0xC003));
+        assert_eq!(resolve("BC", &gb, &symbols), Ok(gb.register.read_bc()));
+        assert_eq!(resolve("[0xC000]", &gb, &symbols), Ok(0x3C));
+        assert!(resolve("Nowhere", &gb, &symbols).is_err());
+    }
+
+    #[test]
+    fn it_should_assign_registers_and_memory() {
+        let (mut gb, symbols) = setup();
+        assign(&mut gb, &symbols, "a", "0x10").unwrap();
+        assert_eq!(gb.register.a, 0x10);
+        assign(&mut gb, &symbols, "pc", "Loop.next").unwrap();
+        assert_eq!(gb.register.pc, 0xC003);
+        assign(&mut gb, &symbols, "[C100]", "A").unwrap();
+        assert_eq!(gb.read(0xC100), 0x10);
+        assert!(assign(&mut gb, &symbols, "b", "256").is_err());
+        assert!(assign(&mut gb, &symbols, "Loop", "1").is_err());
+    }
+
+    #[test]
+    fn it_should_examine_memory() {
+        let (gb, symbols) = setup();
+        let bytes = output(|out| examine(&gb, &symbols, 0xC000, 3, Format::Hex, Unit::Byte, out));
+        assert_eq!(bytes, "C000 <Loop>: 0x3C 0xE0 0x80\n");
+        let words = output(|out| examine(&gb, &symbols, 0xC003, 1, Format::Hex, Unit::Word, out));
+        assert_eq!(words, "C003 <Loop.next>: 0xFB20\n");
+        let lines = output(|out| examine(&gb, &symbols, 0xD000, 9, Format::Decimal, Unit::Byte, out));
+        assert_eq!(lines.lines().count(), 2);
+    }
+
+    #[test]
+    fn it_should_stop_examining_after_the_whole_address_space() {
+        let (gb, symbols) = setup();
+        let words = output(|out| examine(&gb, &symbols, 0xC000, 40000, Format::Hex, Unit::Word, out));
+        assert_eq!(words.lines().count(), 0x8000 / VALUES_PER_LINE);
+        assert!(words.lines().last().unwrap().starts_with(&format!("{:04X}", 0xC000 - 2 * VALUES_PER_LINE)));
+    }
+
+    #[test]
+    fn it_should_disassemble_with_labels() {
+        let (gb, symbols) = setup();
+        let mut debugger = Debugger::default();
+        debugger.breakpoints.add(Breakpoint::new(Location::new(0xC003)));
+        let listing = output(|out| disassemble(&gb, &symbols, &debugger, 0xC000, 3, out));
+        assert_eq!(listing, "Loop:\n=>  C000  3C        INC A\n    C001  E0 80     LDH (0xFF80),A\n\
+                             Loop.next:\n  * C003  20 FB     JR NZ,Loop\n");
+        assert_eq!(describe(&gb, &symbols, 0xC003), "00:C003 Loop.next  JR NZ,Loop");
+    }
+}
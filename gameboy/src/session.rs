@@ -0,0 +1,193 @@
+use cli::Emulation;
+use gb_core::debugger::symbols::Symbols;
+use gb_core::gameboy::CLOCK_SPEED;
+use gb_core::ppu::FRAME_CYCLES;
+use gb_core::printer::Printer;
+use gb_core::{Cartridge, GameBoy, Model};
+use movie::Input;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/**
+ * A Game Boy set up from the command line options.
+ */
+pub struct Session {
+    pub gb: GameBoy,
+    pub symbols: Symbols,
+    pub input: Input,
+    save: PathBuf,
+    screenshot: Option<PathBuf>,
+    frames: Option<u64>,
+    pacer: Option<Pacer>
+}
+
+impl Session {
+    pub fn load(options: &Emulation) -> Result<Session, Box<dyn Error>> {
+        let cartridge = Cartridge::new(fs::read(&options.rom)?)?;
+        let model = match options.model {
+            Some(model) => Model::from(model),
+            None => Model::detect(&cartridge)
+        };
+        info!("Loaded {} as {:?}", cartridge.title(), model);
+        let mut gb = GameBoy::with_model(model);
+        gb.insert(cartridge);
+        gb.illegal_opcode_policy = options.illegal_opcodes.into();
+        gb.ppu.color_correction = options.color_correction.into();
+        match options.boot_rom {
+            Some(ref path) if !options.skip_boot => gb.load_firmware(&fs::read(path)?),
+            _ => gb.skip_boot()
+        }
+        if let Some(ref dir) = options.printer {
+            info!("Printing into {}", dir.display());
+            fs::create_dir_all(dir)?;
+            gb.serial.connect(Box::new(Printer::new(dir.clone())));
+        }
+
+        let name = options.rom.with_extension("sav");
+        let save = match options.save_dir {
+            Some(ref dir) => dir.join(name.file_name().unwrap_or_default()),
+            None => name
+        };
+        if let Ok(ram) = fs::read(&save) {
+            info!("Loaded save {}", save.display());
+            if let Some(ref mut cartridge) = gb.cartridge {
+                cartridge.load_ram(&ram);
+            }
+        }
+
+        let input = match options.play {
+            Some(ref path) => Input::play(path, &mut gb)?,
+            None => Input::Live
+        };
+
+        Ok(Session {
+            gb,
+            input,
+            symbols: load_symbols(&options.rom, options.symbols.as_deref())?,
+            save,
+            screenshot: options.screenshot.clone(),
+            frames: options.frames,
+            pacer: Pacer::new(options.speed)
+        })
+    }
+
+    /**
+     * Whether the frame limit was reached.
+     */
+    pub fn is_finished(&self) -> bool {
+        self.frames.is_some_and(|frames| self.gb.frame() >= frames)
+    }
+
+    /**
+     * Waits after every frame until the real hardware would have finished it.
+     */
+    pub fn pace(&mut self) {
+        if let Some(ref mut pacer) = self.pacer {
+            pacer.wait(self.gb.frame());
+        }
+    }
+
+    /**
+     * Writes the battery backed RAM of the cartridge, if there is any, and the screenshot.
+     *
+     * While playing a movie the cartridge RAM is the movie's, so the save is left alone.
+     */
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(ref path) = self.screenshot {
+            info!("Writing screenshot {}", path.display());
+            self.gb.save_screenshot(path)?;
+        }
+        match self.gb.cartridge {
+            Some(ref cartridge) if !cartridge.ram().is_empty() && !self.input.is_playing() => {
+                info!("Writing save {}", self.save.display());
+                if let Some(dir) = self.save.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(&self.save, cartridge.ram())
+            },
+            _ => Ok(())
+        }
+    }
+}
+
+/**
+ * Reads the symbol file, or the `.sym` or `.map` file next to the ROM if there is one.
+ */
+pub fn load_symbols(rom: &Path, path: Option<&Path>) -> io::Result<Symbols> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => match ["sym", "map"].iter().map(|extension| rom.with_extension(extension)).find(|path| path.is_file()) {
+            Some(path) => path,
+            None => return Ok(Symbols::default())
+        }
+    };
+    let text = fs::read_to_string(&path)?;
+    let symbols = match path.extension() {
+        Some(extension) if extension == "map" => Symbols::parse_map(&text),
+        _ => Symbols::parse_sym(&text)
+    };
+    info!("Loaded {} symbols from {}", symbols.len(), path.display());
+    Ok(symbols)
+}
+
+/// Frames the emulation may fall behind before the pacing gives up catching up
+const MAX_LAG: u32 = 5;
+
+struct Pacer {
+    frame_time: Duration,
+    start: Instant,
+    frame: u64
+}
+
+impl Pacer {
+    /**
+     * No pacing is done for a speed of 0.
+     */
+    fn new(speed: f64) -> Option<Pacer> {
+        if speed <= 0.0 {
+            return None;
+        }
+        let frame_time = FRAME_CYCLES as f64 / CLOCK_SPEED as f64 / speed;
+        Some(Pacer {
+            frame_time: Duration::from_secs_f64(frame_time),
+            start: Instant::now(),
+            frame: 0
+        })
+    }
+
+    fn wait(&mut self, frame: u64) {
+        if frame == self.frame {
+            return;
+        }
+        self.frame = frame;
+        let target = self.start + self.frame_time * frame as u32;
+        let now = Instant::now();
+        if target > now {
+            thread::sleep(target - now);
+        }else if now - target > self.frame_time * MAX_LAG {
+            // don't race to catch up after the emulation was paused
+            self.start = now - self.frame_time * frame as u32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_not_pace_without_speed() {
+        assert!(Pacer::new(0.0).is_none());
+    }
+
+    #[test]
+    fn it_should_scale_the_frame_time() {
+        let normal = Pacer::new(1.0).unwrap();
+        let fast = Pacer::new(2.0).unwrap();
+        assert_eq!(normal.frame_time.as_micros() / 2, fast.frame_time.as_micros());
+    }
+}
@@ -0,0 +1,111 @@
+use gb_core::GameBoy;
+use gb_core::debugger::breakpoint::Breakpoint;
+use gb_core::debugger::expression::ParseError;
+use gb_core::debugger::symbols::Symbols;
+use gb_core::trace::Entry;
+use std::io::{self, Write};
+
+/**
+ * When tracing starts or stops, a breakpoint like `Main.loop if A == 0` or `frame 60`.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Trigger {
+    Breakpoint(Breakpoint),
+    Frame(u64)
+}
+
+impl Trigger {
+    pub fn parse(trigger: &str, symbols: &Symbols) -> Result<Trigger, ParseError> {
+        match trigger.trim().strip_prefix("frame ") {
+            Some(frame) => frame.trim().parse()
+                .map(Trigger::Frame)
+                .map_err(|_| ParseError::InvalidNumber(frame.to_string())),
+            None => Breakpoint::parse(trigger, symbols).map(Trigger::Breakpoint)
+        }
+    }
+
+    fn is_hit(&self, gb: &GameBoy) -> bool {
+        match *self {
+            Trigger::Breakpoint(ref breakpoint) => breakpoint.location.matches(gb, gb.register.pc)
+                && breakpoint.condition.as_ref().is_none_or(|condition| condition.is_true(gb)),
+            Trigger::Frame(frame) => gb.frame() >= frame
+        }
+    }
+}
+
+/**
+ * Writes the CPU state before every instruction between the start and stop trigger.
+ */
+pub struct Tracer {
+    start: Option<Trigger>,
+    stop: Option<Trigger>,
+    /// Appends the symbol at PC to the lines
+    annotate: bool
+}
+
+impl Tracer {
+    pub fn new(start: Option<Trigger>, stop: Option<Trigger>, annotate: bool) -> Tracer {
+        Tracer {
+            start,
+            stop,
+            annotate
+        }
+    }
+
+    /**
+     * Writes the line of the instruction at PC once started, returns false when the stop trigger was hit.
+     */
+    pub fn trace<W: Write>(&mut self, gb: &GameBoy, symbols: &Symbols, out: &mut W) -> io::Result<bool> {
+        if self.start.as_ref().is_some_and(|start| start.is_hit(gb)) {
+            self.start = None;
+        }
+        if self.start.is_some() {
+            return Ok(true);
+        }
+        if self.stop.as_ref().is_some_and(|stop| stop.is_hit(gb)) {
+            return Ok(false);
+        }
+        let entry = Entry::capture(gb);
+        match symbols.name_at(gb, entry.pc) {
+            Some(name) if self.annotate => writeln!(out, "{} ; {}", entry, name)?,
+            _ => writeln!(out, "{}", entry)?
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace(tracer: &mut Tracer, gb: &GameBoy) -> (bool, String) {
+        let mut out = Vec::new();
+        let running = tracer.trace(gb, &Symbols::default(), &mut out).unwrap();
+        (running, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn it_should_parse_triggers() {
+        let symbols = Symbols::parse_sym("00:0150 Main");
+        assert_eq!(Trigger::parse("frame 60", &symbols), Ok(Trigger::Frame(60)));
+        assert!(matches!(Trigger::parse("Main if A == 1", &symbols), Ok(Trigger::Breakpoint(_))));
+        assert!(Trigger::parse("frame x", &symbols).is_err());
+    }
+
+    #[test]
+    fn it_should_trace_between_the_triggers() {
+        let mut gb = GameBoy::new();
+        let symbols = Symbols::default();
+        let start = Trigger::parse("0150", &symbols).ok();
+        let stop = Trigger::parse("0152", &symbols).ok();
+        let mut tracer = Tracer::new(start, stop, false);
+        gb.register.pc = 0x0100;
+        assert_eq!(trace(&mut tracer, &gb), (true, String::new()));
+        gb.register.pc = 0x0150;
+        assert!(trace(&mut tracer, &gb).1.contains("PC:0150"));
+        gb.register.pc = 0x0100;
+        assert!(trace(&mut tracer, &gb).1.contains("PC:0100"));
+        gb.register.pc = 0x0152;
+        assert_eq!(trace(&mut tracer, &gb), (false, String::new()));
+    }
+}
@@ -0,0 +1,138 @@
+use flate2::read::GzDecoder;
+use gb_core::trace::Record;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+/**
+ * Opens a trace, decompressing it when the name ends with `.gz`.
+ */
+pub fn open(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    let file = File::open(path)?;
+    if path.extension().is_some_and(|extension| extension == "gz") {
+        Ok(Box::new(BufReader::new(GzDecoder::new(file))))
+    }else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/**
+ * The lines with fields and their line numbers, anything else is skipped.
+ */
+fn records<R: BufRead>(trace: R) -> impl Iterator<Item = io::Result<(usize, String, Record)>> {
+    trace.lines()
+        .enumerate()
+        .filter_map(|(i, line)| match line {
+            Ok(line) => line.parse().ok().map(|record| Ok((i + 1, line, record))),
+            Err(err) => Some(Err(err))
+        })
+}
+
+/**
+ * Compares the traces line by line and reports the first divergence with the `context` lines around it.
+ *
+ * Returns the line of the reference where the traces diverge. A trace which is longer than the
+ * reference still matches.
+ */
+pub fn compare<R: BufRead, A: BufRead, W: Write>(reference: R, actual: A, context: usize, out: &mut W)
+    -> io::Result<Option<usize>> {
+    let mut reference = records(reference);
+    let mut actual = records(actual);
+    let mut previous = VecDeque::with_capacity(context);
+    let mut compared = 0;
+    loop {
+        let (expected, line) = match (reference.next().transpose()?, actual.next().transpose()?) {
+            (Some(expected), Some(line)) => (expected, line),
+            (Some((number, expected, _)), None) => {
+                writeln!(out, "The trace ends before line {} of the reference:", number)?;
+                print_context(out, &previous)?;
+                writeln!(out, "- {:>8}  {}", number, expected)?;
+                return Ok(Some(number));
+            },
+            (None, _) => {
+                writeln!(out, "The traces match for {} lines", compared)?;
+                return Ok(None);
+            }
+        };
+        let differences = expected.2.diff(&line.2);
+        if differences.is_empty() {
+            if context > 0 && previous.len() == context {
+                previous.pop_front();
+            }
+            if context > 0 {
+                previous.push_back((line.0, line.1));
+            }
+            compared += 1;
+            continue;
+        }
+
+        writeln!(out, "The traces diverge at line {} of the reference and line {} of the trace:", expected.0, line.0)?;
+        print_context(out, &previous)?;
+        writeln!(out, "- {:>8}  {}", expected.0, expected.1)?;
+        writeln!(out, "+ {:>8}  {}", line.0, line.1)?;
+        for _ in 0..context {
+            if let Some((number, text, _)) = reference.next().transpose()? {
+                writeln!(out, "- {:>8}  {}", number, text)?;
+            }
+            if let Some((number, text, _)) = actual.next().transpose()? {
+                writeln!(out, "+ {:>8}  {}", number, text)?;
+            }
+        }
+        writeln!(out)?;
+        for difference in differences {
+            writeln!(out, "{}", difference)?;
+        }
+        return Ok(Some(expected.0));
+    }
+}
+
+fn print_context<W: Write>(out: &mut W, lines: &VecDeque<(usize, String)>) -> io::Result<()> {
+    for (number, line) in lines {
+        writeln!(out, "  {:>8}  {}", number, line)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REFERENCE: &str = "A:01 F:B0 PC:0100\nA:02 F:00 PC:0101\nA:03 F:20 PC:0102\nA:04 F:00 PC:0103\n";
+
+    fn compare_with(actual: &str, context: usize) -> (Option<usize>, String) {
+        let mut out = Vec::new();
+        let line = compare(REFERENCE.as_bytes(), actual.as_bytes(), context, &mut out).unwrap();
+        (line, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn it_should_match_equal_traces() {
+        let actual = "a:01 f:b0 pc:100 LY:00\n\nA:02 F:00 PC:0101\nA:03 F:20 PC:0102\nA:04 F:00 PC:0103\nA:05 F:00 PC:0104\n";
+        let (line, out) = compare_with(actual, 2);
+        assert_eq!(line, None);
+        assert_eq!(out, "The traces match for 4 lines\n");
+    }
+
+    #[test]
+    fn it_should_report_the_first_divergence() {
+        let actual = "A:01 F:B0 PC:0100\nA:02 F:00 PC:0101\nA:03 F:00 PC:0102\nA:04 F:00 PC:0103\n";
+        let (line, out) = compare_with(actual, 1);
+        assert_eq!(line, Some(3));
+        assert_eq!(out, "The traces diverge at line 3 of the reference and line 3 of the trace:\n\
+                         \x20        2  A:02 F:00 PC:0101\n\
+                         -        3  A:03 F:20 PC:0102\n\
+                         +        3  A:03 F:00 PC:0102\n\
+                         -        4  A:04 F:00 PC:0103\n\
+                         +        4  A:04 F:00 PC:0103\n\
+                         \n\
+                         F is 00 instead of 20 (H not set)\n");
+    }
+
+    #[test]
+    fn it_should_report_a_short_trace() {
+        let (line, out) = compare_with("A:01 F:B0 PC:0100\n", 0);
+        assert_eq!(line, Some(2));
+        assert!(out.starts_with("The trace ends before line 2 of the reference:\n"));
+    }
+}